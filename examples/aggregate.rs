@@ -0,0 +1,48 @@
+use std::{net::IpAddr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::{
+    DualstackPinger, Monitor, MonitorOptions,
+    aggregate::{AggregatorOptions, RttAggregator},
+};
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let ips: [IpAddr; 2] = ["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let options = MonitorOptions::new().interval(Duration::from_millis(10));
+    let (mut monitor, _handle) = Monitor::new(&pinger, ips, options);
+    let mut aggregator = RttAggregator::new(
+        AggregatorOptions::new()
+            .window(Duration::from_secs(60))
+            .resolution(Duration::from_secs(5)),
+    );
+
+    let _ = time::timeout(Duration::from_secs(2), async {
+        while let Some(result) = monitor.next().await {
+            aggregator.observe(&result.expect("receive ping response"));
+        }
+    })
+    .await;
+
+    let now = std::time::SystemTime::now();
+    for ip in ips {
+        let Some(histogram) = aggregator.snapshot(ip, now) else {
+            continue;
+        };
+        println!("{ip}: {:?}", histogram.percentiles());
+
+        let max_count = histogram.buckets().map(|bucket| bucket.count).max();
+        for bucket in histogram.buckets() {
+            let width = bucket.count * 50 / max_count.unwrap_or(1);
+            println!(
+                "  {:>10?} {:>5} {}",
+                bucket.lower,
+                bucket.count,
+                "#".repeat(width as usize)
+            );
+        }
+    }
+}
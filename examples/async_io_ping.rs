@@ -0,0 +1,40 @@
+use std::{net::IpAddr, thread, time::Duration};
+
+use async_io::Timer;
+use futures_util::{
+    StreamExt,
+    future::{self, Either},
+};
+use massping::{DualstackPinger, PingerOptions};
+
+fn main() {
+    let localhost_v4: IpAddr = "127.0.0.1".parse().unwrap();
+    let one_one_one_one_v4: IpAddr = "1.1.1.1".parse().unwrap();
+    let not_answering_v4: IpAddr = "0.0.0.1".parse().unwrap();
+    let localhost_v6: IpAddr = "::1".parse().unwrap();
+    let one_one_one_one_v6: IpAddr = "2606:4700:4700::1111".parse().unwrap();
+
+    let (pinger, driver) =
+        DualstackPinger::with_driver(PingerOptions::default()).expect("setup pinger");
+    thread::spawn(move || async_io::block_on(driver));
+
+    let ips = [
+        localhost_v4,
+        one_one_one_one_v4,
+        not_answering_v4,
+        localhost_v6,
+        one_one_one_one_v6,
+    ];
+
+    async_io::block_on(async {
+        let mut stream = pinger.measure_many(ips.into_iter());
+        let mut timeout = Timer::after(Duration::from_secs(5));
+
+        while let Either::Left((Some(result), _)) =
+            future::select(stream.next(), &mut timeout).await
+        {
+            let (addr, took) = result.expect("receive ping response");
+            println!("{}: {:?}", addr, took);
+        }
+    });
+}
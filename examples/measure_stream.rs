@@ -0,0 +1,43 @@
+use std::{net::IpAddr, time::Duration};
+
+use futures_util::{StreamExt, stream};
+use massping::{DualstackPinger, RetryOptions};
+use tokio::time::{self, Instant};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let ips: [IpAddr; 5] = [
+        "127.0.0.1".parse().unwrap(),
+        "::1".parse().unwrap(),
+        "0.0.0.1".parse().unwrap(),
+        "100::1".parse().unwrap(),
+        "127.0.0.2".parse().unwrap(),
+    ];
+
+    // Targets trickling in, as they would from a channel or a resolver
+    let targets = Box::pin(stream::unfold(ips.into_iter(), |mut ips| async move {
+        time::sleep(Duration::from_millis(100)).await;
+        let ip = ips.next()?;
+        println!("got {ip}");
+        Some((ip, ips))
+    }));
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let options = RetryOptions::new()
+        .retries(1)
+        .timeout(Duration::from_millis(200));
+
+    let start = Instant::now();
+    let mut stream = pinger.measure_stream_with_retries(targets, options);
+    while let Some(result) = stream.next().await {
+        let result = result.expect("receive ping response");
+        println!(
+            "{:?}: {} {:?} at attempt {}",
+            start.elapsed(),
+            result.addr(),
+            result.rtt(),
+            result.attempt()
+        );
+    }
+    println!("{:?}: done", start.elapsed());
+}
@@ -0,0 +1,41 @@
+use std::{net::IpAddr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::{DualstackPinger, Monitor, MonitorOptions};
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let localhost_v4: IpAddr = "127.0.0.1".parse().unwrap();
+    let localhost_v6: IpAddr = "::1".parse().unwrap();
+    let not_answering_v4: IpAddr = "0.0.0.1".parse().unwrap();
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let options = MonitorOptions::new()
+        .interval(Duration::from_secs(1))
+        .timeout(Duration::from_millis(500))
+        .jitter(Duration::from_millis(10));
+    let (mut monitor, handle) = Monitor::new(&pinger, [localhost_v4, localhost_v6], options);
+
+    tokio::spawn(async move {
+        time::sleep(Duration::from_secs(3)).await;
+        println!("adding {not_answering_v4}");
+        handle.add(not_answering_v4);
+
+        time::sleep(Duration::from_secs(3)).await;
+        println!("removing {localhost_v6}");
+        handle.remove(localhost_v6);
+    });
+
+    let mut records = (&mut monitor).take(24);
+    while let Some(result) = records.next().await {
+        let record = result.expect("receive ping response");
+        println!(
+            "{} [{}]: {} {:?}",
+            record.target(),
+            record.sequence_number(),
+            record.outcome(),
+            record.rtt()
+        );
+    }
+}
@@ -30,7 +30,8 @@ async fn main() {
         tokio::spawn(async move {
             let _ = time::timeout(Duration::from_secs(5), async {
                 let mut stream = pinger.measure_many(ips.into_iter());
-                while let Some((addr, took)) = stream.next().await {
+                while let Some(result) = stream.next().await {
+                    let (addr, took) = result.expect("receive ping response");
                     println!("{}: {:?}", addr, took);
                 }
             })
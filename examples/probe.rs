@@ -0,0 +1,38 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::{V4ProbePinger, packet::InterfaceQuery};
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let localhost: Ipv4Addr = "127.0.0.1".parse().unwrap();
+
+    let mut pinger = V4ProbePinger::new().expect("setup pinger");
+
+    let queries = [
+        InterfaceQuery::Name("lo".to_owned()),
+        InterfaceQuery::Index(1),
+        InterfaceQuery::Address(localhost.into()),
+        InterfaceQuery::Name("nonexistent".to_owned()),
+    ];
+
+    let _ = time::timeout(Duration::from_secs(5), async {
+        let mut stream = pinger.probe_many(queries.into_iter().map(|query| (localhost, query)));
+        while let Some(result) = stream.next().await {
+            let result = result.expect("receive extended echo reply");
+            match result.status() {
+                Ok(status) => println!(
+                    "{} {:?}: active {}, IPv4 {}, IPv6 {}",
+                    result.proxy(),
+                    result.query(),
+                    status.is_active(),
+                    status.is_ipv4_running(),
+                    status.is_ipv6_running(),
+                ),
+                Err(err) => println!("{} {:?}: {}", result.proxy(), result.query(), err),
+            }
+        }
+    })
+    .await;
+}
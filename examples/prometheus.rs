@@ -0,0 +1,32 @@
+use std::{net::IpAddr, time::Duration};
+
+use massping::{DualstackPinger, exporter::Exporter};
+use tokio::net::TcpListener;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let targets: [IpAddr; 3] = [
+        "127.0.0.1".parse().unwrap(),
+        "::1".parse().unwrap(),
+        "0.0.0.1".parse().unwrap(),
+    ];
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let exporter = Exporter::new(targets)
+        .interval(Duration::from_secs(1))
+        .timeout(Duration::from_millis(500));
+
+    let listener = TcpListener::bind("127.0.0.1:9898")
+        .await
+        .expect("bind metrics listener");
+    println!("serving metrics on http://127.0.0.1:9898/metrics");
+
+    let metrics = exporter.metrics();
+    tokio::spawn(async move {
+        let err = metrics.serve(listener).await.unwrap_err();
+        eprintln!("serving metrics failed: {err}");
+    });
+
+    let err = exporter.run(&pinger).await.unwrap_err();
+    eprintln!("pinging failed: {err}");
+}
@@ -0,0 +1,46 @@
+use std::{net::IpAddr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::{DualstackPinger, quality::QualityTracker, report::PingRecord};
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let ips: [IpAddr; 3] = [
+        "127.0.0.1".parse().unwrap(),
+        "::1".parse().unwrap(),
+        "0.0.0.1".parse().unwrap(),
+    ];
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let mut tracker = QualityTracker::new();
+
+    for seq in 0..20 {
+        let mut pending = ips.to_vec();
+        let _ = time::timeout(Duration::from_millis(100), async {
+            let mut stream = pinger.measure_many(ips.into_iter());
+            while let Some(result) = stream.next().await {
+                let (addr, rtt) = result.expect("receive ping response");
+                pending.retain(|&ip| ip != addr);
+                tracker.observe(&PingRecord::reply(addr, seq, rtt));
+            }
+        })
+        .await;
+
+        for addr in pending {
+            tracker.observe(&PingRecord::timeout(addr, seq));
+        }
+    }
+
+    for ip in ips {
+        let stats = tracker.stats(ip).unwrap();
+        println!(
+            "{ip}: loss {:?}, jitter {:?}, p99 IPDV {:?}, loss bursts {:?}, MOS {:.2}",
+            stats.loss_ratio().unwrap(),
+            stats.jitter(),
+            stats.ipdv().quantile(0.99),
+            stats.loss_bursts().collect::<Vec<_>>(),
+            stats.mos().unwrap(),
+        );
+    }
+}
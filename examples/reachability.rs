@@ -0,0 +1,42 @@
+use std::{net::IpAddr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::{
+    DualstackPinger, Monitor, MonitorOptions,
+    reachability::{ReachabilityOptions, ReachabilityTracker},
+};
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let localhost_v4: IpAddr = "127.0.0.1".parse().unwrap();
+    let not_answering_v4: IpAddr = "0.0.0.1".parse().unwrap();
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let options = MonitorOptions::new()
+        .interval(Duration::from_millis(200))
+        .timeout(Duration::from_millis(100));
+    let (mut monitor, _handle) = Monitor::new(&pinger, [localhost_v4, not_answering_v4], options);
+    let mut tracker = ReachabilityTracker::new(
+        ReachabilityOptions::new()
+            .down_after(2, 3)
+            .up_after(2)
+            .flapping(3, Duration::from_secs(60)),
+    );
+
+    let _ = time::timeout(Duration::from_secs(3), async {
+        while let Some(result) = monitor.next().await {
+            let record = result.expect("receive ping response");
+            if let Some(transition) = tracker.observe(&record) {
+                println!(
+                    "{}: {} -> {} (flapping: {})",
+                    transition.target(),
+                    transition.from(),
+                    transition.to(),
+                    transition.is_flapping()
+                );
+            }
+        }
+    })
+    .await;
+}
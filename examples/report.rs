@@ -0,0 +1,50 @@
+use std::{collections::HashMap, io, net::IpAddr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::{
+    DualstackPinger,
+    report::{JsonLinesWriter, PingRecord, SummaryRecord},
+};
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let ips: [IpAddr; 3] = [
+        "127.0.0.1".parse().unwrap(),
+        "::1".parse().unwrap(),
+        "0.0.0.1".parse().unwrap(),
+    ];
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let mut writer = JsonLinesWriter::new(io::stdout().lock());
+    let mut summaries = ips
+        .iter()
+        .map(|&ip| (ip, SummaryRecord::new(ip)))
+        .collect::<HashMap<_, _>>();
+
+    for seq in 0..3 {
+        let mut pending = ips.to_vec();
+        let _ = time::timeout(Duration::from_secs(1), async {
+            let mut stream = pinger.measure_many(ips.into_iter());
+            while let Some(result) = stream.next().await {
+                let (addr, rtt) = result.expect("receive ping response");
+                pending.retain(|&ip| ip != addr);
+
+                let record = PingRecord::reply(addr, seq, rtt);
+                summaries.get_mut(&addr).unwrap().add(&record);
+                writer.write(&record).expect("write record");
+            }
+        })
+        .await;
+
+        for addr in pending {
+            let record = PingRecord::timeout(addr, seq);
+            summaries.get_mut(&addr).unwrap().add(&record);
+            writer.write(&record).expect("write record");
+        }
+    }
+
+    for ip in ips {
+        writer.write(&summaries[&ip]).expect("write record");
+    }
+}
@@ -0,0 +1,38 @@
+use std::{net::IpAddr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::{DualstackPinger, RetryOptions};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let ips: [IpAddr; 4] = [
+        "127.0.0.1".parse().unwrap(),
+        "::1".parse().unwrap(),
+        "0.0.0.1".parse().unwrap(),
+        "100::1".parse().unwrap(),
+    ];
+
+    let pinger = DualstackPinger::new().expect("setup pinger");
+    let options = RetryOptions::new()
+        .retries(2)
+        .timeout(Duration::from_millis(200))
+        .backoff(2.0);
+
+    let mut stream = pinger.measure_many_with_retries(ips.into_iter(), options);
+    while let Some(result) = stream.next().await {
+        let result = result.expect("receive ping response");
+        match result.rtt() {
+            Some(rtt) => println!(
+                "{}: {:?} at attempt {}",
+                result.addr(),
+                rtt,
+                result.attempt()
+            ),
+            None => println!(
+                "{}: lost after {} attempts",
+                result.addr(),
+                result.attempt()
+            ),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use massping::{
+    PingerOptions, V4Pinger,
+    sim::{SimHost, SimIcmpError, SimNetwork},
+};
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread", start_paused = true)]
+async fn main() {
+    let fast = Ipv4Addr::new(192, 0, 2, 1);
+    let slow = Ipv4Addr::new(192, 0, 2, 2);
+    let flaky = Ipv4Addr::new(192, 0, 2, 3);
+    let unreachable = Ipv4Addr::new(192, 0, 2, 4);
+    let router = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+
+    let network = SimNetwork::new(42);
+    network.add_host(
+        fast.into(),
+        SimHost::new().latency(Duration::from_millis(10)),
+    );
+    network.add_host(
+        slow.into(),
+        SimHost::new()
+            .latency(Duration::from_millis(300))
+            .jitter(Duration::from_millis(50)),
+    );
+    network.add_host(
+        flaky.into(),
+        SimHost::new()
+            .latency(Duration::from_millis(40))
+            .loss(0.5)
+            .duplication(0.2),
+    );
+    network.add_host(
+        unreachable.into(),
+        SimHost::new().icmp_error(router, SimIcmpError::DestinationUnreachable { code: 1 }),
+    );
+
+    let (pinger, driver) = V4Pinger::with_transport(PingerOptions::default(), network.socket());
+    tokio::spawn(driver);
+
+    for round in 0..3 {
+        let _ = time::timeout(Duration::from_secs(1), async {
+            let mut stream = pinger.measure_many([fast, slow, flaky, unreachable].into_iter());
+            while let Some(result) = stream.next().await {
+                let (addr, took) = result.expect("receive ping response");
+                println!("round {round}: {addr}: {took:?}");
+            }
+        })
+        .await;
+    }
+}
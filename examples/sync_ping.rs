@@ -0,0 +1,21 @@
+use std::{
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use massping::V4SyncPinger;
+
+fn main() {
+    let localhost: Ipv4Addr = "127.0.0.1".parse().unwrap();
+    let one_one_one_one: Ipv4Addr = "1.1.1.1".parse().unwrap();
+    let not_answering: Ipv4Addr = "0.0.0.1".parse().unwrap();
+
+    let mut pinger = V4SyncPinger::new().expect("setup pinger");
+
+    let ips = [localhost, one_one_one_one, not_answering];
+    let deadline = Instant::now() + Duration::from_secs(2);
+    for result in pinger.measure_many(ips.into_iter(), deadline) {
+        let (addr, took) = result.expect("receive ping response");
+        println!("{}: {:?}", addr, took);
+    }
+}
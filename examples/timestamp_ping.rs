@@ -0,0 +1,29 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use futures_util::StreamExt;
+use massping::TimestampPinger;
+use tokio::time;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let localhost: Ipv4Addr = "127.0.0.1".parse().unwrap();
+    let one_one_one_one: Ipv4Addr = "1.1.1.1".parse().unwrap();
+
+    let mut pinger = TimestampPinger::new().expect("setup pinger, requires CAP_NET_RAW");
+
+    let _ = time::timeout(Duration::from_secs(5), async {
+        let mut stream = pinger.measure_many([localhost, one_one_one_one].into_iter());
+        while let Some(result) = stream.next().await {
+            let (addr, measurement) = result.expect("receive timestamp reply");
+            println!(
+                "{}: rtt {:?}, forward {:?}ms, reverse {:?}ms, clock offset {:?}ms",
+                addr,
+                measurement.rtt(),
+                measurement.forward_delay(),
+                measurement.reverse_delay(),
+                measurement.clock_offset(),
+            );
+        }
+    })
+    .await;
+}
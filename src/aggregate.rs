@@ -0,0 +1,626 @@
+//! Round trip time distributions in constant memory
+//!
+//! [`RttHistogram`] counts round trip times into logarithmic buckets, each
+//! spanning about 6% of its lower bound, so that quantiles are estimated
+//! within 3% no matter how many replies were recorded. Histograms can be
+//! [merged](RttHistogram::merge), for example to combine the results of
+//! several pingers.
+//!
+//! [`WindowedHistogram`] keeps the distribution over a sliding window of
+//! time, and [`RttAggregator`] keeps one for each target, fed with
+//! [`PingRecord`]s.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::report::PingRecord;
+
+/// Round trip times below this many nanoseconds get a bucket of their own
+const LINEAR_BUCKETS: usize = 32;
+/// Number of buckets for each power of two above [`LINEAR_BUCKETS`]
+const SUB_BUCKETS: usize = LINEAR_BUCKETS / 2;
+
+/// Distribution of round trip times
+///
+/// Only the buckets between the shortest and the longest round trip times
+/// are kept, so memory usage grows logarithmically with their ratio, and
+/// doesn't depend on the number of round trip times recorded.
+///
+/// Durations are [serialized as nanoseconds](crate#serialization).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RttHistogram {
+    /// Index of the bucket counted by the first element of `counts`
+    offset: usize,
+    /// Counts of the buckets from `offset`, with the first and the last
+    /// ones being non-zero
+    counts: Vec<u64>,
+    count: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    min: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    max: Option<Duration>,
+}
+
+/// The usual quantiles of a [`RttHistogram`]
+///
+/// Durations are [serialized as nanoseconds](crate#serialization).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Percentiles {
+    /// Number of round trip times
+    pub count: u64,
+    /// Shortest round trip time
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    pub min: Duration,
+    /// Median round trip time
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    pub p50: Duration,
+    /// 90th percentile of the round trip times
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    pub p90: Duration,
+    /// 99th percentile of the round trip times
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    pub p99: Duration,
+    /// Longest round trip time
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    pub max: Duration,
+}
+
+/// A bucket of a [`RttHistogram`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    /// Shortest round trip time counted in the bucket
+    pub lower: Duration,
+    /// Longest round trip time counted in the bucket
+    pub upper: Duration,
+    /// Number of round trip times in the bucket
+    pub count: u64,
+}
+
+/// Distribution of round trip times over a sliding window of time
+///
+/// The window is split into slices of `resolution`, aligned on the Unix
+/// epoch. Once a slice falls out of the window, all of its round trip
+/// times are forgotten at once. Aligning the slices allows merging
+/// windowed histograms built with the same window and resolution.
+#[derive(Debug, Clone)]
+pub struct WindowedHistogram {
+    resolution: Duration,
+    /// Number of slices in the window
+    len: u64,
+    /// Slices, indexed by the number of `resolution` since the Unix epoch,
+    /// oldest first
+    slices: VecDeque<(u64, RttHistogram)>,
+}
+
+/// Options for [`RttAggregator`]
+#[derive(Debug, Clone)]
+pub struct AggregatorOptions {
+    window: Duration,
+    resolution: Duration,
+}
+
+/// Keeps a [`WindowedHistogram`] for each target
+#[derive(Debug, Clone)]
+pub struct RttAggregator {
+    options: AggregatorOptions,
+    targets: HashMap<IpAddr, WindowedHistogram>,
+}
+
+impl RttHistogram {
+    /// Construct an empty `RttHistogram`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a round trip time
+    pub fn record(&mut self, rtt: Duration) {
+        let index = bucket_index(rtt);
+        self.reserve(index, index);
+        self.counts[index - self.offset] += 1;
+        self.count += 1;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+    }
+
+    /// Add every round trip time recorded in `other`
+    pub fn merge(&mut self, other: &Self) {
+        if other.counts.is_empty() {
+            return;
+        }
+
+        self.reserve(other.offset, other.offset + other.counts.len() - 1);
+        let start = other.offset - self.offset;
+        for (count, other_count) in self.counts[start..].iter_mut().zip(&other.counts) {
+            *count += other_count;
+        }
+        self.count += other.count;
+        self.min = match (self.min, other.min) {
+            (Some(min), Some(other_min)) => Some(min.min(other_min)),
+            (min, other_min) => min.or(other_min),
+        };
+        self.max = self.max.max(other.max);
+    }
+
+    /// Get the number of round trip times recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Whether no round trip time was recorded
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Get the shortest round trip time recorded
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    /// Get the longest round trip time recorded
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    /// Estimate the round trip time below which a `quantile` of the
+    /// round trip times fall
+    ///
+    /// Returns `None` if no round trip time was recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `quantile` isn't between `0.0` and `1.0`.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        assert!(
+            (0.0..=1.0).contains(&quantile),
+            "quantile must be between 0.0 and 1.0"
+        );
+        let (min, max) = self.min.zip(self.max)?;
+        if quantile == 0.0 {
+            return Some(min);
+        }
+        if quantile == 1.0 {
+            return Some(max);
+        }
+
+        let rank = ((quantile * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let (lower, upper) = bucket_bounds(self.offset + index);
+                let middle = lower + (upper - lower) / 2;
+                return Some(Duration::from_nanos(middle).max(min).min(max));
+            }
+        }
+
+        Some(max)
+    }
+
+    /// Get the median, 90th and 99th percentiles, along with the
+    /// shortest and longest round trip times
+    ///
+    /// Returns `None` if no round trip time was recorded.
+    pub fn percentiles(&self) -> Option<Percentiles> {
+        Some(Percentiles {
+            count: self.count,
+            min: self.min?,
+            p50: self.quantile(0.5)?,
+            p90: self.quantile(0.9)?,
+            p99: self.quantile(0.99)?,
+            max: self.max?,
+        })
+    }
+
+    /// Iterate over the non-empty buckets, shortest round trip times first
+    ///
+    /// Useful for drawing the distribution of the round trip times.
+    pub fn buckets(&self) -> impl Iterator<Item = Bucket> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(index, &count)| {
+                let (lower, upper) = bucket_bounds(self.offset + index);
+                Bucket {
+                    lower: Duration::from_nanos(lower),
+                    upper: Duration::from_nanos(upper),
+                    count,
+                }
+            })
+    }
+
+    /// Forget every round trip time recorded
+    pub fn clear(&mut self) {
+        self.offset = 0;
+        self.counts.clear();
+        self.count = 0;
+        self.min = None;
+        self.max = None;
+    }
+
+    /// Make room in `counts` for the buckets `first..=last`
+    fn reserve(&mut self, first: usize, last: usize) {
+        if self.counts.is_empty() {
+            self.offset = first;
+        } else if first < self.offset {
+            self.counts
+                .splice(0..0, std::iter::repeat_n(0, self.offset - first));
+            self.offset = first;
+        }
+
+        if self.offset + self.counts.len() <= last {
+            self.counts.resize(last - self.offset + 1, 0);
+        }
+    }
+}
+
+impl WindowedHistogram {
+    /// Construct an empty `WindowedHistogram` spanning `window`, which
+    /// slides in steps of `resolution`
+    ///
+    /// `window` is rounded up to a multiple of `resolution`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resolution` is zero.
+    pub fn new(window: Duration, resolution: Duration) -> Self {
+        assert!(!resolution.is_zero(), "resolution must not be zero");
+        let len = window.as_nanos().div_ceil(resolution.as_nanos()).max(1);
+
+        Self {
+            resolution,
+            len: u64::try_from(len).unwrap_or(u64::MAX),
+            slices: VecDeque::new(),
+        }
+    }
+
+    /// Record a round trip time measured at `timestamp`
+    ///
+    /// Round trip times measured before the window ending at the latest
+    /// `timestamp` recorded so far are ignored.
+    pub fn record(&mut self, rtt: Duration, timestamp: SystemTime) {
+        let index = self.slice_index(timestamp);
+        if let Some(histogram) = self.slice_mut(index) {
+            histogram.record(rtt);
+        }
+    }
+
+    /// Add every round trip time recorded in `other`
+    ///
+    /// Both histograms should have the same window and resolution.
+    pub fn merge(&mut self, other: &Self) {
+        for (index, other_histogram) in &other.slices {
+            if let Some(histogram) = self.slice_mut(*index) {
+                histogram.merge(other_histogram);
+            }
+        }
+    }
+
+    /// Get the distribution of the round trip times over the window
+    /// ending at `now`
+    pub fn snapshot(&self, now: SystemTime) -> RttHistogram {
+        let first = self.first_index(self.slice_index(now));
+
+        let mut snapshot = RttHistogram::new();
+        for (_, histogram) in self.slices.iter().filter(|(index, _)| *index >= first) {
+            snapshot.merge(histogram);
+        }
+        snapshot
+    }
+
+    /// Get the slice starting at `index`, creating it if needed
+    ///
+    /// Returns `None` if the slice is older than the window.
+    fn slice_mut(&mut self, index: u64) -> Option<&mut RttHistogram> {
+        if let Some((latest, _)) = self.slices.back() {
+            if index < self.first_index(*latest) {
+                return None;
+            }
+        }
+
+        let position = self
+            .slices
+            .iter()
+            .rposition(|(slice_index, _)| *slice_index <= index);
+        let position = match position {
+            Some(position) if self.slices[position].0 == index => position,
+            Some(position) => {
+                self.slices
+                    .insert(position + 1, (index, RttHistogram::new()));
+                position + 1
+            }
+            None => {
+                self.slices.push_front((index, RttHistogram::new()));
+                0
+            }
+        };
+
+        // Drop the slices which just fell out of the window
+        let first = self.first_index(self.slices.back().map_or(index, |(latest, _)| *latest));
+        let expired = self
+            .slices
+            .iter()
+            .take_while(|(slice_index, _)| *slice_index < first)
+            .count();
+        self.slices.drain(..expired);
+
+        Some(&mut self.slices[position - expired].1)
+    }
+
+    /// Index of the oldest slice of the window ending with slice `latest`
+    fn first_index(&self, latest: u64) -> u64 {
+        (latest + 1).saturating_sub(self.len)
+    }
+
+    fn slice_index(&self, timestamp: SystemTime) -> u64 {
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        u64::try_from(since_epoch.as_nanos() / self.resolution.as_nanos()).unwrap_or(u64::MAX)
+    }
+}
+
+impl AggregatorOptions {
+    /// Construct the default `AggregatorOptions`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the duration over which round trip times are aggregated.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Set how often the window slides.
+    ///
+    /// Defaults to 10 seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resolution` is zero.
+    pub fn resolution(mut self, resolution: Duration) -> Self {
+        assert!(!resolution.is_zero(), "resolution must not be zero");
+        self.resolution = resolution;
+        self
+    }
+}
+
+impl Default for AggregatorOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(5 * 60),
+            resolution: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RttAggregator {
+    /// Construct a new `RttAggregator` without any target
+    pub fn new(options: AggregatorOptions) -> Self {
+        Self {
+            options,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Record the round trip time of `record`
+    ///
+    /// Records without a reply are ignored.
+    pub fn observe(&mut self, record: &PingRecord) {
+        if let Some(rtt) = record.rtt() {
+            self.record(record.target(), rtt, record.timestamp());
+        }
+    }
+
+    /// Record a round trip time to `target` measured at `timestamp`
+    pub fn record(&mut self, target: IpAddr, rtt: Duration, timestamp: SystemTime) {
+        self.histogram_mut(target).record(rtt, timestamp);
+    }
+
+    /// Add every round trip time recorded in `other`
+    ///
+    /// Both aggregators should have the same options.
+    pub fn merge(&mut self, other: &Self) {
+        for (&target, other_histogram) in &other.targets {
+            self.histogram_mut(target).merge(other_histogram);
+        }
+    }
+
+    /// Get the distribution of the round trip times to `target` over the
+    /// window ending at `now`
+    pub fn snapshot(&self, target: IpAddr, now: SystemTime) -> Option<RttHistogram> {
+        self.targets
+            .get(&target)
+            .map(|histogram| histogram.snapshot(now))
+    }
+
+    /// Iterate over the targets with recorded round trip times
+    pub fn targets(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.targets.keys().copied()
+    }
+
+    /// Stop tracking `target`, forgetting its round trip times
+    pub fn remove(&mut self, target: IpAddr) {
+        self.targets.remove(&target);
+    }
+
+    fn histogram_mut(&mut self, target: IpAddr) -> &mut WindowedHistogram {
+        let options = &self.options;
+        self.targets
+            .entry(target)
+            .or_insert_with(|| WindowedHistogram::new(options.window, options.resolution))
+    }
+}
+
+fn bucket_index(rtt: Duration) -> usize {
+    let nanos = u64::try_from(rtt.as_nanos()).unwrap_or(u64::MAX);
+    if nanos < LINEAR_BUCKETS as u64 {
+        return nanos as usize;
+    }
+
+    // Keep the top bits, so that `nanos >> shift` is in `SUB_BUCKETS..LINEAR_BUCKETS`
+    let shift =
+        (u64::BITS - nanos.leading_zeros()) as usize - SUB_BUCKETS.trailing_zeros() as usize - 1;
+    LINEAR_BUCKETS + (shift - 1) * SUB_BUCKETS + (nanos >> shift) as usize - SUB_BUCKETS
+}
+
+/// Get the shortest and longest round trip times in nanoseconds counted
+/// in bucket `index`
+fn bucket_bounds(index: usize) -> (u64, u64) {
+    if index < LINEAR_BUCKETS {
+        return (index as u64, index as u64);
+    }
+
+    let shift = (index - LINEAR_BUCKETS) / SUB_BUCKETS + 1;
+    let sub_bucket = ((index - LINEAR_BUCKETS) % SUB_BUCKETS + SUB_BUCKETS) as u64;
+    let lower = sub_bucket << shift;
+    let upper = lower.saturating_add((1 << shift) - 1);
+    (lower, upper)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{
+        AggregatorOptions, RttAggregator, RttHistogram, WindowedHistogram, bucket_bounds,
+        bucket_index,
+    };
+
+    #[test]
+    fn bucket_bounds_round_trip() {
+        let last = bucket_index(Duration::MAX);
+        assert_eq!(bucket_bounds(last).1, u64::MAX);
+
+        let mut next_lower = 0;
+        for index in 0..last {
+            let (lower, upper) = bucket_bounds(index);
+            assert_eq!(lower, next_lower, "bucket {index}");
+            assert!(lower <= upper);
+            assert_eq!(bucket_index(Duration::from_nanos(lower)), index);
+            assert_eq!(bucket_index(Duration::from_nanos(upper)), index);
+            next_lower = upper + 1;
+        }
+    }
+
+    #[test]
+    fn quantiles_within_3_percent() {
+        let mut histogram = RttHistogram::new();
+        let rtts = (1..=10_000)
+            .map(|i| Duration::from_micros(i * 7 + 13))
+            .collect::<Vec<_>>();
+        for &rtt in &rtts {
+            histogram.record(rtt);
+        }
+
+        assert_eq!(histogram.count(), 10_000);
+        assert_eq!(histogram.min(), Some(rtts[0]));
+        assert_eq!(histogram.max(), Some(rtts[9_999]));
+        for quantile in [0.01, 0.1, 0.25, 0.5, 0.9, 0.99, 0.999] {
+            let expected = rtts[(quantile * 10_000.0) as usize - 1].as_secs_f64();
+            let estimated = histogram.quantile(quantile).unwrap().as_secs_f64();
+            assert!(
+                (estimated - expected).abs() <= expected * 0.03,
+                "quantile {quantile}: estimated {estimated}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn sparse_buckets() {
+        let mut histogram = RttHistogram::new();
+        histogram.record(Duration::from_millis(20));
+        histogram.record(Duration::from_millis(10));
+        histogram.record(Duration::from_millis(40));
+
+        // Only the buckets between 10 and 40 ms are kept
+        assert!(histogram.counts.len() <= 2 * super::SUB_BUCKETS + 1);
+        assert_eq!(
+            histogram.buckets().map(|bucket| bucket.count).sum::<u64>(),
+            3
+        );
+        assert!(histogram.buckets().is_sorted_by_key(|bucket| bucket.lower));
+
+        histogram.clear();
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.buckets().count(), 0);
+        assert_eq!(histogram.quantile(0.5), None);
+    }
+
+    #[test]
+    fn merge() {
+        let mut all = RttHistogram::new();
+        let mut short = RttHistogram::new();
+        let mut long = RttHistogram::new();
+        for micros in 1..=100 {
+            let rtt = Duration::from_micros(micros * 50);
+            all.record(rtt);
+            if micros % 3 == 0 {
+                long.record(rtt * 100);
+                all.record(rtt * 100);
+            } else {
+                short.record(rtt);
+            }
+        }
+        for micros in (1..=100).filter(|micros| micros % 3 == 0) {
+            short.record(Duration::from_micros(micros * 50));
+        }
+
+        let mut merged = long.clone();
+        merged.merge(&short);
+        assert_eq!(merged, all);
+        merged.merge(&RttHistogram::new());
+        assert_eq!(merged, all);
+
+        let mut merged = RttHistogram::new();
+        merged.merge(&short);
+        merged.merge(&long);
+        assert_eq!(merged, all);
+    }
+
+    #[test]
+    fn window_expiry() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut histogram =
+            WindowedHistogram::new(Duration::from_secs(30), Duration::from_secs(10));
+        histogram.record(Duration::from_millis(1), at(5));
+        histogram.record(Duration::from_millis(2), at(15));
+        histogram.record(Duration::from_millis(3), at(25));
+        assert_eq!(histogram.snapshot(at(29)).count(), 3);
+
+        // The slice of [0, 10) falls out of the window
+        assert_eq!(histogram.snapshot(at(30)).count(), 2);
+        histogram.record(Duration::from_millis(4), at(35));
+        assert_eq!(
+            histogram.snapshot(at(35)).min(),
+            Some(Duration::from_millis(2))
+        );
+
+        // Too old to be recorded
+        histogram.record(Duration::from_millis(5), at(9));
+        assert_eq!(histogram.snapshot(at(35)).count(), 3);
+        assert_eq!(histogram.snapshot(at(60)).count(), 0);
+
+        let target = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let mut aggregator = RttAggregator::new(
+            AggregatorOptions::new()
+                .window(Duration::from_secs(30))
+                .resolution(Duration::from_secs(10)),
+        );
+        aggregator.record(target, Duration::from_millis(1), at(5));
+        aggregator.record(target, Duration::from_millis(2), at(35));
+        let snapshot = aggregator.snapshot(target, at(35)).unwrap();
+        assert_eq!(snapshot.count(), 1);
+        assert_eq!(snapshot.max(), Some(Duration::from_millis(2)));
+        assert_eq!(aggregator.targets().collect::<Vec<_>>(), [target]);
+    }
+}
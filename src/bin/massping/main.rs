@@ -0,0 +1,559 @@
+//! An fping-compatible command line interface to [`DualstackPinger`]
+
+use std::{
+    collections::HashMap,
+    future::{self, Future},
+    io,
+    iter::Copied,
+    net::IpAddr,
+    num::NonZeroU32,
+    path::PathBuf,
+    pin::pin,
+    process::ExitCode,
+    slice,
+    task::Poll,
+    time::Duration,
+};
+
+use clap::{Parser, error::ErrorKind};
+use massping::{DualstackMeasureManyStream, DualstackPinger};
+use tokio::time::{self, Instant};
+
+use self::{
+    stats::{Millis, TargetStats},
+    targets::Targets,
+};
+
+mod stats;
+mod targets;
+
+/// Size of the ICMP echo requests sent by [`massping::Pinger`]
+///
+/// Unlike fping's 56 bytes, the payload is 64 bytes long, so replies are
+/// 8 bytes larger than fping's.
+const PACKET_LEN: usize = 8 + 64;
+/// Size of the IPv4 header, without options
+const IPV4_HEADER_LEN: usize = 20;
+/// Granularity of the timer used to pace the pings
+const MIN_TICK: Duration = Duration::from_millis(1);
+
+/// Exit codes, same as fping
+const EXIT_UNREACHABLE: u8 = 1;
+const EXIT_UNKNOWN: u8 = 2;
+const EXIT_USAGE: u8 = 3;
+const EXIT_SYSTEM: u8 = 4;
+
+/// Send ICMP echo requests to network hosts, in batches.
+///
+/// Targets are taken from the command line, from a file given through
+/// -f, or from stdin if neither is given. A target can also be
+/// a CIDR network, which is expanded to all of its host addresses.
+#[derive(Parser)]
+#[command(name = "massping", version)]
+struct Args {
+    /// Show targets that are alive
+    #[arg(short = 'a', long = "alive")]
+    alive: bool,
+
+    /// Show targets that are unreachable
+    #[arg(short = 'u', long = "unreach")]
+    unreachable: bool,
+
+    /// Ping each target N times, printing every result and a summary
+    #[arg(
+        short = 'c',
+        long = "count",
+        value_name = "N",
+        conflicts_with = "vcount"
+    )]
+    count: Option<NonZeroU32>,
+
+    /// Same as -c, summarizing the round trip time of every ping
+    #[arg(short = 'C', long = "vcount", value_name = "N")]
+    vcount: Option<NonZeroU32>,
+
+    /// Don't print the result of every ping, only the summary
+    #[arg(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Print cumulative statistics on exit
+    #[arg(short = 's', long = "stats")]
+    stats: bool,
+
+    /// Read targets from FILE, or from stdin if FILE is -
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    file: Option<PathBuf>,
+
+    /// Generate targets from a CIDR network, or from a start and an end address
+    #[arg(short = 'g', long = "generate")]
+    generate: bool,
+
+    /// Interval between two pings to the same target, in milliseconds
+    #[arg(
+        short = 'p',
+        long = "period",
+        value_name = "MSEC",
+        default_value_t = 1000
+    )]
+    period: u64,
+
+    /// Interval between two pings to any target, in milliseconds
+    #[arg(
+        short = 'i',
+        long = "interval",
+        value_name = "MSEC",
+        default_value_t = 10
+    )]
+    interval: u64,
+
+    /// Maximum number of pings sent per second, instead of -i
+    #[arg(long = "rate", value_name = "PPS", conflicts_with = "interval")]
+    rate: Option<NonZeroU32>,
+
+    /// Time to wait for the reply to the first ping, in milliseconds
+    #[arg(
+        short = 't',
+        long = "timeout",
+        value_name = "MSEC",
+        default_value_t = 500
+    )]
+    timeout: u64,
+
+    /// Number of retries for unreachable targets, when neither -c nor -C are given
+    #[arg(short = 'r', long = "retry", value_name = "N", default_value_t = 3)]
+    retries: u32,
+
+    /// Factor the timeout is multiplied by at every retry
+    #[arg(short = 'B', long = "backoff", value_name = "F", default_value_t = 1.5)]
+    backoff: f64,
+
+    /// Targets to ping
+    #[arg(value_name = "TARGET")]
+    targets: Vec<String>,
+}
+
+impl Args {
+    /// Timeout of the given retry, or `None` if it's too long to be represented
+    fn retry_timeout(&self, retry: u32) -> Option<Duration> {
+        let secs = Duration::from_millis(self.timeout).as_secs_f64();
+        Duration::try_from_secs_f64(secs * self.backoff.powf(f64::from(retry))).ok()
+    }
+}
+
+/// The targets being pinged, along with their statistics
+struct Session {
+    args: Args,
+    targets: Targets,
+    stats: Vec<TargetStats>,
+    /// Width of the longest target name, for aligning the output
+    name_width: usize,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(err) => {
+            let _ = err.print();
+            return match err.kind() {
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => ExitCode::SUCCESS,
+                _ => ExitCode::from(EXIT_USAGE),
+            };
+        }
+    };
+    if !args.backoff.is_finite() || args.backoff < 1.0 {
+        eprintln!("massping: the backoff factor must be at least 1");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    // Retries are only made without -c and -C, and as the timeout
+    // grows with every one of them, the last one is the longest
+    if args.count.or(args.vcount).is_none() && args.retry_timeout(args.retries).is_none() {
+        eprintln!(
+            "massping: the backoff factor is too large for {} retries",
+            args.retries
+        );
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    let targets = match read_targets(&args) {
+        Ok(targets) => targets,
+        Err(err) => {
+            eprintln!("massping: {err}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let pinger = match DualstackPinger::new() {
+        Ok(pinger) => pinger,
+        Err(err) => {
+            eprintln!("massping: can't create socket: {err}");
+            return ExitCode::from(EXIT_SYSTEM);
+        }
+    };
+
+    let mut session = Session::new(args, targets);
+    let start = Instant::now();
+    let result = match session.args.count.or(session.args.vcount) {
+        Some(count) => session.run_count(&pinger, count.get()).await,
+        None => session.run_alive(&pinger).await,
+    };
+    if let Err(err) = result {
+        eprintln!("massping: {err}");
+        return ExitCode::from(EXIT_SYSTEM);
+    }
+
+    session.print_summary(start.elapsed());
+
+    if !session.targets.unknown.is_empty() {
+        ExitCode::from(EXIT_UNKNOWN)
+    } else if session.stats.iter().any(|stats| !stats.is_alive()) {
+        ExitCode::from(EXIT_UNREACHABLE)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn read_targets(args: &Args) -> Result<Targets, String> {
+    let mut targets = Targets::default();
+
+    if args.generate {
+        match args.targets.as_slice() {
+            [net] if net.contains('/') => targets.add(net)?,
+            [start, end] => targets.add_range(start, end)?,
+            _ => return Err("-g requires a CIDR network or a start and an end address".into()),
+        }
+    } else {
+        for name in &args.targets {
+            targets.add(name)?;
+        }
+    }
+
+    match &args.file {
+        Some(path) => targets.add_from_file(path)?,
+        None if args.targets.is_empty() => targets.add_from_reader(io::stdin().lock())?,
+        None => {}
+    }
+
+    Ok(targets)
+}
+
+impl Session {
+    fn new(args: Args, targets: Targets) -> Self {
+        let keep_rtts = args.vcount.is_some();
+        let stats = targets
+            .targets
+            .iter()
+            .map(|_| TargetStats::new(keep_rtts))
+            .collect();
+        let name_width = targets
+            .targets
+            .iter()
+            .map(|target| target.name.len())
+            .max()
+            .unwrap_or_default();
+
+        Self {
+            args,
+            targets,
+            stats,
+            name_width,
+        }
+    }
+
+    /// Ping every target until it replies, retrying with an increasing timeout
+    async fn run_alive(&mut self, pinger: &DualstackPinger) -> io::Result<()> {
+        let index = self.index();
+        let mut pending = (0..self.targets.targets.len()).collect::<Vec<_>>();
+
+        for retry in 0..=self.args.retries {
+            if pending.is_empty() {
+                break;
+            }
+
+            let addrs = pending
+                .iter()
+                .map(|&i| {
+                    self.stats[i].sent();
+                    self.targets.targets[i].addr
+                })
+                .collect::<Vec<_>>();
+            let timeout = self
+                .args
+                .retry_timeout(retry)
+                .expect("the timeout of the last retry was checked when parsing arguments");
+            ping_round(pinger, &addrs, timeout, self.interval(), |addr, rtt| {
+                let i = index[&addr];
+                self.stats[i].received(rtt);
+                if self.args.alive || !self.args.unreachable {
+                    println!("{} is alive", self.targets.targets[i].name);
+                }
+            })
+            .await?;
+
+            pending.retain(|&i| !self.stats[i].is_alive());
+        }
+
+        if self.args.unreachable || !self.args.alive {
+            for &i in &pending {
+                println!("{} is unreachable", self.targets.targets[i].name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ping every target `count` times, one round every period
+    async fn run_count(&mut self, pinger: &DualstackPinger, count: u32) -> io::Result<()> {
+        let index = self.index();
+        let addrs = self
+            .targets
+            .targets
+            .iter()
+            .map(|target| target.addr)
+            .collect::<Vec<_>>();
+        let timeout = Duration::from_millis(self.args.timeout);
+        let period = Duration::from_millis(self.args.period);
+
+        for round in 0..count {
+            let round_start = Instant::now();
+
+            for stats in &mut self.stats {
+                stats.sent();
+            }
+            ping_round(pinger, &addrs, timeout, self.interval(), |addr, rtt| {
+                let i = index[&addr];
+                self.stats[i].received(rtt);
+                if !self.args.quiet {
+                    let name = &self.targets.targets[i].name;
+                    print_reply(
+                        name,
+                        self.name_width,
+                        &self.stats[i],
+                        round,
+                        Some((addr, rtt)),
+                    );
+                }
+            })
+            .await?;
+
+            if !self.args.quiet {
+                for (target, stats) in self.targets.targets.iter().zip(&self.stats) {
+                    if !stats.replied() {
+                        print_reply(&target.name, self.name_width, stats, round, None);
+                    }
+                }
+            }
+
+            if round + 1 < count {
+                time::sleep_until(round_start + period).await;
+            }
+        }
+
+        if self.args.alive || self.args.unreachable {
+            for (target, stats) in self.targets.targets.iter().zip(&self.stats) {
+                if (self.args.alive && stats.is_alive())
+                    || (self.args.unreachable && !stats.is_alive())
+                {
+                    println!("{}", target.name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn index(&self) -> HashMap<IpAddr, usize> {
+        self.targets
+            .targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| (target.addr, i))
+            .collect()
+    }
+
+    fn interval(&self) -> Duration {
+        match self.args.rate {
+            Some(rate) => Duration::from_secs(1) / rate.get(),
+            None => Duration::from_millis(self.args.interval),
+        }
+    }
+
+    fn print_summary(&self, elapsed: Duration) {
+        let width = self.name_width;
+
+        if self.args.count.is_some() {
+            for (target, stats) in self.targets.targets.iter().zip(&self.stats) {
+                let name = &target.name;
+                let (sent, received, loss) =
+                    (stats.sent_count(), stats.received_count(), stats.loss());
+                match (stats.min(), stats.avg(), stats.max()) {
+                    (Some(min), Some(avg), Some(max)) => eprintln!(
+                        "{name:<width$} : xmt/rcv/%loss = {sent}/{received}/{loss}%, min/avg/max = {}/{}/{}",
+                        Millis(min),
+                        Millis(avg),
+                        Millis(max)
+                    ),
+                    _ => eprintln!("{name:<width$} : xmt/rcv/%loss = {sent}/{received}/{loss}%"),
+                }
+            }
+        }
+
+        if self.args.vcount.is_some() {
+            for (target, stats) in self.targets.targets.iter().zip(&self.stats) {
+                let rtts = stats
+                    .rtts()
+                    .iter()
+                    .map(|rtt| rtt.map_or_else(|| "-".to_owned(), |rtt| Millis(rtt).to_string()))
+                    .collect::<Vec<_>>();
+                eprintln!("{:<width$} : {}", target.name, rtts.join(" "));
+            }
+        }
+
+        if self.args.stats {
+            self.print_stats(elapsed);
+        }
+    }
+
+    fn print_stats(&self, elapsed: Duration) {
+        let alive = self.stats.iter().filter(|stats| stats.is_alive()).count();
+        let sent = self.stats.iter().map(TargetStats::sent_count).sum::<u32>();
+        let received = self
+            .stats
+            .iter()
+            .map(TargetStats::received_count)
+            .sum::<u32>();
+        let min = self.stats.iter().filter_map(TargetStats::min).min();
+        let max = self.stats.iter().filter_map(TargetStats::max).max();
+        let total = self.stats.iter().map(TargetStats::total).sum::<Duration>();
+        let (min, avg, max) = match (min, max) {
+            (Some(min), Some(max)) => (min, total / received, max),
+            _ => (Duration::ZERO, Duration::ZERO, Duration::ZERO),
+        };
+
+        eprintln!();
+        eprintln!(" {:>7} targets", self.targets.targets.len());
+        eprintln!(" {alive:>7} alive");
+        eprintln!(" {:>7} unreachable", self.targets.targets.len() - alive);
+        eprintln!(" {:>7} unknown addresses", self.targets.unknown.len());
+        eprintln!();
+        eprintln!(" {:>7} timeouts (waiting for response)", sent - received);
+        eprintln!(" {sent:>7} ICMP Echos sent");
+        eprintln!(" {received:>7} ICMP Echo Replies received");
+        eprintln!(" {:>7} other ICMP received", 0);
+        eprintln!();
+        eprintln!(" {} ms (min round trip time)", Millis(min));
+        eprintln!(" {} ms (avg round trip time)", Millis(avg));
+        eprintln!(" {} ms (max round trip time)", Millis(max));
+        eprintln!(" {:>12.3} sec (elapsed real time)", elapsed.as_secs_f64());
+        eprintln!();
+    }
+}
+
+/// Print the result of a ping, after it has been recorded into `stats`
+///
+/// Like fping, the size of IPv4 replies includes the IP header,
+/// while the size of IPv6 replies doesn't.
+fn print_reply(
+    name: &str,
+    width: usize,
+    stats: &TargetStats,
+    round: u32,
+    reply: Option<(IpAddr, Duration)>,
+) {
+    let avg = stats
+        .avg()
+        .map_or_else(|| "NaN".to_owned(), |avg| Millis(avg).to_string());
+
+    match reply {
+        Some((addr, rtt)) => println!(
+            "{name:<width$} : [{round}], {} bytes, {} ms ({avg} avg, {}% loss)",
+            if addr.is_ipv4() {
+                IPV4_HEADER_LEN + PACKET_LEN
+            } else {
+                PACKET_LEN
+            },
+            Millis(rtt),
+            stats.loss()
+        ),
+        None => println!(
+            "{name:<width$} : [{round}], timed out ({avg} avg, {}% loss)",
+            stats.loss()
+        ),
+    }
+}
+
+/// A group of targets pinged at the same time
+struct Batch<'a> {
+    stream: DualstackMeasureManyStream<'a, Copied<slice::Iter<'a, IpAddr>>>,
+    deadline: Instant,
+    remaining: usize,
+}
+
+/// Ping every address once, calling `on_reply` as the replies arrive
+///
+/// Pings are spread out so that at most one is sent every `interval`.
+/// Returns once every address has either replied or timed out.
+async fn ping_round(
+    pinger: &DualstackPinger,
+    addrs: &[IpAddr],
+    timeout: Duration,
+    interval: Duration,
+    mut on_reply: impl FnMut(IpAddr, Duration),
+) -> io::Result<()> {
+    let tick = interval.max(MIN_TICK);
+    let batch_len = if interval.is_zero() {
+        addrs.len().max(1)
+    } else {
+        (tick.as_nanos() / interval.as_nanos()) as usize
+    };
+
+    let mut chunks = addrs.chunks(batch_len);
+    let mut batches = Vec::<Batch<'_>>::new();
+    let mut next_batch_at = Instant::now();
+    let mut sleep = pin!(time::sleep_until(next_batch_at));
+
+    future::poll_fn(|cx| {
+        loop {
+            let now = Instant::now();
+            while next_batch_at <= now {
+                let Some(chunk) = chunks.next() else {
+                    break;
+                };
+
+                batches.push(Batch {
+                    stream: pinger.measure_many(chunk.iter().copied()),
+                    deadline: now + timeout,
+                    remaining: chunk.len(),
+                });
+                next_batch_at += tick;
+            }
+
+            batches.retain(|batch| batch.deadline > now);
+            for batch in &mut batches {
+                while batch.remaining > 0 {
+                    match batch.stream.poll_next_unpin(cx) {
+                        Poll::Ready(Some(Ok((addr, rtt)))) => {
+                            batch.remaining -= 1;
+                            on_reply(addr, rtt);
+                        }
+                        Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+                        Poll::Ready(None) | Poll::Pending => break,
+                    }
+                }
+            }
+            batches.retain(|batch| batch.remaining > 0);
+
+            let next_deadline = batches.iter().map(|batch| batch.deadline).min();
+            let wake_at = match (chunks.len(), next_deadline) {
+                (0, None) => return Poll::Ready(Ok(())),
+                (0, Some(deadline)) => deadline,
+                (_, None) => next_batch_at,
+                (_, Some(deadline)) => deadline.min(next_batch_at),
+            };
+
+            sleep.as_mut().reset(wake_at);
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    })
+    .await
+}
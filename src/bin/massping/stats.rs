@@ -0,0 +1,108 @@
+use std::{fmt, time::Duration};
+
+/// What has been sent to and received from a target
+#[derive(Default)]
+pub(crate) struct TargetStats {
+    sent: u32,
+    received: u32,
+    min: Option<Duration>,
+    max: Duration,
+    total: Duration,
+    /// Whether a reply arrived for the last ping
+    replied: bool,
+    /// Round trip time of every ping, only kept for the `-C` summary
+    rtts: Option<Vec<Option<Duration>>>,
+}
+
+impl TargetStats {
+    pub(crate) fn new(keep_rtts: bool) -> Self {
+        Self {
+            rtts: keep_rtts.then(Vec::new),
+            ..Self::default()
+        }
+    }
+
+    /// Record a ping being sent
+    pub(crate) fn sent(&mut self) {
+        self.sent += 1;
+        self.replied = false;
+        if let Some(rtts) = &mut self.rtts {
+            rtts.push(None);
+        }
+    }
+
+    /// Record the reply to the last ping
+    pub(crate) fn received(&mut self, rtt: Duration) {
+        self.received += 1;
+        self.replied = true;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = self.max.max(rtt);
+        self.total += rtt;
+        if let Some(last) = self.rtts.as_mut().and_then(|rtts| rtts.last_mut()) {
+            *last = Some(rtt);
+        }
+    }
+
+    pub(crate) fn replied(&self) -> bool {
+        self.replied
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        self.received > 0
+    }
+
+    pub(crate) fn sent_count(&self) -> u32 {
+        self.sent
+    }
+
+    pub(crate) fn received_count(&self) -> u32 {
+        self.received
+    }
+
+    pub(crate) fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    pub(crate) fn max(&self) -> Option<Duration> {
+        self.is_alive().then_some(self.max)
+    }
+
+    pub(crate) fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub(crate) fn avg(&self) -> Option<Duration> {
+        self.is_alive().then(|| self.total / self.received)
+    }
+
+    /// Percentage of lost pings, rounded down
+    pub(crate) fn loss(&self) -> u32 {
+        ((self.sent - self.received) * 100)
+            .checked_div(self.sent)
+            .unwrap_or_default()
+    }
+
+    /// Round trip time of every ping, `None` for the lost ones
+    pub(crate) fn rtts(&self) -> &[Option<Duration>] {
+        self.rtts.as_deref().unwrap_or_default()
+    }
+}
+
+/// Formats a round trip time in milliseconds the way fping does,
+/// with fewer decimals as the value grows
+pub(crate) struct Millis(pub(crate) Duration);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.as_secs_f64() * 1000.0;
+        if millis < 1.0 {
+            write!(f, "{millis:.3}")
+        } else if millis < 10.0 {
+            write!(f, "{millis:.2}")
+        } else if millis < 100.0 {
+            write!(f, "{millis:.1}")
+        } else {
+            write!(f, "{millis:.0}")
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    net::{IpAddr, ToSocketAddrs},
+    path::Path,
+};
+
+use ipnet::{IpAddrRange, IpNet, Ipv4AddrRange, Ipv6AddrRange};
+
+/// Maximum number of targets generated out of a single network or range
+const MAX_GENERATE: usize = 1 << 24;
+
+/// A host to be pinged
+pub(crate) struct Target {
+    /// The name the host was given as, printed in the output
+    pub(crate) name: String,
+    pub(crate) addr: IpAddr,
+}
+
+/// The list of targets, in the order they were given
+#[derive(Default)]
+pub(crate) struct Targets {
+    pub(crate) targets: Vec<Target>,
+    /// Names that couldn't be resolved
+    pub(crate) unknown: Vec<String>,
+    seen: HashSet<IpAddr>,
+}
+
+impl Targets {
+    /// Add a host name, address or CIDR network
+    ///
+    /// Names that can't be resolved are reported on stderr.
+    pub(crate) fn add(&mut self, name: &str) -> Result<(), String> {
+        if name.contains('/') {
+            let net = name
+                .parse::<IpNet>()
+                .map_err(|err| format!("{name}: {err}"))?;
+            return self.add_generated(name, net.hosts());
+        }
+
+        match resolve(name) {
+            Ok(addr) => self.push(name.to_owned(), addr),
+            Err(err) => {
+                eprintln!("{name}: {err}");
+                self.unknown.push(name.to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Add every address from `start` to `end`, both included
+    pub(crate) fn add_range(&mut self, start: &str, end: &str) -> Result<(), String> {
+        let parse = |addr: &str| {
+            addr.parse::<IpAddr>()
+                .map_err(|err| format!("{addr}: {err}"))
+        };
+
+        let range = match (parse(start)?, parse(end)?) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => IpAddrRange::V4(Ipv4AddrRange::new(start, end)),
+            (IpAddr::V6(start), IpAddr::V6(end)) => IpAddrRange::V6(Ipv6AddrRange::new(start, end)),
+            _ => return Err(format!("{start} and {end} aren't of the same IP version")),
+        };
+        self.add_generated(&format!("{start} {end}"), range)
+    }
+
+    /// Add the targets read from `reader`, one per line
+    ///
+    /// Only the first word of each line is considered, while empty
+    /// lines and lines starting with `#` are skipped.
+    pub(crate) fn add_from_reader(&mut self, reader: impl BufRead) -> Result<(), String> {
+        for line in reader.lines() {
+            let line = line.map_err(|err| format!("can't read targets: {err}"))?;
+            match line.split_whitespace().next() {
+                Some(name) if !name.starts_with('#') => self.add(name)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the targets listed in `path`, or in stdin if `path` is `-`
+    pub(crate) fn add_from_file(&mut self, path: &Path) -> Result<(), String> {
+        if path == Path::new("-") {
+            return self.add_from_reader(io::stdin().lock());
+        }
+
+        let file =
+            File::open(path).map_err(|err| format!("can't open {}: {err}", path.display()))?;
+        self.add_from_reader(BufReader::new(file))
+    }
+
+    fn add_generated(
+        &mut self,
+        name: &str,
+        addrs: impl Iterator<Item = IpAddr>,
+    ) -> Result<(), String> {
+        let mut generated = 0;
+        for addr in addrs {
+            generated += 1;
+            if generated > MAX_GENERATE {
+                return Err(format!(
+                    "{name}: can't generate more than {MAX_GENERATE} targets"
+                ));
+            }
+            self.push(addr.to_string(), addr);
+        }
+        Ok(())
+    }
+
+    /// Add a target, unless its address was already added
+    fn push(&mut self, name: String, addr: IpAddr) {
+        if self.seen.insert(addr) {
+            self.targets.push(Target { name, addr });
+        }
+    }
+}
+
+fn resolve(name: &str) -> io::Result<IpAddr> {
+    if let Ok(addr) = name.parse() {
+        return Ok(addr);
+    }
+
+    (name, 0)
+        .to_socket_addrs()?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address associated"))
+}
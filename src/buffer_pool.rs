@@ -0,0 +1,93 @@
+use std::{
+    mem::{self, MaybeUninit},
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+
+/// A pool of fixed size receive buffers.
+///
+/// Buffers are handed out by [`BufferPool::get`] and go back to the pool
+/// once the [`PooledBuffer`], or the [`Bytes`] it was frozen into, is dropped.
+#[derive(Clone)]
+pub(crate) struct BufferPool {
+    inner: Arc<InnerPool>,
+}
+
+struct InnerPool {
+    idle: Mutex<Vec<Box<[u8]>>>,
+    buffer_len: usize,
+    max_idle: usize,
+}
+
+/// A buffer borrowed from a [`BufferPool`]
+pub(crate) struct PooledBuffer {
+    buf: Box<[u8]>,
+    len: usize,
+    pool: Arc<InnerPool>,
+}
+
+impl BufferPool {
+    /// Construct a new pool of buffers of `buffer_len` bytes, retaining
+    /// at most `max_idle` of them while they are not being used
+    pub(crate) fn new(buffer_len: usize, max_idle: usize) -> Self {
+        Self {
+            inner: Arc::new(InnerPool {
+                idle: Mutex::new(Vec::with_capacity(max_idle)),
+                buffer_len,
+                max_idle,
+            }),
+        }
+    }
+
+    /// Take a buffer out of the pool, allocating a new one if the pool is empty
+    pub(crate) fn get(&self) -> PooledBuffer {
+        let buf = self
+            .inner
+            .idle
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| vec![0; self.inner.buffer_len].into_boxed_slice());
+
+        PooledBuffer {
+            buf,
+            len: 0,
+            pool: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl PooledBuffer {
+    /// Get the whole buffer for a socket to write into
+    pub(crate) fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        // SAFETY: `u8` and `MaybeUninit<u8>` have the same layout and the
+        // sockets only ever write initialized bytes into the buffer
+        unsafe { mem::transmute::<&mut [u8], &mut [MaybeUninit<u8>]>(&mut self.buf) }
+    }
+
+    /// Turn the first `len` bytes of the buffer into [`Bytes`]
+    ///
+    /// The buffer is returned to the pool once every clone of
+    /// the returned [`Bytes`] has been dropped.
+    pub(crate) fn freeze(mut self, len: usize) -> Bytes {
+        assert!(len <= self.buf.len());
+        self.len = len;
+        Bytes::from_owner(self)
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut idle = self.pool.idle.lock().unwrap();
+        if idle.len() < self.pool.max_idle {
+            idle.push(mem::take(&mut self.buf));
+        }
+    }
+}
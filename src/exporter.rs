@@ -0,0 +1,604 @@
+//! Prometheus metrics for continuous monitoring
+//!
+//! [`Exporter`] pings a set of targets at a fixed interval, and keeps
+//! the following metrics for each of them in [`Metrics`], labeled by
+//! `target`:
+//!
+//! | Metric                                  | Type      | Description                                        |
+//! |-----------------------------------------|-----------|----------------------------------------------------|
+//! | `massping_up`                           | gauge     | 1 if the target replied to the last ping, else 0   |
+//! | `massping_loss_ratio`                   | gauge     | ratio of lost pings over the last few rounds       |
+//! | `massping_last_seen_timestamp_seconds`  | gauge     | Unix time of the last reply                        |
+//! | `massping_rtt_seconds`                  | histogram | round trip time of the replies                     |
+//! | `massping_pings_total`                  | counter   | number of pings sent                               |
+//!
+//! [`Metrics::render`] formats them in the Prometheus text exposition
+//! format. With the `prometheus-http` feature, [`Metrics::serve`] exposes
+//! them over HTTP.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    convert::Infallible,
+    fmt::{self, Write as _},
+    future, io,
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, PoisonError},
+    task::Poll,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    DualstackPinger,
+    rt::{Instant, Sleep},
+};
+
+/// Default upper bounds of the round trip time histogram buckets, in seconds
+const DEFAULT_BUCKETS: [f64; 13] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Pings a set of targets forever, recording the results into [`Metrics`]
+pub struct Exporter {
+    targets: Vec<IpAddr>,
+    interval: Duration,
+    timeout: Duration,
+    metrics: Metrics,
+}
+
+/// Metrics of every target of an [`Exporter`].
+///
+/// Cloning it is cheap, as clones share the same metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<Mutex<InnerMetrics>>,
+}
+
+struct InnerMetrics {
+    /// Upper bounds of the histogram buckets, in seconds
+    buckets: Vec<f64>,
+    loss_window: usize,
+    targets: BTreeMap<IpAddr, TargetMetrics>,
+}
+
+#[derive(Default)]
+struct TargetMetrics {
+    up: bool,
+    /// Whether each of the last pings got a reply
+    window: VecDeque<bool>,
+    last_seen: Option<SystemTime>,
+    /// Replies in each histogram bucket, not cumulative
+    bucket_counts: Vec<u64>,
+    rtt_sum: Duration,
+    rtt_count: u64,
+    pings: u64,
+}
+
+impl Exporter {
+    /// Construct a new `Exporter` pinging `targets`
+    ///
+    /// By default a round of pings is sent every 5 seconds, waiting 1 second
+    /// for the replies, and the loss ratio is computed over the last 10 rounds.
+    pub fn new<I>(targets: I) -> Self
+    where
+        I: IntoIterator<Item = IpAddr>,
+    {
+        let mut targets = targets.into_iter().collect::<Vec<_>>();
+        targets.sort_unstable();
+        targets.dedup();
+
+        let metrics = Metrics {
+            inner: Arc::new(Mutex::new(InnerMetrics {
+                buckets: DEFAULT_BUCKETS.to_vec(),
+                loss_window: 10,
+                targets: BTreeMap::new(),
+            })),
+        };
+        Self {
+            targets,
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(1),
+            metrics,
+        }
+    }
+
+    /// Set the interval between the start of two rounds of pings
+    ///
+    /// If it's shorter than the timeout, the next round starts as
+    /// soon as the previous one is over.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set how long to wait for the replies of a round of pings
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the upper bounds of the round trip time histogram buckets
+    pub fn buckets(self, buckets: impl IntoIterator<Item = Duration>) -> Self {
+        let mut buckets = buckets
+            .into_iter()
+            .map(|bucket| bucket.as_secs_f64())
+            .collect::<Vec<_>>();
+        buckets.sort_unstable_by(f64::total_cmp);
+        buckets.dedup();
+
+        self.metrics.lock().buckets = buckets;
+        self
+    }
+
+    /// Set the number of rounds the loss ratio is computed over
+    pub fn loss_window(self, rounds: NonZeroUsize) -> Self {
+        self.metrics.lock().loss_window = rounds.get();
+        self
+    }
+
+    /// Get the metrics recorded by the exporter
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Ping the targets through `pinger` forever
+    ///
+    /// Only returns if receiving the replies fails.
+    pub async fn run(&self, pinger: &DualstackPinger) -> io::Result<Infallible> {
+        let mut sleep = Sleep::new(Instant::now());
+
+        loop {
+            let round_start = Instant::now();
+            let replies = self
+                .round(pinger, &mut sleep, round_start + self.timeout)
+                .await?;
+            self.metrics.record_round(&self.targets, &replies);
+
+            sleep.reset(round_start + self.interval);
+            future::poll_fn(|cx| sleep.poll(cx)).await;
+        }
+    }
+
+    /// Ping every target once, collecting the replies received before `deadline`
+    async fn round(
+        &self,
+        pinger: &DualstackPinger,
+        sleep: &mut Sleep,
+        deadline: Instant,
+    ) -> io::Result<HashMap<IpAddr, (Duration, SystemTime)>> {
+        let mut replies = HashMap::with_capacity(self.targets.len());
+        let mut stream = pinger.measure_many(self.targets.iter().copied());
+        sleep.reset(deadline);
+
+        future::poll_fn(|cx| {
+            while replies.len() < self.targets.len() {
+                match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok((addr, rtt)))) => {
+                        replies.insert(addr, (rtt, SystemTime::now()));
+                    }
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+                    Poll::Ready(None) | Poll::Pending => break,
+                }
+            }
+
+            if replies.len() == self.targets.len() || sleep.poll(cx).is_ready() {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await?;
+
+        Ok(replies)
+    }
+}
+
+impl Metrics {
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.lock()
+            .render(&mut out)
+            .expect("writing into a String can't fail");
+        out
+    }
+
+    fn record_round(&self, targets: &[IpAddr], replies: &HashMap<IpAddr, (Duration, SystemTime)>) {
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+
+        for &target in targets {
+            let metrics = inner.targets.entry(target).or_default();
+            let reply = replies.get(&target);
+
+            metrics.pings += 1;
+            metrics.up = reply.is_some();
+            metrics.window.push_back(reply.is_some());
+            while metrics.window.len() > inner.loss_window {
+                metrics.window.pop_front();
+            }
+
+            if let Some(&(rtt, seen_at)) = reply {
+                metrics.last_seen = Some(seen_at);
+                metrics.rtt_sum += rtt;
+                metrics.rtt_count += 1;
+
+                metrics.bucket_counts.resize(inner.buckets.len(), 0);
+                let rtt = rtt.as_secs_f64();
+                if let Some(bucket) = inner.buckets.iter().position(|&le| rtt <= le) {
+                    metrics.bucket_counts[bucket] += 1;
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, InnerMetrics> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl InnerMetrics {
+    fn render(&self, out: &mut String) -> fmt::Result {
+        header(
+            out,
+            "massping_up",
+            "gauge",
+            "Whether the target replied to the last ping",
+        )?;
+        for (target, metrics) in &self.targets {
+            let target = LabelValue(target);
+            writeln!(
+                out,
+                "massping_up{{target=\"{target}\"}} {}",
+                u8::from(metrics.up)
+            )?;
+        }
+
+        header(
+            out,
+            "massping_loss_ratio",
+            "gauge",
+            "Ratio of pings without a reply over the last rounds",
+        )?;
+        for (target, metrics) in &self.targets {
+            let target = LabelValue(target);
+            let lost = metrics.window.iter().filter(|&&replied| !replied).count();
+            let ratio = lost as f64 / metrics.window.len().max(1) as f64;
+            writeln!(out, "massping_loss_ratio{{target=\"{target}\"}} {ratio}")?;
+        }
+
+        header(
+            out,
+            "massping_last_seen_timestamp_seconds",
+            "gauge",
+            "Unix time of the last reply from the target",
+        )?;
+        for (target, metrics) in &self.targets {
+            let target = LabelValue(target);
+            if let Some(last_seen) = metrics.last_seen {
+                let secs = last_seen
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                writeln!(
+                    out,
+                    "massping_last_seen_timestamp_seconds{{target=\"{target}\"}} {secs}"
+                )?;
+            }
+        }
+
+        header(
+            out,
+            "massping_rtt_seconds",
+            "histogram",
+            "Round trip time of the replies",
+        )?;
+        for (target, metrics) in &self.targets {
+            let target = LabelValue(target);
+            let mut cumulative = 0;
+            for (i, le) in self.buckets.iter().enumerate() {
+                cumulative += metrics.bucket_counts.get(i).copied().unwrap_or_default();
+                writeln!(
+                    out,
+                    "massping_rtt_seconds_bucket{{target=\"{target}\",le=\"{le}\"}} {cumulative}"
+                )?;
+            }
+            writeln!(
+                out,
+                "massping_rtt_seconds_bucket{{target=\"{target}\",le=\"+Inf\"}} {}",
+                metrics.rtt_count
+            )?;
+            writeln!(
+                out,
+                "massping_rtt_seconds_sum{{target=\"{target}\"}} {}",
+                metrics.rtt_sum.as_secs_f64()
+            )?;
+            writeln!(
+                out,
+                "massping_rtt_seconds_count{{target=\"{target}\"}} {}",
+                metrics.rtt_count
+            )?;
+        }
+
+        header(
+            out,
+            "massping_pings_total",
+            "counter",
+            "Number of pings sent",
+        )?;
+        for (target, metrics) in &self.targets {
+            let target = LabelValue(target);
+            writeln!(
+                out,
+                "massping_pings_total{{target=\"{target}\"}} {}",
+                metrics.pings
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} {kind}")
+}
+
+/// A label value, escaped as required by the text exposition format
+struct LabelValue<T>(T);
+
+impl<T: fmt::Display> fmt::Display for LabelValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Escaper<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+        impl fmt::Write for Escaper<'_, '_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for c in s.chars() {
+                    match c {
+                        '\\' => self.0.write_str("\\\\")?,
+                        '"' => self.0.write_str("\\\"")?,
+                        '\n' => self.0.write_str("\\n")?,
+                        c => self.0.write_char(c)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        write!(Escaper(f), "{}", self.0)
+    }
+}
+
+#[cfg(feature = "prometheus-http")]
+mod http {
+    use std::{convert::Infallible, io, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::{TcpListener, TcpStream},
+        time,
+    };
+
+    use super::Metrics;
+
+    /// Maximum size of the request head
+    const MAX_REQUEST_LEN: usize = 8192;
+    /// Time given to clients for sending the request head
+    const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+    impl Metrics {
+        /// Serve the metrics over HTTP on `listener`
+        ///
+        /// `GET /metrics` requests are answered with [`Metrics::render`], while
+        /// every other request gets a 404. Each connection is handled by its own
+        /// tokio task and is closed after the response, or if the request head
+        /// isn't received within 10 seconds. Only returns if accepting a
+        /// connection fails.
+        pub async fn serve(&self, listener: TcpListener) -> io::Result<Infallible> {
+            loop {
+                let (stream, _addr) = listener.accept().await?;
+
+                let metrics = self.clone();
+                tokio::spawn(async move {
+                    let _ = metrics.respond(stream).await;
+                });
+            }
+        }
+
+        async fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+            let Ok(request) = time::timeout(READ_TIMEOUT, read_request(&mut stream)).await else {
+                return Ok(());
+            };
+            let Some(request) = request? else {
+                return Ok(());
+            };
+
+            let response = if request.starts_with(b"GET /metrics ")
+                || request.starts_with(b"GET /metrics?")
+            {
+                let body = self.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_owned()
+            };
+
+            stream.write_all(response.as_bytes()).await?;
+            stream.shutdown().await
+        }
+    }
+
+    /// Read the request head, or `None` if the connection is closed or it's too long
+    async fn read_request(stream: &mut TcpStream) -> io::Result<Option<Vec<u8>>> {
+        let mut request = Vec::with_capacity(1024);
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            if request.len() >= MAX_REQUEST_LEN {
+                return Ok(None);
+            }
+
+            let mut buf = [0; 1024];
+            let read = stream.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+
+        Ok(Some(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        num::NonZeroUsize,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{Exporter, LabelValue};
+
+    const V4: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+    #[test]
+    fn render() {
+        let exporter =
+            Exporter::new([V6, V4]).buckets([Duration::from_millis(5), Duration::from_millis(1)]);
+        let metrics = exporter.metrics();
+        let replies = HashMap::from([(
+            V4,
+            (
+                Duration::from_millis(3),
+                UNIX_EPOCH + Duration::from_millis(1500),
+            ),
+        )]);
+        metrics.record_round(&exporter.targets, &replies);
+
+        assert_eq!(
+            metrics.render(),
+            "\
+# HELP massping_up Whether the target replied to the last ping
+# TYPE massping_up gauge
+massping_up{target=\"192.0.2.1\"} 1
+massping_up{target=\"2001:db8::1\"} 0
+# HELP massping_loss_ratio Ratio of pings without a reply over the last rounds
+# TYPE massping_loss_ratio gauge
+massping_loss_ratio{target=\"192.0.2.1\"} 0
+massping_loss_ratio{target=\"2001:db8::1\"} 1
+# HELP massping_last_seen_timestamp_seconds Unix time of the last reply from the target
+# TYPE massping_last_seen_timestamp_seconds gauge
+massping_last_seen_timestamp_seconds{target=\"192.0.2.1\"} 1.5
+# HELP massping_rtt_seconds Round trip time of the replies
+# TYPE massping_rtt_seconds histogram
+massping_rtt_seconds_bucket{target=\"192.0.2.1\",le=\"0.001\"} 0
+massping_rtt_seconds_bucket{target=\"192.0.2.1\",le=\"0.005\"} 1
+massping_rtt_seconds_bucket{target=\"192.0.2.1\",le=\"+Inf\"} 1
+massping_rtt_seconds_sum{target=\"192.0.2.1\"} 0.003
+massping_rtt_seconds_count{target=\"192.0.2.1\"} 1
+massping_rtt_seconds_bucket{target=\"2001:db8::1\",le=\"0.001\"} 0
+massping_rtt_seconds_bucket{target=\"2001:db8::1\",le=\"0.005\"} 0
+massping_rtt_seconds_bucket{target=\"2001:db8::1\",le=\"+Inf\"} 0
+massping_rtt_seconds_sum{target=\"2001:db8::1\"} 0
+massping_rtt_seconds_count{target=\"2001:db8::1\"} 0
+# HELP massping_pings_total Number of pings sent
+# TYPE massping_pings_total counter
+massping_pings_total{target=\"192.0.2.1\"} 1
+massping_pings_total{target=\"2001:db8::1\"} 1
+"
+        );
+    }
+
+    #[test]
+    fn loss_window() {
+        let exporter = Exporter::new([V4]).loss_window(NonZeroUsize::new(2).unwrap());
+        let metrics = exporter.metrics();
+        let reply = HashMap::from([(V4, (Duration::from_millis(1), UNIX_EPOCH))]);
+        for replies in [HashMap::new(), reply.clone(), HashMap::new(), reply] {
+            metrics.record_round(&exporter.targets, &replies);
+        }
+
+        let render = metrics.render();
+        assert!(render.contains("massping_up{target=\"192.0.2.1\"} 1\n"));
+        assert!(render.contains("massping_loss_ratio{target=\"192.0.2.1\"} 0.5\n"));
+        assert!(render.contains("massping_pings_total{target=\"192.0.2.1\"} 4\n"));
+    }
+
+    #[test]
+    fn label_values() {
+        assert_eq!(LabelValue("a\\b\"c\nd").to_string(), "a\\\\b\\\"c\\nd");
+        assert_eq!(LabelValue("fe80::1").to_string(), "fe80::1");
+    }
+
+    #[cfg(feature = "prometheus-http")]
+    mod http {
+        use std::time::Duration;
+
+        use tokio::{
+            io::{AsyncReadExt as _, AsyncWriteExt as _},
+            net::{TcpListener, TcpStream},
+            time::Instant,
+        };
+
+        use super::{
+            super::{Exporter, Metrics},
+            V4,
+        };
+
+        async fn serve(metrics: Metrics) -> TcpStream {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move { metrics.serve(listener).await });
+
+            TcpStream::connect(addr).await.unwrap()
+        }
+
+        #[tokio::test]
+        async fn metrics() {
+            let metrics = Exporter::new([V4]).metrics();
+            let mut stream = serve(metrics.clone()).await;
+            stream
+                .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            let (head, body) = response.split_once("\r\n\r\n").unwrap();
+            assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(
+                head.contains("\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n")
+            );
+            assert_eq!(body, metrics.render());
+        }
+
+        #[tokio::test]
+        async fn not_found() {
+            let mut stream = serve(Exporter::new([V4]).metrics()).await;
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn idle_client() {
+            let mut stream = serve(Exporter::new([V4]).metrics()).await;
+            let start = Instant::now();
+            stream
+                .write_all(b"GET /metrics HTTP/1.1\r\n")
+                .await
+                .unwrap();
+
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await.unwrap();
+            assert!(response.is_empty());
+            assert!(start.elapsed() >= Duration::from_secs(10));
+        }
+    }
+}
@@ -1,12 +1,42 @@
-//! Asynchronous ICMP ping library using Linux DGRAM sockets and the
-//! tokio runtime.
+//! Asynchronous ICMP ping library using Linux DGRAM sockets and either
+//! the tokio or the async-io runtime.
 //!
 //! This crate uses `SOCK_DGRAM` sockets with `IPPROTO_ICMP`/`IPPROTO_ICMPV6`,
 //! which allows sending ICMP echo requests without root privileges on Linux.
 //!
 //! ## Features
 //!
-//! * `stream`: implements [`Stream`] for [`MeasureManyStream`].
+//! * `stream`: implements [`Stream`] for [`MeasureManyStream`], and adds
+//!   [`Pinger::measure_stream_with_retries`] taking the targets from a
+//!   [`Stream`].
+//! * `runtime-tokio` (default): uses the tokio reactor and timers, and
+//!   allows spawning the receive task of [`Pinger`] with [`Pinger::new`].
+//! * `runtime-async-io`: uses the async-io reactor and timers, which makes
+//!   the crate usable with smol. The receive task has to be run through
+//!   [`Pinger::with_driver`]. `runtime-tokio` takes precedence if both
+//!   features are enabled.
+//! * `io-uring`: sends and receives ICMP packets through `io_uring` instead
+//!   of epoll, submitting the echo requests of a batch together. Packets are
+//!   still copied, as ICMP sockets don't support zero-copy sends. Requires
+//!   Linux 5.3 or later.
+//! * `sim`: adds the [`sim`] module, a simulated network for testing code
+//!   built on top of [`Pinger`] without sending any real ICMP packet.
+//! * `prometheus`: adds the [`exporter`] module, which continuously pings
+//!   a set of targets and renders the results as Prometheus metrics.
+//! * `prometheus-http`: serves the Prometheus metrics over HTTP. Requires
+//!   the tokio runtime.
+//! * `serde`: implements `Serialize` and `Deserialize` for result and
+//!   statistics types, as described in [Serialization](#serialization).
+//! * `cli`: builds the `massping` binary, an fping-compatible command line
+//!   interface to [`DualstackPinger`].
+//!
+//! ## Serialization
+//!
+//! With the `serde` feature, durations such as round trip times are
+//! serialized as an integer number of nanoseconds, and points in time as
+//! nanoseconds since the Unix epoch. Serializing a duration longer than
+//! `u64::MAX` nanoseconds, about 584 years, or a point in time before the
+//! Unix epoch fails.
 //!
 //! ## MSRV version policy
 //!
@@ -24,28 +54,65 @@
 )]
 
 use std::{
+    future::{self, Future},
     io,
     marker::PhantomData,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    pin::Pin,
     task::{Context, Poll},
     time::Duration,
 };
-#[cfg(feature = "stream")]
-use std::{pin::Pin, task::ready};
 
 #[cfg(feature = "stream")]
 use futures_core::Stream;
 
 pub use self::{
     ip_version::IpVersion,
-    pinger::{MeasureManyStream, Pinger, V4Pinger, V6Pinger},
+    monitor::{Monitor, MonitorHandle, MonitorOptions},
+    pinger::{
+        MeasureManyStream, Pinger, PingerDriver, PingerOptions, Shutdown, V4Pinger, V6Pinger,
+    },
+    probe_pinger::{ProbeManyStream, ProbePinger, ProbeResult, V4ProbePinger, V6ProbePinger},
+    reply_queue::OverflowPolicy,
+    retry::{RetryMeasureManyStream, RetryOptions, RetryResult},
+    sharded_pinger::{ShardedMeasureManyStream, ShardedPinger, V4ShardedPinger, V6ShardedPinger},
+    sync_pinger::{SyncMeasureMany, SyncPinger, V4SyncPinger, V6SyncPinger},
+    timestamp_pinger::{TimestampMeasureManyStream, TimestampMeasurement, TimestampPinger},
 };
 
+#[cfg(feature = "stream")]
+pub use self::measure_stream::{DualstackRetryMeasureStream, RetryMeasureStream};
+
+#[cfg(not(any(feature = "runtime-tokio", feature = "runtime-async-io")))]
+compile_error!("either the `runtime-tokio` or the `runtime-async-io` feature must be enabled");
+
+pub mod aggregate;
+mod buffer_pool;
+#[cfg(feature = "prometheus")]
+pub mod exporter;
 mod ip_version;
+#[cfg(feature = "stream")]
+mod measure_stream;
+mod monitor;
+#[cfg(feature = "serde")]
+mod nanos;
 pub mod packet;
 mod pinger;
+mod probe_pinger;
+pub mod quality;
 pub mod raw_pinger;
+pub mod reachability;
+mod reply_queue;
+pub mod report;
+mod retry;
+mod rt;
+mod sharded_pinger;
+#[cfg(feature = "sim")]
+pub mod sim;
 mod socket;
+mod sync_pinger;
+mod timestamp_pinger;
+pub mod transport;
 
 /// A pinger for both [`Ipv4Addr`] and [`Ipv6Addr`] addresses.
 pub struct DualstackPinger {
@@ -60,12 +127,51 @@ impl DualstackPinger {
     /// be used for as long as possible, altough it might also
     /// be beneficial to `Drop` the `DualstackPinger` and recreate it if
     /// you are not going to be sending pings for a long period of time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime, see [`Pinger::new`].
+    #[cfg(feature = "runtime-tokio")]
     pub fn new() -> io::Result<Self> {
         let v4 = V4Pinger::new()?;
         let v6 = V6Pinger::new()?;
         Ok(Self { v4, v6 })
     }
 
+    /// Construct a new `DualstackPinger` with the given `options`, leaving
+    /// it to the caller to run the receive task of both pingers.
+    ///
+    /// See [`Pinger::with_driver`].
+    pub fn with_driver(
+        options: PingerOptions,
+    ) -> io::Result<(Self, impl Future<Output = ()> + Send + 'static)> {
+        let (v4, v4_driver) = V4Pinger::with_driver(options.clone())?;
+        let (v6, v6_driver) = V6Pinger::with_driver(options)?;
+
+        let mut v4_driver = Some(v4_driver);
+        let mut v6_driver = Some(v6_driver);
+        let driver = future::poll_fn(move |cx| {
+            if let Some(driver) = &mut v4_driver {
+                if Pin::new(driver).poll(cx).is_ready() {
+                    v4_driver = None;
+                }
+            }
+            if let Some(driver) = &mut v6_driver {
+                if Pin::new(driver).poll(cx).is_ready() {
+                    v6_driver = None;
+                }
+            }
+
+            if v4_driver.is_none() && v6_driver.is_none() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        });
+
+        Ok((Self { v4, v6 }, driver))
+    }
+
     /// Ping `addresses`
     ///
     /// Creates [`DualstackMeasureManyStream`] which **lazily** sends ping
@@ -90,6 +196,60 @@ impl DualstackPinger {
             v6: self.v6.measure_many(addresses_v6),
         }
     }
+
+    /// Ping `addresses`, retrying the ones which don't reply in time
+    ///
+    /// Creates [`DualstackRetryMeasureManyStream`], see
+    /// [`Pinger::measure_many_with_retries`].
+    pub fn measure_many_with_retries<I>(
+        &self,
+        addresses: I,
+        options: RetryOptions,
+    ) -> DualstackRetryMeasureManyStream<'_, I>
+    where
+        I: Iterator<Item = IpAddr> + Clone,
+    {
+        let addresses_v4 = FilterIpAddr {
+            iter: addresses.clone(),
+            _marker: PhantomData,
+        };
+        let addresses_v6 = FilterIpAddr {
+            iter: addresses,
+            _marker: PhantomData,
+        };
+
+        DualstackRetryMeasureManyStream {
+            v4: self
+                .v4
+                .measure_many_with_retries(addresses_v4, options.clone()),
+            v6: self.v6.measure_many_with_retries(addresses_v6, options),
+        }
+    }
+
+    /// Ping the addresses coming from `addresses` as they arrive, retrying
+    /// the ones which don't reply in time
+    ///
+    /// Creates [`DualstackRetryMeasureStream`], see
+    /// [`Pinger::measure_stream_with_retries`].
+    #[cfg(feature = "stream")]
+    pub fn measure_stream_with_retries<S>(
+        &self,
+        addresses: S,
+        options: RetryOptions,
+    ) -> DualstackRetryMeasureStream<'_, S>
+    where
+        S: Stream<Item = IpAddr> + Unpin,
+    {
+        DualstackRetryMeasureStream::new(&self.v4, &self.v6, addresses, options)
+    }
+
+    /// Stop the receive tasks of both pingers, waiting for them to exit
+    ///
+    /// See [`Pinger::shutdown`].
+    pub async fn shutdown(&self) {
+        self.v4.shutdown().await;
+        self.v6.shutdown().await;
+    }
 }
 
 /// A [`Stream`] of ping responses.
@@ -98,23 +258,35 @@ impl DualstackPinger {
 /// like [`tokio::time::timeout`] should be used to prevent the program
 /// from hanging indefinitely.
 ///
-/// Leaking this method might crate a slowly forever growing memory leak.
+/// Replies are queued up the same way as for [`MeasureManyStream`].
 ///
 /// [`Stream`]: futures_core::Stream
-/// [`tokio::time::timeout`]: tokio::time::timeout
+/// [`tokio::time::timeout`]: https://docs.rs/tokio/latest/tokio/time/fn.timeout.html
 pub struct DualstackMeasureManyStream<'a, I: Iterator<Item = IpAddr>> {
     v4: MeasureManyStream<'a, Ipv4Addr, FilterIpAddr<I, Ipv4Addr>>,
     v6: MeasureManyStream<'a, Ipv6Addr, FilterIpAddr<I, Ipv6Addr>>,
 }
 
 impl<I: Iterator<Item = IpAddr>> DualstackMeasureManyStream<'_, I> {
-    pub fn poll_next_unpin(&mut self, cx: &mut Context<'_>) -> Poll<(IpAddr, Duration)> {
-        if let Poll::Ready((v4, rtt)) = self.v4.poll_next_unpin(cx) {
-            return Poll::Ready((IpAddr::V4(v4), rtt));
+    /// Poll for the next ping response
+    ///
+    /// See [`MeasureManyStream::poll_next_unpin`].
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<(IpAddr, Duration)>>> {
+        let v4 = self.v4.poll_next_unpin(cx);
+        if let Poll::Ready(Some(result)) = v4 {
+            return Poll::Ready(Some(result.map(|(v4, rtt)| (IpAddr::V4(v4), rtt))));
         }
 
-        if let Poll::Ready((v6, rtt)) = self.v6.poll_next_unpin(cx) {
-            return Poll::Ready((IpAddr::V6(v6), rtt));
+        let v6 = self.v6.poll_next_unpin(cx);
+        if let Poll::Ready(Some(result)) = v6 {
+            return Poll::Ready(Some(result.map(|(v6, rtt)| (IpAddr::V6(v6), rtt))));
+        }
+
+        if v4.is_ready() && v6.is_ready() {
+            return Poll::Ready(None);
         }
 
         Poll::Pending
@@ -123,11 +295,55 @@ impl<I: Iterator<Item = IpAddr>> DualstackMeasureManyStream<'_, I> {
 
 #[cfg(feature = "stream")]
 impl<I: Iterator<Item = IpAddr> + Unpin> Stream for DualstackMeasureManyStream<'_, I> {
-    type Item = (IpAddr, Duration);
+    type Item = io::Result<(IpAddr, Duration)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+/// A [`Stream`] of the final outcome of every target.
+///
+/// See [`RetryMeasureManyStream`].
+///
+/// [`Stream`]: futures_core::Stream
+pub struct DualstackRetryMeasureManyStream<'a, I: Iterator<Item = IpAddr>> {
+    v4: RetryMeasureManyStream<'a, Ipv4Addr, FilterIpAddr<I, Ipv4Addr>>,
+    v6: RetryMeasureManyStream<'a, Ipv6Addr, FilterIpAddr<I, Ipv6Addr>>,
+}
+
+impl<I: Iterator<Item = IpAddr>> DualstackRetryMeasureManyStream<'_, I> {
+    /// Poll for the final outcome of the next target
+    ///
+    /// See [`RetryMeasureManyStream::poll_next_unpin`].
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<RetryResult<IpAddr>>>> {
+        let v4 = self.v4.poll_next_unpin(cx);
+        if let Poll::Ready(Some(result)) = v4 {
+            return Poll::Ready(Some(result.map(|result| result.map_addr(IpAddr::V4))));
+        }
+
+        let v6 = self.v6.poll_next_unpin(cx);
+        if let Poll::Ready(Some(result)) = v6 {
+            return Poll::Ready(Some(result.map(|result| result.map_addr(IpAddr::V6))));
+        }
+
+        if v4.is_ready() && v6.is_ready() {
+            return Poll::Ready(None);
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<I: Iterator<Item = IpAddr> + Unpin> Stream for DualstackRetryMeasureManyStream<'_, I> {
+    type Item = io::Result<RetryResult<IpAddr>>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let result = ready!(self.as_mut().poll_next_unpin(cx));
-        Poll::Ready(Some(result))
+        self.as_mut().poll_next_unpin(cx)
     }
 }
 
@@ -0,0 +1,271 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use crate::{
+    IpVersion, Pinger, RetryOptions, RetryResult,
+    retry::{AddressSource, Retrier},
+};
+
+/// A [`Stream`] of the final outcome of every target coming from another
+/// [`Stream`].
+///
+/// Targets are pinged as soon as they arrive, and the stream ends once the
+/// input ended and every target got a reply or ran out of retries. See
+/// [`RetryMeasureManyStream`] for how targets are retried.
+///
+/// [`RetryMeasureManyStream`]: crate::RetryMeasureManyStream
+pub struct RetryMeasureStream<'a, V: IpVersion, S: Stream<Item = V> + Unpin> {
+    inner: Retrier<'a, V, StreamSource<S>>,
+}
+
+/// A [`Stream`] of the final outcome of every target coming from another
+/// [`Stream`].
+///
+/// See [`RetryMeasureStream`].
+pub struct DualstackRetryMeasureStream<'a, S: Stream<Item = IpAddr> + Unpin> {
+    addresses: S,
+    exhausted: bool,
+    /// Target waiting for the pinger of its IP version to take the previous one
+    blocked_addr: Option<IpAddr>,
+    v4: Retrier<'a, Ipv4Addr, SlotSource<Ipv4Addr>>,
+    v6: Retrier<'a, Ipv6Addr, SlotSource<Ipv6Addr>>,
+}
+
+struct StreamSource<S>(S);
+
+/// Targets handed over one at a time by [`DualstackRetryMeasureStream`]
+struct SlotSource<V> {
+    slot: Option<V>,
+    closed: bool,
+}
+
+impl<'a, V: IpVersion, S: Stream<Item = V> + Unpin> RetryMeasureStream<'a, V, S> {
+    pub(crate) fn new(pinger: &'a Pinger<V>, addresses: S, options: RetryOptions) -> Self {
+        Self {
+            inner: Retrier::new(pinger, StreamSource(addresses), options),
+        }
+    }
+
+    /// Poll for the final outcome of the next target
+    ///
+    /// See [`RetryMeasureManyStream::poll_next_unpin`].
+    ///
+    /// [`RetryMeasureManyStream::poll_next_unpin`]: crate::RetryMeasureManyStream::poll_next_unpin
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<RetryResult<V>>>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl<V: IpVersion, S: Stream<Item = V> + Unpin> Stream for RetryMeasureStream<'_, V, S> {
+    type Item = io::Result<RetryResult<V>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+impl<'a, S: Stream<Item = IpAddr> + Unpin> DualstackRetryMeasureStream<'a, S> {
+    pub(crate) fn new(
+        v4: &'a Pinger<Ipv4Addr>,
+        v6: &'a Pinger<Ipv6Addr>,
+        addresses: S,
+        options: RetryOptions,
+    ) -> Self {
+        Self {
+            addresses,
+            exhausted: false,
+            blocked_addr: None,
+            v4: Retrier::new(v4, SlotSource::new(), options.clone()),
+            v6: Retrier::new(v6, SlotSource::new(), options),
+        }
+    }
+
+    /// Poll for the final outcome of the next target
+    ///
+    /// See [`RetryMeasureManyStream::poll_next_unpin`].
+    ///
+    /// [`RetryMeasureManyStream::poll_next_unpin`]: crate::RetryMeasureManyStream::poll_next_unpin
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<RetryResult<IpAddr>>>> {
+        loop {
+            self.poll_feed(cx);
+            let handed_over = self.handed_over();
+
+            let v4 = self.v4.poll_next_unpin(cx);
+            if let Poll::Ready(Some(result)) = v4 {
+                return Poll::Ready(Some(result.map(|result| result.map_addr(IpAddr::V4))));
+            }
+
+            let v6 = self.v6.poll_next_unpin(cx);
+            if let Poll::Ready(Some(result)) = v6 {
+                return Poll::Ready(Some(result.map(|result| result.map_addr(IpAddr::V6))));
+            }
+
+            if v4.is_ready() && v6.is_ready() {
+                return Poll::Ready(None);
+            }
+
+            // Keep feeding targets for as long as the pingers take them
+            if self.handed_over() == handed_over {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Hand out targets to the pinger of their IP version, until one of
+    /// them still has a target it didn't take
+    fn poll_feed(&mut self, cx: &mut Context<'_>) {
+        while !self.exhausted {
+            let addr = match self.blocked_addr.take() {
+                Some(addr) => addr,
+                None => match Pin::new(&mut self.addresses).poll_next(cx) {
+                    Poll::Ready(Some(addr)) => addr,
+                    Poll::Ready(None) => {
+                        self.exhausted = true;
+                        self.v4.source_mut().closed = true;
+                        self.v6.source_mut().closed = true;
+                        break;
+                    }
+                    Poll::Pending => break,
+                },
+            };
+
+            let accepted = match addr {
+                IpAddr::V4(v4) => self.v4.source_mut().offer(v4),
+                IpAddr::V6(v6) => self.v6.source_mut().offer(v6),
+            };
+            if !accepted {
+                self.blocked_addr = Some(addr);
+                break;
+            }
+        }
+    }
+
+    /// Number of targets handed over but not taken yet
+    fn handed_over(&mut self) -> usize {
+        usize::from(self.v4.source_mut().slot.is_some())
+            + usize::from(self.v6.source_mut().slot.is_some())
+    }
+}
+
+impl<S: Stream<Item = IpAddr> + Unpin> Stream for DualstackRetryMeasureStream<'_, S> {
+    type Item = io::Result<RetryResult<IpAddr>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+impl<V, S: Stream<Item = V> + Unpin> AddressSource<V> for StreamSource<S> {
+    fn poll_next_address(&mut self, cx: &mut Context<'_>) -> Poll<Option<V>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+impl<V> SlotSource<V> {
+    fn new() -> Self {
+        Self {
+            slot: None,
+            closed: false,
+        }
+    }
+
+    /// Hand over `addr`, unless the previous target wasn't taken yet
+    fn offer(&mut self, addr: V) -> bool {
+        if self.slot.is_some() {
+            return false;
+        }
+
+        self.slot = Some(addr);
+        true
+    }
+}
+
+impl<V> AddressSource<V> for SlotSource<V> {
+    fn poll_next_address(&mut self, _cx: &mut Context<'_>) -> Poll<Option<V>> {
+        match self.slot.take() {
+            Some(addr) => Poll::Ready(Some(addr)),
+            None if self.closed => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sim", feature = "runtime-tokio"))]
+mod tests {
+    use std::{collections::HashSet, future::poll_fn, net::Ipv4Addr, time::Duration};
+
+    use futures_util::stream;
+    use tokio::time;
+
+    use crate::{
+        PingerOptions, RetryOptions, V4Pinger,
+        sim::{SimHost, SimNetwork},
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn long_running_input() {
+        // Up targets reply in time, slow ones only to the first attempt
+        // after it timed out, and down ones never
+        let targets = (1..=30)
+            .map(|i| Ipv4Addr::new(192, 0, 2, i))
+            .collect::<Vec<_>>();
+        let network = SimNetwork::new(1);
+        for (i, &target) in targets.iter().enumerate() {
+            let latency = match i % 3 {
+                0 => Duration::from_millis(10),
+                1 => Duration::from_millis(150),
+                _ => continue,
+            };
+            network.add_host(target.into(), SimHost::new().latency(latency));
+        }
+        let (pinger, driver) = V4Pinger::with_transport(PingerOptions::new(), network.socket());
+        tokio::spawn(driver);
+
+        // A target every 50 ms, so that most of them are pinged while
+        // earlier ones are being retried
+        let input = Box::pin(stream::unfold(
+            targets.clone().into_iter(),
+            |mut targets| async {
+                time::sleep(Duration::from_millis(50)).await;
+                Some((targets.next()?, targets))
+            },
+        ));
+        let mut stream = pinger.measure_stream_with_retries(
+            input,
+            RetryOptions::new()
+                .retries(2)
+                .timeout(Duration::from_millis(100)),
+        );
+
+        let mut resolved = HashSet::new();
+        while let Some(result) = poll_fn(|cx| stream.poll_next_unpin(cx)).await {
+            let result = result.unwrap();
+            let i = targets
+                .iter()
+                .position(|&target| target == result.addr())
+                .unwrap();
+            match i % 3 {
+                0 => assert_eq!(result.rtt(), Some(Duration::from_millis(10))),
+                1 => assert_eq!(result.rtt(), Some(Duration::from_millis(150))),
+                _ => assert_eq!((result.attempt(), result.rtt()), (3, None)),
+            }
+            assert!(resolved.insert(result.addr()));
+
+            // Replies to earlier attempts aren't waited for anymore
+            assert!(!stream.inner.is_waiting_for(&result.addr()));
+        }
+        assert_eq!(resolved.len(), targets.len());
+    }
+}
@@ -0,0 +1,659 @@
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+
+use crate::{
+    DualstackPinger, IpVersion, MeasureManyStream, Pinger,
+    pinger::SendQueue,
+    report::PingRecord,
+    rt::{Instant, Sleep},
+};
+
+/// Options for [`Monitor`]
+#[derive(Debug, Clone)]
+pub struct MonitorOptions {
+    interval: Duration,
+    timeout: Duration,
+    jitter: Duration,
+}
+
+/// Pings a changing set of targets at a fixed interval.
+///
+/// Instead of pinging every target at once, the pings are spread evenly
+/// across the interval: with `n` targets, one is pinged every
+/// `interval / n`, going through them in a round-robin fashion.
+///
+/// The results of every ping are [`Stream`]ed as [`PingRecord`]s, either
+/// a [`Reply`] or a [`Timeout`]. The stream never ends, even if there are
+/// no targets left, as more can be added through [`MonitorHandle`].
+///
+/// [`Stream`]: futures_core::Stream
+/// [`Reply`]: crate::report::Outcome::Reply
+/// [`Timeout`]: crate::report::Outcome::Timeout
+pub struct Monitor<'a> {
+    options: MonitorOptions,
+    shared: Arc<Shared>,
+    /// Targets, in the order they are pinged in
+    targets: Vec<IpAddr>,
+    cursor: usize,
+    sequence_numbers: HashMap<IpAddr, u64>,
+    /// When the next slot of the schedule starts, jitter excluded
+    next_slot: Instant,
+    /// When the next ping is sent
+    next_send: Option<Instant>,
+    v4: Rounds<'a, Ipv4Addr>,
+    v6: Rounds<'a, Ipv6Addr>,
+    /// Deadline, target and sequence number of the pings sent, oldest first
+    deadlines: VecDeque<(Instant, IpAddr, u64)>,
+    timed_out: VecDeque<PingRecord>,
+    sleep: Sleep,
+}
+
+/// Adds and removes targets of a [`Monitor`].
+///
+/// Can be cloned and sent to other threads. Changes are applied the next
+/// time the [`Monitor`] is polled.
+#[derive(Clone)]
+pub struct MonitorHandle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<SharedState>,
+}
+
+#[derive(Default)]
+struct SharedState {
+    changes: Vec<Change>,
+    waker: Option<Waker>,
+}
+
+enum Change {
+    Add(IpAddr),
+    Remove(IpAddr),
+}
+
+/// The rounds of the pings sent to the targets of one IP version
+///
+/// Pings go into a single long-lived round, unless it's still waiting
+/// for the reply to the previous ping to the same target, or that ping
+/// timed out, as replies can only be told apart by their round. Other
+/// rounds are dropped once they don't wait for any reply anymore.
+struct Rounds<'a, V: IpVersion> {
+    pinger: &'a Pinger<V>,
+    /// The round pings are sent in last
+    rounds: VecDeque<Round<'a, V>>,
+}
+
+struct Round<'a, V: IpVersion> {
+    stream: MeasureManyStream<'a, V, SendQueue<V>>,
+    /// Sequence number of the pings waiting for a reply
+    pending: HashMap<V, u64>,
+    /// Targets whose ping timed out, which may still get a late reply
+    timed_out: HashSet<V>,
+}
+
+impl MonitorOptions {
+    /// Construct the default `MonitorOptions`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how often each target is pinged.
+    ///
+    /// Defaults to 1 second.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set how long to wait for a reply before reporting a timeout.
+    ///
+    /// Defaults to 1 second.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Delay each ping by a random duration up to `jitter`, so that
+    /// pings from different monitors don't line up.
+    ///
+    /// Defaults to no jitter.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl Default for MonitorOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            jitter: Duration::ZERO,
+        }
+    }
+}
+
+impl<'a> Monitor<'a> {
+    /// Construct a new `Monitor` pinging `targets` through `pinger`
+    ///
+    /// Duplicate targets are only pinged once.
+    pub fn new<I>(
+        pinger: &'a DualstackPinger,
+        targets: I,
+        options: MonitorOptions,
+    ) -> (Self, MonitorHandle)
+    where
+        I: IntoIterator<Item = IpAddr>,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(SharedState::default()),
+        });
+        let now = Instant::now();
+
+        let mut monitor = Self {
+            options,
+            shared: Arc::clone(&shared),
+            targets: Vec::new(),
+            cursor: 0,
+            sequence_numbers: HashMap::new(),
+            next_slot: now,
+            next_send: None,
+            v4: Rounds::new(&pinger.v4),
+            v6: Rounds::new(&pinger.v6),
+            deadlines: VecDeque::new(),
+            timed_out: VecDeque::new(),
+            sleep: Sleep::new(now),
+        };
+        for target in targets {
+            monitor.add(target);
+        }
+
+        (monitor, MonitorHandle { shared })
+    }
+
+    /// Get the targets currently being pinged
+    pub fn targets(&self) -> &[IpAddr] {
+        &self.targets
+    }
+
+    /// Poll for the next ping result
+    ///
+    /// An error is returned if receiving the replies fails.
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<PingRecord>>> {
+        self.apply_changes(cx);
+
+        loop {
+            self.poll_send();
+
+            match self.poll_in_flight(cx) {
+                Poll::Ready(result) => return Poll::Ready(Some(result)),
+                Poll::Pending => {}
+            }
+
+            let next_deadline = self.deadlines.front().map(|&(deadline, _, _)| deadline);
+            let wake_at = match (self.next_send, next_deadline) {
+                (None, None) => return Poll::Pending,
+                (Some(send_at), None) => send_at,
+                (None, Some(deadline)) => deadline,
+                (Some(send_at), Some(deadline)) => send_at.min(deadline),
+            };
+
+            self.sleep.reset(wake_at);
+            if self.sleep.poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Send the pings that are due
+    fn poll_send(&mut self) {
+        let now = Instant::now();
+
+        while !self.targets.is_empty() {
+            let send_at = *self
+                .next_send
+                .get_or_insert_with(|| self.next_slot + random_jitter(self.options.jitter));
+            if send_at > now {
+                break;
+            }
+
+            // Picked only now, so that targets added in the meantime get their turn
+            let target = self.targets[self.cursor];
+            self.cursor = (self.cursor + 1) % self.targets.len();
+
+            let sequence_number = self.sequence_numbers.entry(target).or_default();
+            match target {
+                IpAddr::V4(v4) => self.v4.send(v4, *sequence_number),
+                IpAddr::V6(v6) => self.v6.send(v6, *sequence_number),
+            }
+            self.deadlines
+                .push_back((now + self.options.timeout, target, *sequence_number));
+            *sequence_number += 1;
+            self.next_send = None;
+
+            // Don't try to catch up on the slots missed while the stream wasn't polled
+            let slot = self.slot();
+            self.next_slot = (self.next_slot + slot).max(now.checked_sub(slot).unwrap_or(now));
+        }
+    }
+
+    /// Poll the pings waiting for a reply, moving the expired ones into `timed_out`
+    fn poll_in_flight(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<PingRecord>> {
+        if let Poll::Ready(result) = self.v4.poll_reply(cx) {
+            return Poll::Ready(result.map(|(addr, sequence_number, rtt)| {
+                PingRecord::reply(addr.into(), sequence_number, rtt)
+            }));
+        }
+        if let Poll::Ready(result) = self.v6.poll_reply(cx) {
+            return Poll::Ready(result.map(|(addr, sequence_number, rtt)| {
+                PingRecord::reply(addr.into(), sequence_number, rtt)
+            }));
+        }
+
+        let now = Instant::now();
+        while let Some(&(deadline, target, sequence_number)) = self.deadlines.front() {
+            if deadline > now {
+                break;
+            }
+
+            self.deadlines.pop_front();
+            // The ping may have got a reply or its target may have been removed since
+            let timed_out = match target {
+                IpAddr::V4(v4) => self.v4.time_out(v4, sequence_number),
+                IpAddr::V6(v6) => self.v6.time_out(v6, sequence_number),
+            };
+            if timed_out {
+                self.timed_out
+                    .push_back(PingRecord::timeout(target, sequence_number));
+            }
+        }
+
+        match self.timed_out.pop_front() {
+            Some(record) => Poll::Ready(Ok(record)),
+            None => Poll::Pending,
+        }
+    }
+
+    fn apply_changes(&mut self, cx: &mut Context<'_>) {
+        let changes = {
+            let mut state = self.shared.lock();
+            if !state
+                .waker
+                .as_ref()
+                .is_some_and(|waker| waker.will_wake(cx.waker()))
+            {
+                state.waker = Some(cx.waker().clone());
+            }
+            std::mem::take(&mut state.changes)
+        };
+
+        for change in changes {
+            match change {
+                Change::Add(target) => self.add(target),
+                Change::Remove(target) => self.remove(target),
+            }
+        }
+    }
+
+    fn add(&mut self, target: IpAddr) {
+        if self.targets.contains(&target) {
+            return;
+        }
+
+        if self.targets.is_empty() {
+            self.next_slot = Instant::now();
+        }
+        self.targets.push(target);
+    }
+
+    fn remove(&mut self, target: IpAddr) {
+        let Some(index) = self.targets.iter().position(|&t| t == target) else {
+            return;
+        };
+
+        self.targets.remove(index);
+        if index < self.cursor {
+            self.cursor -= 1;
+        }
+        if self.cursor >= self.targets.len() {
+            self.cursor = 0;
+        }
+        if self.targets.is_empty() {
+            self.next_send = None;
+        }
+
+        self.sequence_numbers.remove(&target);
+        match target {
+            IpAddr::V4(v4) => self.v4.remove(v4),
+            IpAddr::V6(v6) => self.v6.remove(v6),
+        }
+        self.timed_out.retain(|record| record.target() != target);
+    }
+
+    /// Time between two consecutive pings
+    fn slot(&self) -> Duration {
+        let targets = u32::try_from(self.targets.len()).unwrap_or(u32::MAX);
+        self.options.interval / targets.max(1)
+    }
+}
+
+#[cfg(feature = "stream")]
+impl Stream for Monitor<'_> {
+    type Item = io::Result<PingRecord>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+impl<'a, V: IpVersion> Rounds<'a, V> {
+    fn new(pinger: &'a Pinger<V>) -> Self {
+        Self {
+            pinger,
+            rounds: VecDeque::new(),
+        }
+    }
+
+    /// Queue a ping to `target`, sent the next time the rounds are polled
+    fn send(&mut self, target: V, sequence_number: u64) {
+        let round = match self.rounds.back_mut() {
+            Some(round)
+                if !round.pending.contains_key(&target) && !round.timed_out.contains(&target) =>
+            {
+                round
+            }
+            _ => {
+                self.rounds.push_back(Round {
+                    stream: self.pinger.measure_many(SendQueue(VecDeque::new())),
+                    pending: HashMap::new(),
+                    timed_out: HashSet::new(),
+                });
+                self.rounds.back_mut().unwrap()
+            }
+        };
+
+        round.stream.send_queue_mut().0.push_back(target);
+        round.pending.insert(target, sequence_number);
+    }
+
+    /// Poll for the next reply, returning its source, sequence number and round trip time
+    fn poll_reply(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<(V, u64, Duration)>> {
+        for round in &mut self.rounds {
+            loop {
+                match round.stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok((addr, rtt)))) => {
+                        if let Some(sequence_number) = round.pending.remove(&addr) {
+                            return Poll::Ready(Ok((addr, sequence_number, rtt)));
+                        }
+                    }
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+                    Poll::Ready(None) | Poll::Pending => break,
+                }
+            }
+        }
+
+        // Drop the rounds which are done, except the one pings are sent in
+        if let Some(current) = self.rounds.pop_back() {
+            self.rounds.retain(|round| !round.pending.is_empty());
+            self.rounds.push_back(current);
+        }
+        Poll::Pending
+    }
+
+    /// Stop waiting for the reply to the ping to `target` with `sequence_number`
+    ///
+    /// Returns whether the ping was still waiting for a reply.
+    fn time_out(&mut self, target: V, sequence_number: u64) -> bool {
+        for round in &mut self.rounds {
+            if round.pending.get(&target) == Some(&sequence_number) {
+                round.pending.remove(&target);
+                round.stream.forget(&target);
+                round.timed_out.insert(target);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Stop waiting for the replies from `target`
+    fn remove(&mut self, target: V) {
+        for round in &mut self.rounds {
+            if round.pending.remove(&target).is_some() {
+                round.stream.forget(&target);
+                round
+                    .stream
+                    .send_queue_mut()
+                    .0
+                    .retain(|&addr| addr != target);
+            }
+        }
+    }
+}
+
+impl MonitorHandle {
+    /// Start pinging `target`, unless it's already being pinged
+    pub fn add(&self, target: IpAddr) {
+        self.change(Change::Add(target));
+    }
+
+    /// Stop pinging `target`
+    ///
+    /// The results of pings to `target` still waiting for a reply are discarded.
+    pub fn remove(&self, target: IpAddr) {
+        self.change(Change::Remove(target));
+    }
+
+    fn change(&self, change: Change) {
+        let mut state = self.shared.lock();
+        state.changes.push(change);
+        if let Some(waker) = &state.waker {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, SharedState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn random_jitter(jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return Duration::ZERO;
+    }
+
+    let nanos = u64::try_from(jitter.as_nanos()).unwrap_or(u64::MAX);
+    Duration::from_nanos(rand::random_range(0..=nanos))
+}
+
+#[cfg(all(test, feature = "sim", feature = "runtime-tokio"))]
+mod tests {
+    use std::{
+        future::poll_fn,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        time::Duration,
+    };
+
+    use tokio::time::Instant;
+
+    use super::{Monitor, MonitorOptions};
+    use crate::{
+        DualstackPinger, PingerOptions, V4Pinger, V6Pinger,
+        report::{Outcome, PingRecord},
+        sim::{SimHost, SimNetwork},
+    };
+
+    const V4: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const V4_OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+    const V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+    const LOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 99));
+
+    fn pinger(network: &SimNetwork) -> DualstackPinger {
+        let (v4, v4_driver) = V4Pinger::with_transport(PingerOptions::new(), network.socket());
+        let (v6, v6_driver) = V6Pinger::with_transport(PingerOptions::new(), network.socket());
+        tokio::spawn(v4_driver);
+        tokio::spawn(v6_driver);
+        DualstackPinger { v4, v6 }
+    }
+
+    fn network(latency: Duration) -> SimNetwork {
+        let network = SimNetwork::new(1);
+        for target in [V4, V4_OTHER, V6] {
+            network.add_host(target, SimHost::new().latency(latency));
+        }
+        network
+    }
+
+    /// Get the next record, along with when it was received since `start`
+    async fn next(monitor: &mut Monitor<'_>, start: Instant) -> (PingRecord, Duration) {
+        let record = poll_fn(|cx| monitor.poll_next_unpin(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        (record, start.elapsed())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spread_evenly() {
+        let network = network(Duration::ZERO);
+        let pinger = pinger(&network);
+        let start = Instant::now();
+        let (mut monitor, _handle) = Monitor::new(
+            &pinger,
+            [V4, V6, V4_OTHER, LOST],
+            MonitorOptions::new()
+                .interval(Duration::from_secs(1))
+                .timeout(Duration::from_millis(500)),
+        );
+
+        let mut records = Vec::new();
+        for _ in 0..12 {
+            let (record, at) = next(&mut monitor, start).await;
+            records.push((
+                record.target(),
+                record.sequence_number(),
+                record.outcome(),
+                at.as_millis(),
+            ));
+        }
+
+        let mut expected = Vec::new();
+        for round in 0..4 {
+            let start = round * 1000;
+            expected.extend([
+                (V4, round, Outcome::Reply, u128::from(start)),
+                (V6, round, Outcome::Reply, u128::from(start + 250)),
+                (V4_OTHER, round, Outcome::Reply, u128::from(start + 500)),
+                (LOST, round, Outcome::Timeout, u128::from(start + 750 + 500)),
+            ]);
+        }
+        expected.sort_by_key(|&(_, _, _, at)| at);
+        expected.truncate(records.len());
+        assert_eq!(records, expected);
+
+        // Every ping went through the same round
+        assert_eq!(monitor.v4.rounds.len(), 1);
+        assert_eq!(monitor.v6.rounds.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn add_and_remove() {
+        let network = network(Duration::from_millis(10));
+        let pinger = pinger(&network);
+        let start = Instant::now();
+        let (mut monitor, handle) = Monitor::new(
+            &pinger,
+            [V4],
+            MonitorOptions::new().interval(Duration::from_secs(1)),
+        );
+
+        let (record, at) = next(&mut monitor, start).await;
+        assert_eq!((record.target(), at), (V4, Duration::from_millis(10)));
+
+        handle.add(V6);
+        handle.add(V4);
+        // The ping to V4 was already scheduled a whole interval later, as it was alone
+        let (record, at) = next(&mut monitor, start).await;
+        assert_eq!((record.target(), at), (V4, Duration::from_millis(1010)));
+        assert_eq!(monitor.targets(), [V4, V6]);
+        let (record, at) = next(&mut monitor, start).await;
+        assert_eq!((record.target(), at), (V6, Duration::from_millis(1510)));
+        let (record, at) = next(&mut monitor, start).await;
+        assert_eq!((record.target(), at), (V4, Duration::from_millis(2010)));
+
+        handle.remove(V4);
+        let (record, at) = next(&mut monitor, start).await;
+        assert_eq!((record.target(), at), (V6, Duration::from_millis(2510)));
+        assert_eq!(monitor.targets(), [V6]);
+        let (record, at) = next(&mut monitor, start).await;
+        assert_eq!((record.target(), at), (V6, Duration::from_millis(3510)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn overlapping_pings() {
+        // Every target has two pings waiting for a reply at once
+        let network = network(Duration::from_millis(1500));
+        let pinger = pinger(&network);
+        let start = Instant::now();
+        let (mut monitor, _handle) = Monitor::new(
+            &pinger,
+            [V4],
+            MonitorOptions::new()
+                .interval(Duration::from_secs(1))
+                .timeout(Duration::from_secs(2)),
+        );
+
+        for sequence_number in 0..5 {
+            let (record, at) = next(&mut monitor, start).await;
+            assert_eq!(record.sequence_number(), sequence_number);
+            assert_eq!(record.rtt(), Some(Duration::from_millis(1500)));
+            assert_eq!(
+                at,
+                Duration::from_millis(1500) + Duration::from_secs(sequence_number)
+            );
+            assert!(monitor.v4.rounds.len() <= 2);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn late_replies() {
+        // Replies arrive after the ping timed out, while the next one is in flight
+        let network = network(Duration::from_millis(1300));
+        let pinger = pinger(&network);
+        let start = Instant::now();
+        let (mut monitor, _handle) = Monitor::new(
+            &pinger,
+            [V4],
+            MonitorOptions::new()
+                .interval(Duration::from_secs(1))
+                .timeout(Duration::from_millis(900)),
+        );
+
+        for sequence_number in 0..5 {
+            let (record, at) = next(&mut monitor, start).await;
+            assert_eq!(record.sequence_number(), sequence_number);
+            assert_eq!(record.outcome(), Outcome::Timeout);
+            assert_eq!(
+                at,
+                Duration::from_millis(900) + Duration::from_secs(sequence_number)
+            );
+            assert!(monitor.v4.rounds.len() <= 2);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Serialization of [`Duration`]s and [`SystemTime`]s as nanoseconds,
+//! as described in the [crate documentation](crate#serialization)
+//!
+//! [`SystemTime`]: std::time::SystemTime
+
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser::Error};
+
+/// Serialize a [`Duration`] as a number of nanoseconds
+pub(crate) fn serialize<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    nanos(duration)?.serialize(serializer)
+}
+
+fn nanos<E: Error>(duration: &Duration) -> Result<u64, E> {
+    u64::try_from(duration.as_nanos())
+        .map_err(|_| E::custom("duration too long to be represented in nanoseconds"))
+}
+
+/// Deserialize a [`Duration`] from a number of nanoseconds
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_nanos)
+}
+
+/// Serialization of optional [`Duration`]s as nanoseconds
+pub(crate) mod option {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_some(&super::nanos::<S::Error>(duration)?),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<u64>::deserialize(deserializer).map(|nanos| nanos.map(Duration::from_nanos))
+    }
+}
+
+/// Serialization of [`SystemTime`]s as nanoseconds since the Unix epoch
+pub(crate) mod system_time {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserializer, Serializer, de::Error as _, ser::Error as _};
+
+    pub(crate) fn serialize<S: Serializer>(
+        time: &SystemTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let since_epoch = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| S::Error::custom("time before the Unix epoch"))?;
+        super::serialize(&since_epoch, serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SystemTime, D::Error> {
+        let since_epoch = super::deserialize(deserializer)?;
+        UNIX_EPOCH
+            .checked_add(since_epoch)
+            .ok_or_else(|| D::Error::custom("time out of range"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt::Debug,
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use serde::{Serialize, de::DeserializeOwned};
+
+    use crate::{
+        aggregate::RttHistogram,
+        quality::QualityStats,
+        reachability::{ReachabilityOptions, ReachabilityTracker},
+        report::{PingRecord, SummaryRecord},
+    };
+
+    const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    /// Round trip `value` through bincode, which unlike JSON doesn't
+    /// describe the types it encodes
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+        let bytes = bincode::serialize(value).unwrap();
+        assert_eq!(&bincode::deserialize::<T>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn records() {
+        let timestamp = UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789);
+        let reply =
+            PingRecord::reply(TARGET, 1, Duration::from_micros(1500)).with_timestamp(timestamp);
+        let timeout = PingRecord::timeout(TARGET, 2).with_timestamp(timestamp);
+        round_trip(&reply);
+        round_trip(&timeout);
+
+        let mut summary = SummaryRecord::new(TARGET);
+        round_trip(&summary);
+        summary.add(&reply);
+        summary.add(&timeout);
+        round_trip(&summary);
+    }
+
+    #[test]
+    fn statistics() {
+        let mut histogram = RttHistogram::new();
+        round_trip(&histogram);
+        let mut quality = QualityStats::new();
+        round_trip(&quality);
+
+        for millis in [12, 10, 250, 11] {
+            histogram.record(Duration::from_millis(millis));
+            quality.add(Some(Duration::from_millis(millis)));
+        }
+        quality.add(None);
+        round_trip(&histogram);
+        round_trip(&histogram.percentiles().unwrap());
+        round_trip(&quality);
+
+        let mut tracker = ReachabilityTracker::new(ReachabilityOptions::new().up_after(1));
+        let transition = tracker
+            .observe_outcome(TARGET, true, UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+        round_trip(&transition);
+    }
+}
@@ -2,35 +2,248 @@
 //!
 //! [`raw_pinger`]: crate::raw_pinger
 
-use std::marker::PhantomData;
+use std::{
+    fmt,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
 
 use bytes::{Bytes, BytesMut};
 use pnet_packet::{
-    Packet as _,
-    icmp::{IcmpPacket, IcmpTypes},
-    icmpv6::{Icmpv6Packet, Icmpv6Types},
-    util,
+    Packet as _, icmp::IcmpTypes, icmpv6::Icmpv6Types, ip::IpNextHeaderProtocols, ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet, util,
 };
 
 use crate::IpVersion;
 
+/// Length of an ICMP timestamp message
+const TIMESTAMP_LEN: usize = 20;
+/// ICMP types of the extended echo messages, as described by RFC 8335
+const EXTENDED_ECHO_REQUEST_V4: u8 = 42;
+const EXTENDED_ECHO_REPLY_V4: u8 = 43;
+const EXTENDED_ECHO_REQUEST_V6: u8 = 160;
+const EXTENDED_ECHO_REPLY_V6: u8 = 161;
+/// Class-Num of the Interface Identification Object
+const INTERFACE_IDENTIFICATION_CLASS: u8 = 3;
+
 /// An ICMP echo request packet
 pub struct EchoRequestPacket<V: IpVersion> {
     buf: Bytes,
     _version: PhantomData<V>,
 }
 
+/// A reusable ICMP echo request packet
+///
+/// Unlike [`EchoRequestPacket`], the sequence number and the payload can
+/// be modified after the packet has been built. Only the modified bytes
+/// are folded into the checksum, as described by RFC 1624, so a template
+/// can be reused for many packets without recomputing the whole checksum
+/// or allocating.
+pub struct EchoRequestTemplate<V: IpVersion> {
+    buf: Box<[u8]>,
+    /// Whether the checksum is left as zero for the kernel to fill in
+    kernel_checksum: bool,
+    _version: PhantomData<V>,
+}
+
 /// An ICMP echo reply packet
 pub struct EchoReplyPacket<V: IpVersion> {
     source: V,
     identifier: u16,
     sequence_number: u16,
-    payload: Bytes,
+    buf: Bytes,
+}
+
+/// The header fields of an [`EchoReplyPacket`], without its payload
+///
+/// Obtained through [`EchoReplyPacket::metadata`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EchoReplyMetadata<V: IpVersion> {
+    /// The source IP address
+    pub source: V,
+    /// The ICMP packet identifier
+    pub identifier: u16,
+    /// The ICMP packet sequence number
+    pub sequence_number: u16,
+    /// The length of the ICMP packet payload
+    pub payload_len: usize,
+}
+
+/// An ICMP error message sent in response to an echo request
+///
+/// The echo request which caused the error is recovered from the
+/// original IP and ICMP header embedded into the message, so that it
+/// can be matched back to its identifier and sequence number.
+#[derive(Debug, Clone)]
+pub struct IcmpErrorPacket<V: IpVersion> {
+    source: V,
+    kind: IcmpErrorKind<V>,
+    destination: V,
+    identifier: u16,
+    sequence_number: u16,
+}
+
+/// The type of an [`IcmpErrorPacket`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IcmpErrorKind<V: IpVersion> {
+    /// Destination Unreachable, with its code
+    ///
+    /// The "fragmentation needed" code of IPv4 is reported
+    /// as [`PacketTooBig`](Self::PacketTooBig) instead.
+    DestinationUnreachable { code: u8 },
+    /// Time Exceeded, with its code
+    TimeExceeded { code: u8 },
+    /// Parameter Problem, with its code and the offset
+    /// of the octet of the original packet that caused it
+    ParameterProblem { code: u8, pointer: u32 },
+    /// Packet Too Big for IPv6, or Destination Unreachable with the
+    /// "fragmentation needed" code for IPv4, with the MTU of the next hop
+    ///
+    /// For IPv4 the MTU is 0 if the message was sent by a
+    /// router that doesn't implement RFC 1191.
+    PacketTooBig { mtu: u32 },
+    /// Redirect, with its code and the gateway to send packets to instead
+    Redirect { code: u8, gateway: V },
+}
+
+/// An ICMP timestamp request packet, which only exists for IPv4
+///
+/// Timestamps are expressed in milliseconds since midnight UT, as described
+/// by RFC 792. A timestamp with the most significant bit set isn't expressed
+/// in standard units.
+pub struct TimestampRequestPacket {
+    buf: Bytes,
+}
+
+/// An ICMP timestamp reply packet, which only exists for IPv4
+///
+/// See [`TimestampRequestPacket`] for how timestamps are expressed.
+#[derive(Debug, Clone)]
+pub struct TimestampReplyPacket {
+    source: Ipv4Addr,
+    identifier: u16,
+    sequence_number: u16,
+    originate: u32,
+    receive: u32,
+    transmit: u32,
+}
+
+/// An ICMP extended echo request packet, as described by RFC 8335
+///
+/// Asks the node it's sent to, acting as a proxy, about the status
+/// of one of its interfaces or of one of its neighbors.
+pub struct ExtendedEchoRequestPacket<V: IpVersion> {
+    buf: Bytes,
+    query: InterfaceQuery,
+    _version: PhantomData<V>,
+}
+
+/// An ICMP extended echo reply packet, as described by RFC 8335
+#[derive(Debug, Clone)]
+pub struct ExtendedEchoReplyPacket<V: IpVersion> {
+    source: V,
+    identifier: u16,
+    sequence_number: u8,
+    code: u8,
+    flags: u8,
+}
+
+/// The interface an [`ExtendedEchoRequestPacket`] asks about
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InterfaceQuery {
+    /// The interface of the proxy node with the given name
+    Name(String),
+    /// The interface of the proxy node with the given index
+    Index(u32),
+    /// The interface of the proxy node with the given address
+    Address(IpAddr),
+    /// The interface with the given address of a node directly connected to the proxy node
+    Neighbor(IpAddr),
+}
+
+/// The status of an interface, as reported by an [`ExtendedEchoReplyPacket`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterfaceStatus {
+    flags: u8,
+}
+
+/// Why a proxy node couldn't answer an [`ExtendedEchoRequestPacket`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProbeError {
+    /// The request was malformed
+    MalformedQuery,
+    /// The proxy node has no interface matching the query
+    NoSuchInterface,
+    /// The neighbor isn't in the ARP or Neighbor Discovery table of the proxy node
+    NoSuchTableEntry,
+    /// Multiple interfaces of the proxy node match the query
+    MultipleInterfacesSatisfyQuery,
+    /// A code not described by RFC 8335
+    Other(u8),
+}
+
+/// An error returned when parsing an ICMP packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The packet is shorter than its headers
+    Truncated,
+    /// The packet has a different ICMP type or code than the one expected
+    BadType { icmp_type: u8, code: u8 },
+    /// The checksum doesn't match the contents of the packet
+    BadChecksum,
+    /// The packet embedded into an ICMP error message isn't an ICMP echo request
+    BadOriginal,
+    /// The ICMP extension structure isn't valid or doesn't contain the expected object
+    BadExtension,
+}
+
+/// An error returned when writing an ICMP packet into a buffer shorter than it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BufferTooShort {
+    /// The length of the packet
+    pub required: usize,
 }
 
 impl<V: IpVersion> EchoRequestPacket<V> {
     /// Build a new ICMP echo request packet
+    ///
+    /// For IPv6 the checksum covers the addresses of the IPv6 header, so it's
+    /// left as zero for the kernel to fill in. Use [`with_addresses`] for
+    /// building an IPv6 packet to be sent through a raw socket.
+    ///
+    /// [`with_addresses`]: Self::with_addresses
     pub fn new(identifier: u16, sequence_number: u16, payload: &[u8]) -> Self {
+        Self::build(identifier, sequence_number, payload, None)
+    }
+
+    /// Build a new ICMP echo request packet to be sent from `source` to `destination`
+    ///
+    /// Unlike [`new`](Self::new), the checksum is always complete.
+    pub fn with_addresses(
+        source: V,
+        destination: V,
+        identifier: u16,
+        sequence_number: u16,
+        payload: &[u8],
+    ) -> Self {
+        Self::build(
+            identifier,
+            sequence_number,
+            payload,
+            Some((source, destination)),
+        )
+    }
+
+    fn build(
+        identifier: u16,
+        sequence_number: u16,
+        payload: &[u8],
+        addresses: Option<(V, V)>,
+    ) -> Self {
         if V::IS_V4 {
             use pnet_packet::icmp::echo_request::MutableEchoRequestPacket;
 
@@ -41,7 +254,7 @@ impl<V: IpVersion> EchoRequestPacket<V> {
             packet.set_identifier(identifier);
             packet.set_sequence_number(sequence_number);
             packet.set_payload(payload);
-            packet.set_checksum(util::checksum(packet.packet(), 1));
+            packet.set_checksum(checksum(packet.packet(), addresses));
 
             let packet_len = packet.packet().len();
             debug_assert_eq!(buf.len(), packet_len);
@@ -57,7 +270,7 @@ impl<V: IpVersion> EchoRequestPacket<V> {
             packet.set_identifier(identifier);
             packet.set_sequence_number(sequence_number);
             packet.set_payload(payload);
-            packet.set_checksum(util::checksum(packet.packet(), 1));
+            packet.set_checksum(checksum(packet.packet(), addresses));
 
             let packet_len = packet.packet().len();
             debug_assert_eq!(buf.len(), packet_len);
@@ -73,45 +286,391 @@ impl<V: IpVersion> EchoRequestPacket<V> {
         }
     }
 
-    pub(crate) fn as_bytes(&self) -> &[u8] {
+    /// Parse an ICMP echo request packet
+    ///
+    /// The checksum is only verified for IPv4, as for IPv6
+    /// it also covers the addresses of the IPv6 header.
+    pub fn parse(buf: &[u8]) -> Result<Self, ParseError> {
+        let request_type = if V::IS_V4 {
+            IcmpTypes::EchoRequest.0
+        } else {
+            Icmpv6Types::EchoRequest.0
+        };
+        parse_query::<V>(buf, request_type)?;
+
+        Ok(Self::from_buf(Bytes::copy_from_slice(buf)))
+    }
+
+    /// Get the ICMP packet identifier
+    pub fn identifier(&self) -> u16 {
+        u16::from_be_bytes([self.buf[4], self.buf[5]])
+    }
+
+    /// Get the ICMP packet sequence number
+    pub fn sequence_number(&self) -> u16 {
+        u16::from_be_bytes([self.buf[6], self.buf[7]])
+    }
+
+    /// Get the ICMP packet payload
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[8..]
+    }
+
+    /// Get the length of the packet in bytes
+    #[expect(clippy::len_without_is_empty, reason = "packets are never empty")]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Get the bytes of the packet
+    pub fn as_bytes(&self) -> &[u8] {
         &self.buf
     }
+
+    /// Write the packet into the beginning of `buf`, returning its length
+    ///
+    /// Fails without writing anything if `buf` is shorter than [`len`](Self::len).
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, BufferTooShort> {
+        write_packet(&self.buf, buf)
+    }
+
+    /// Check the checksum of the packet, as if it was sent from `source` to `destination`
+    pub fn verify_checksum(&self, source: V, destination: V) -> bool {
+        verify_checksum(&self.buf, source, destination)
+    }
 }
 
-impl<V: IpVersion> EchoReplyPacket<V> {
-    /// Parse an ICMP echo reply packet
-    pub(crate) fn from_reply(source: V, buf: Bytes) -> Option<Self> {
-        if V::IS_V4 {
-            if let Some(icmp_packet) = IcmpPacket::new(&buf) {
-                if icmp_packet.get_icmp_type() == IcmpTypes::EchoReply {
-                    use pnet_packet::icmp::echo_reply::EchoReplyPacket;
-
-                    if let Some(echo_reply_packet) = EchoReplyPacket::new(&buf) {
-                        return Some(Self {
-                            source,
-                            identifier: echo_reply_packet.get_identifier(),
-                            sequence_number: echo_reply_packet.get_sequence_number(),
-                            payload: buf.slice_ref(echo_reply_packet.payload()),
-                        });
-                    }
-                }
+impl<V: IpVersion> EchoRequestTemplate<V> {
+    /// Build a new ICMP echo request template
+    ///
+    /// The checksum is computed the same way as [`EchoRequestPacket::new`].
+    /// For IPv6 it's left as zero, and stays so when the template is modified.
+    pub fn new(identifier: u16, sequence_number: u16, payload: &[u8]) -> Self {
+        Self::from_packet(
+            &EchoRequestPacket::new(identifier, sequence_number, payload),
+            !V::IS_V4,
+        )
+    }
+
+    /// Build a new ICMP echo request template for packets
+    /// sent from `source` to `destination`
+    ///
+    /// The checksum is computed the same way as [`EchoRequestPacket::with_addresses`].
+    pub fn with_addresses(
+        source: V,
+        destination: V,
+        identifier: u16,
+        sequence_number: u16,
+        payload: &[u8],
+    ) -> Self {
+        Self::from_packet(
+            &EchoRequestPacket::with_addresses(
+                source,
+                destination,
+                identifier,
+                sequence_number,
+                payload,
+            ),
+            false,
+        )
+    }
+
+    fn from_packet(packet: &EchoRequestPacket<V>, kernel_checksum: bool) -> Self {
+        Self {
+            buf: Box::from(packet.as_bytes()),
+            kernel_checksum,
+            _version: PhantomData,
+        }
+    }
+
+    /// Get the ICMP packet sequence number
+    pub fn sequence_number(&self) -> u16 {
+        u16::from_be_bytes([self.buf[6], self.buf[7]])
+    }
+
+    /// Set the ICMP packet sequence number
+    pub fn set_sequence_number(&mut self, sequence_number: u16) {
+        self.patch(6, &sequence_number.to_be_bytes());
+    }
+
+    /// Get the ICMP packet payload
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[8..]
+    }
+
+    /// Overwrite the payload starting at `offset` with `bytes`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset + bytes.len()` is greater than the payload length.
+    pub fn patch_payload(&mut self, offset: usize, bytes: &[u8]) {
+        assert!(
+            offset + bytes.len() <= self.payload().len(),
+            "patch out of payload bounds"
+        );
+
+        self.patch(8 + offset, bytes);
+    }
+
+    /// Get the length of the packet in bytes
+    #[expect(clippy::len_without_is_empty, reason = "packets are never empty")]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Get the bytes of the packet
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Write the packet into the beginning of `buf`, returning its length
+    ///
+    /// Fails without writing anything if `buf` is shorter than [`len`](Self::len).
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, BufferTooShort> {
+        write_packet(&self.buf, buf)
+    }
+
+    fn patch(&mut self, offset: usize, bytes: &[u8]) {
+        if self.kernel_checksum {
+            self.buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+            return;
+        }
+
+        // Checksum the whole 16 bit words touched by the patch
+        let start = offset & !1;
+        let end = offset + bytes.len();
+
+        let old = ones_complement_sum(&self.buf[start..end]);
+        self.buf[offset..end].copy_from_slice(bytes);
+        let new = ones_complement_sum(&self.buf[start..end]);
+
+        // RFC 1624, eqn. 3: HC' = ~(~HC + ~m + m')
+        let checksum = u16::from_be_bytes([self.buf[2], self.buf[3]]);
+        let checksum = !fold(u32::from(!checksum) + u32::from(!old) + u32::from(new));
+        self.buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+}
+
+/// Compute the checksum of an `ICMPv6` message sent from `source` to `destination`
+///
+/// The checksum field of `packet` is skipped.
+pub fn icmpv6_checksum(packet: &[u8], source: Ipv6Addr, destination: Ipv6Addr) -> u16 {
+    util::ipv6_checksum(
+        packet,
+        1,
+        &[],
+        &source,
+        &destination,
+        IpNextHeaderProtocols::Icmpv6,
+    )
+}
+
+/// Compute the checksum of the ICMP message `packet`
+///
+/// For IPv6 the checksum can only be computed if
+/// `addresses` is set, otherwise zero is returned.
+fn checksum<V: IpVersion>(packet: &[u8], addresses: Option<(V, V)>) -> u16 {
+    if V::IS_V4 {
+        return util::checksum(packet, 1);
+    }
+
+    match addresses.map(|(source, destination)| (source.into(), destination.into())) {
+        Some((IpAddr::V6(source), IpAddr::V6(destination))) => {
+            icmpv6_checksum(packet, source, destination)
+        }
+        _ => 0,
+    }
+}
+
+/// Copy `packet` into the beginning of `buf`, returning its length
+fn write_packet(packet: &[u8], buf: &mut [u8]) -> Result<usize, BufferTooShort> {
+    let buf = buf.get_mut(..packet.len()).ok_or(BufferTooShort {
+        required: packet.len(),
+    })?;
+    buf.copy_from_slice(packet);
+    Ok(packet.len())
+}
+
+fn verify_checksum<V: IpVersion>(packet: &[u8], source: V, destination: V) -> bool {
+    let expected = checksum(packet, Some((source, destination)));
+    u16::from_be_bytes([packet[2], packet[3]]) == expected
+}
+
+/// Check the header of the ICMP query message `buf` (echo or timestamp),
+/// returning its identifier and sequence number
+fn parse_query<V: IpVersion>(buf: &[u8], expected_type: u8) -> Result<(u16, u16), ParseError> {
+    let [icmp_type, code, _, _, a, b, c, d] = *buf.get(..8).ok_or(ParseError::Truncated)? else {
+        unreachable!()
+    };
+    if icmp_type != expected_type || code != 0 {
+        return Err(ParseError::BadType { icmp_type, code });
+    }
+    if V::IS_V4 && ones_complement_sum(buf) != 0xffff {
+        return Err(ParseError::BadChecksum);
+    }
+
+    Ok((u16::from_be_bytes([a, b]), u16::from_be_bytes([c, d])))
+}
+
+/// Check the header and the length of the ICMP timestamp message `buf`,
+/// returning its identifier and sequence number
+fn parse_timestamp(buf: &[u8], expected_type: u8) -> Result<(u16, u16), ParseError> {
+    let header = parse_query::<Ipv4Addr>(buf, expected_type)?;
+    if buf.len() < TIMESTAMP_LEN {
+        return Err(ParseError::Truncated);
+    }
+
+    Ok(header)
+}
+
+/// Parse the Interface Identification Object out of an RFC 4884 extension structure
+fn parse_interface_identification(
+    extension: &[u8],
+    local: bool,
+) -> Result<InterfaceQuery, ParseError> {
+    let [version, _, _, _, a, b, class, c_type] =
+        *extension.get(..8).ok_or(ParseError::Truncated)?
+    else {
+        unreachable!()
+    };
+    if version >> 4 != 2 || class != INTERFACE_IDENTIFICATION_CLASS {
+        return Err(ParseError::BadExtension);
+    }
+    let object_len = usize::from(u16::from_be_bytes([a, b]));
+    let payload = extension
+        .get(8..4 + object_len.max(4))
+        .ok_or(ParseError::Truncated)?;
+
+    let query = match (c_type, payload) {
+        (1, name) if local => {
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            let name = std::str::from_utf8(name).map_err(|_| ParseError::BadExtension)?;
+            InterfaceQuery::Name(name.to_owned())
+        }
+        (2, &[a, b, c, d]) if local => InterfaceQuery::Index(u32::from_be_bytes([a, b, c, d])),
+        (3, [0, 1, 4, _, addr @ ..]) if addr.len() == 4 => {
+            let addr = IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(addr).unwrap()));
+            if local {
+                InterfaceQuery::Address(addr)
+            } else {
+                InterfaceQuery::Neighbor(addr)
             }
-        } else if let Some(icmp_packet) = Icmpv6Packet::new(&buf) {
-            if icmp_packet.get_icmpv6_type() == Icmpv6Types::EchoReply {
-                use pnet_packet::icmpv6::echo_reply::EchoReplyPacket;
-
-                if let Some(echo_reply_packet) = EchoReplyPacket::new(&buf) {
-                    return Some(Self {
-                        source,
-                        identifier: echo_reply_packet.get_identifier(),
-                        sequence_number: echo_reply_packet.get_sequence_number(),
-                        payload: buf.slice_ref(echo_reply_packet.payload()),
-                    });
-                }
+        }
+        (3, [0, 2, 16, _, addr @ ..]) if addr.len() == 16 => {
+            let addr = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(addr).unwrap()));
+            if local {
+                InterfaceQuery::Address(addr)
+            } else {
+                InterfaceQuery::Neighbor(addr)
             }
         }
+        _ => return Err(ParseError::BadExtension),
+    };
+    Ok(query)
+}
+
+/// Ones' complement sum of `bytes` as 16 bit big endian words, with the
+/// last word padded with zero if needed
+fn ones_complement_sum(bytes: &[u8]) -> u16 {
+    let sum = bytes
+        .chunks(2)
+        .map(|word| match *word {
+            [high, low] => u32::from(u16::from_be_bytes([high, low])),
+            [high] => u32::from(u16::from_be_bytes([high, 0])),
+            _ => unreachable!(),
+        })
+        .fold(0, |sum, word| fold(sum + word).into());
+    fold(sum)
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+impl<V: IpVersion> EchoReplyPacket<V> {
+    /// Build a new ICMP echo reply packet, as if it was received from `source`
+    ///
+    /// The checksum is computed the same way as [`EchoRequestPacket::new`].
+    pub fn new(source: V, identifier: u16, sequence_number: u16, payload: &[u8]) -> Self {
+        Self::build(source, identifier, sequence_number, payload, None)
+    }
+
+    /// Build a new ICMP echo reply packet sent from `source` to `destination`
+    ///
+    /// Unlike [`new`](Self::new), the checksum is always complete.
+    pub fn with_addresses(
+        source: V,
+        destination: V,
+        identifier: u16,
+        sequence_number: u16,
+        payload: &[u8],
+    ) -> Self {
+        Self::build(
+            source,
+            identifier,
+            sequence_number,
+            payload,
+            Some((source, destination)),
+        )
+    }
+
+    fn build(
+        source: V,
+        identifier: u16,
+        sequence_number: u16,
+        payload: &[u8],
+        addresses: Option<(V, V)>,
+    ) -> Self {
+        let reply_type = if V::IS_V4 {
+            IcmpTypes::EchoReply.0
+        } else {
+            Icmpv6Types::EchoReply.0
+        };
+
+        let mut buf = BytesMut::zeroed(8 + payload.len());
+        buf[0] = reply_type;
+        buf[4..6].copy_from_slice(&identifier.to_be_bytes());
+        buf[6..8].copy_from_slice(&sequence_number.to_be_bytes());
+        buf[8..].copy_from_slice(payload);
+        let checksum = checksum(&buf, addresses);
+        buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+        Self {
+            source,
+            identifier,
+            sequence_number,
+            buf: buf.freeze(),
+        }
+    }
+
+    /// Parse an ICMP echo reply packet received from `source`
+    ///
+    /// The checksum is only verified for IPv4, as for IPv6 it also covers
+    /// the addresses of the IPv6 header. See [`verify_checksum`].
+    ///
+    /// [`verify_checksum`]: Self::verify_checksum
+    pub fn parse(source: V, buf: &[u8]) -> Result<Self, ParseError> {
+        Self::from_reply(source, Bytes::copy_from_slice(buf))
+    }
 
-        None
+    /// Parse an ICMP echo reply packet without copying its payload
+    pub(crate) fn from_reply(source: V, buf: Bytes) -> Result<Self, ParseError> {
+        let reply_type = if V::IS_V4 {
+            IcmpTypes::EchoReply.0
+        } else {
+            Icmpv6Types::EchoReply.0
+        };
+        let (identifier, sequence_number) = parse_query::<V>(&buf, reply_type)?;
+
+        Ok(Self {
+            source,
+            identifier,
+            sequence_number,
+            buf,
+        })
     }
 
     /// Get the source IP address
@@ -131,6 +690,1113 @@ impl<V: IpVersion> EchoReplyPacket<V> {
 
     /// Get the ICMP packet payload
     pub fn payload(&self) -> &[u8] {
-        &self.payload
+        &self.buf[8..]
+    }
+
+    /// Get the length of the packet in bytes
+    #[expect(clippy::len_without_is_empty, reason = "packets are never empty")]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Get the bytes of the packet
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Write the packet into the beginning of `buf`, returning its length
+    ///
+    /// Fails without writing anything if `buf` is shorter than [`len`](Self::len).
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, BufferTooShort> {
+        write_packet(&self.buf, buf)
+    }
+
+    /// Check the checksum of the packet, given the `destination` it was received on
+    pub fn verify_checksum(&self, destination: V) -> bool {
+        verify_checksum(&self.buf, self.source, destination)
+    }
+
+    /// Get the header fields of the packet, which unlike the packet
+    /// itself can be stored or serialized
+    pub fn metadata(&self) -> EchoReplyMetadata<V> {
+        EchoReplyMetadata {
+            source: self.source,
+            identifier: self.identifier,
+            sequence_number: self.sequence_number,
+            payload_len: self.payload().len(),
+        }
+    }
+}
+
+impl TimestampRequestPacket {
+    /// Build a new ICMP timestamp request packet, sent at `originate`
+    pub fn new(identifier: u16, sequence_number: u16, originate: u32) -> Self {
+        let mut buf = BytesMut::zeroed(TIMESTAMP_LEN);
+        buf[0] = IcmpTypes::Timestamp.0;
+        buf[4..6].copy_from_slice(&identifier.to_be_bytes());
+        buf[6..8].copy_from_slice(&sequence_number.to_be_bytes());
+        buf[8..12].copy_from_slice(&originate.to_be_bytes());
+        let checksum = util::checksum(&buf, 1);
+        buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+        Self { buf: buf.freeze() }
+    }
+
+    /// Parse an ICMP timestamp request packet
+    pub fn parse(buf: &[u8]) -> Result<Self, ParseError> {
+        parse_timestamp(buf, IcmpTypes::Timestamp.0)?;
+
+        Ok(Self {
+            buf: Bytes::copy_from_slice(buf),
+        })
+    }
+
+    /// Get the ICMP packet identifier
+    pub fn identifier(&self) -> u16 {
+        u16::from_be_bytes([self.buf[4], self.buf[5]])
+    }
+
+    /// Get the ICMP packet sequence number
+    pub fn sequence_number(&self) -> u16 {
+        u16::from_be_bytes([self.buf[6], self.buf[7]])
+    }
+
+    /// Get the time the request was sent at
+    pub fn originate(&self) -> u32 {
+        u32::from_be_bytes(self.buf[8..12].try_into().unwrap())
+    }
+
+    /// Get the length of the packet in bytes
+    #[expect(clippy::len_without_is_empty, reason = "packets are never empty")]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Get the bytes of the packet
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Write the packet into the beginning of `buf`, returning its length
+    ///
+    /// Fails without writing anything if `buf` is shorter than [`len`](Self::len).
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, BufferTooShort> {
+        write_packet(&self.buf, buf)
+    }
+}
+
+impl TimestampReplyPacket {
+    /// Parse an ICMP timestamp reply packet received from `source`
+    pub fn parse(source: Ipv4Addr, buf: &[u8]) -> Result<Self, ParseError> {
+        let (identifier, sequence_number) = parse_timestamp(buf, IcmpTypes::TimestampReply.0)?;
+        let timestamp =
+            |offset: usize| u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap());
+
+        Ok(Self {
+            source,
+            identifier,
+            sequence_number,
+            originate: timestamp(8),
+            receive: timestamp(12),
+            transmit: timestamp(16),
+        })
+    }
+
+    /// Get the source IP address
+    pub fn source(&self) -> Ipv4Addr {
+        self.source
+    }
+
+    /// Get the ICMP packet identifier
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Get the ICMP packet sequence number
+    pub fn sequence_number(&self) -> u16 {
+        self.sequence_number
+    }
+
+    /// Get the time the request was sent at, as copied from the request
+    pub fn originate(&self) -> u32 {
+        self.originate
+    }
+
+    /// Get the time the request was received at by the replying host
+    pub fn receive(&self) -> u32 {
+        self.receive
+    }
+
+    /// Get the time the reply was sent at by the replying host
+    pub fn transmit(&self) -> u32 {
+        self.transmit
+    }
+}
+
+impl<V: IpVersion> ExtendedEchoRequestPacket<V> {
+    /// Build a new ICMP extended echo request packet
+    ///
+    /// The checksum is computed the same way as [`EchoRequestPacket::new`].
+    pub fn new(identifier: u16, sequence_number: u8, query: InterfaceQuery) -> Self {
+        Self::build(identifier, sequence_number, query, None)
+    }
+
+    /// Build a new ICMP extended echo request packet to be sent from `source` to `destination`
+    ///
+    /// The checksum is computed the same way as [`EchoRequestPacket::with_addresses`].
+    pub fn with_addresses(
+        source: V,
+        destination: V,
+        identifier: u16,
+        sequence_number: u8,
+        query: InterfaceQuery,
+    ) -> Self {
+        Self::build(
+            identifier,
+            sequence_number,
+            query,
+            Some((source, destination)),
+        )
+    }
+
+    fn build(
+        identifier: u16,
+        sequence_number: u8,
+        query: InterfaceQuery,
+        addresses: Option<(V, V)>,
+    ) -> Self {
+        let request_type = if V::IS_V4 {
+            EXTENDED_ECHO_REQUEST_V4
+        } else {
+            EXTENDED_ECHO_REQUEST_V6
+        };
+        let local = !matches!(query, InterfaceQuery::Neighbor(_));
+
+        let mut buf = BytesMut::with_capacity(32);
+        buf.extend_from_slice(&[request_type, 0, 0, 0]);
+        buf.extend_from_slice(&identifier.to_be_bytes());
+        buf.extend_from_slice(&[sequence_number, u8::from(local)]);
+
+        // RFC 4884 extension header, followed by the Interface Identification Object
+        buf.extend_from_slice(&[2 << 4, 0, 0, 0]);
+        let object_start = buf.len();
+        buf.extend_from_slice(&[0, 0, INTERFACE_IDENTIFICATION_CLASS, 0]);
+        let c_type = match &query {
+            InterfaceQuery::Name(name) => {
+                buf.extend_from_slice(name.as_bytes());
+                // Padded with NUL to a 32 bit boundary
+                buf.resize(buf.len().next_multiple_of(4), 0);
+                1
+            }
+            InterfaceQuery::Index(index) => {
+                buf.extend_from_slice(&index.to_be_bytes());
+                2
+            }
+            InterfaceQuery::Address(addr) | InterfaceQuery::Neighbor(addr) => {
+                match addr {
+                    IpAddr::V4(addr) => {
+                        buf.extend_from_slice(&[0, 1, 4, 0]);
+                        buf.extend_from_slice(&addr.octets());
+                    }
+                    IpAddr::V6(addr) => {
+                        buf.extend_from_slice(&[0, 2, 16, 0]);
+                        buf.extend_from_slice(&addr.octets());
+                    }
+                }
+                3
+            }
+        };
+        let object_len = (buf.len() - object_start) as u16;
+        buf[object_start..object_start + 2].copy_from_slice(&object_len.to_be_bytes());
+        buf[object_start + 3] = c_type;
+
+        let extension_checksum = util::checksum(&buf[8..], 1);
+        buf[10..12].copy_from_slice(&extension_checksum.to_be_bytes());
+        let checksum = checksum(&buf, addresses);
+        buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+        Self {
+            buf: buf.freeze(),
+            query,
+            _version: PhantomData,
+        }
+    }
+
+    /// Parse an ICMP extended echo request packet
+    ///
+    /// The checksum is only verified for IPv4, the same way as [`EchoRequestPacket::parse`].
+    pub fn parse(buf: &[u8]) -> Result<Self, ParseError> {
+        let request_type = if V::IS_V4 {
+            EXTENDED_ECHO_REQUEST_V4
+        } else {
+            EXTENDED_ECHO_REQUEST_V6
+        };
+        parse_query::<V>(buf, request_type)?;
+
+        let local = buf[7] & 1 == 1;
+        let query = parse_interface_identification(&buf[8..], local)?;
+
+        Ok(Self {
+            buf: Bytes::copy_from_slice(buf),
+            query,
+            _version: PhantomData,
+        })
+    }
+
+    /// Get the ICMP packet identifier
+    pub fn identifier(&self) -> u16 {
+        u16::from_be_bytes([self.buf[4], self.buf[5]])
+    }
+
+    /// Get the ICMP packet sequence number
+    pub fn sequence_number(&self) -> u8 {
+        self.buf[6]
+    }
+
+    /// Get the interface the packet asks about
+    pub fn query(&self) -> &InterfaceQuery {
+        &self.query
+    }
+
+    /// Get the length of the packet in bytes
+    #[expect(clippy::len_without_is_empty, reason = "packets are never empty")]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Get the bytes of the packet
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Write the packet into the beginning of `buf`, returning its length
+    ///
+    /// Fails without writing anything if `buf` is shorter than [`len`](Self::len).
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, BufferTooShort> {
+        write_packet(&self.buf, buf)
+    }
+}
+
+impl<V: IpVersion> ExtendedEchoReplyPacket<V> {
+    /// Parse an ICMP extended echo reply packet received from `source`
+    ///
+    /// The checksum is only verified for IPv4, the same way as [`EchoReplyPacket::parse`].
+    pub fn parse(source: V, buf: &[u8]) -> Result<Self, ParseError> {
+        let reply_type = if V::IS_V4 {
+            EXTENDED_ECHO_REPLY_V4
+        } else {
+            EXTENDED_ECHO_REPLY_V6
+        };
+        let [icmp_type, code, _, _, a, b, sequence_number, flags] =
+            *buf.get(..8).ok_or(ParseError::Truncated)?
+        else {
+            unreachable!()
+        };
+        if icmp_type != reply_type {
+            return Err(ParseError::BadType { icmp_type, code });
+        }
+        if V::IS_V4 && ones_complement_sum(buf) != 0xffff {
+            return Err(ParseError::BadChecksum);
+        }
+
+        Ok(Self {
+            source,
+            identifier: u16::from_be_bytes([a, b]),
+            sequence_number,
+            code,
+            flags,
+        })
+    }
+
+    /// Get the source IP address
+    pub fn source(&self) -> V {
+        self.source
+    }
+
+    /// Get the ICMP packet identifier
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Get the ICMP packet sequence number
+    pub fn sequence_number(&self) -> u8 {
+        self.sequence_number
+    }
+
+    /// Get the status of the interface, or why the proxy node couldn't report it
+    pub fn status(&self) -> Result<InterfaceStatus, ProbeError> {
+        match self.code {
+            0 => Ok(InterfaceStatus { flags: self.flags }),
+            1 => Err(ProbeError::MalformedQuery),
+            2 => Err(ProbeError::NoSuchInterface),
+            3 => Err(ProbeError::NoSuchTableEntry),
+            4 => Err(ProbeError::MultipleInterfacesSatisfyQuery),
+            code => Err(ProbeError::Other(code)),
+        }
+    }
+}
+
+impl InterfaceStatus {
+    /// Get the state of the neighbor in the ARP or Neighbor Discovery table
+    /// of the proxy node, only set for [`InterfaceQuery::Neighbor`]
+    ///
+    /// The values are described by RFC 8335: 1 means incomplete, 2 reachable,
+    /// 3 stale, 4 delay, 5 probe and 6 failed.
+    pub fn state(&self) -> u8 {
+        self.flags >> 5
+    }
+
+    /// Whether the interface is active
+    pub fn is_active(&self) -> bool {
+        self.flags & 0b100 != 0
+    }
+
+    /// Whether IPv4 is running on the interface
+    pub fn is_ipv4_running(&self) -> bool {
+        self.flags & 0b010 != 0
+    }
+
+    /// Whether IPv6 is running on the interface
+    pub fn is_ipv6_running(&self) -> bool {
+        self.flags & 0b001 != 0
+    }
+}
+
+impl<V: IpVersion> IcmpErrorPacket<V> {
+    /// Parse an ICMP error message sent by `source`
+    ///
+    /// Fails with [`ParseError::BadType`] if `buf` isn't one of the ICMP error
+    /// messages described by [`IcmpErrorKind`], or with
+    /// [`ParseError::BadOriginal`] if the packet embedded into it isn't an
+    /// ICMP echo request. The checksum is only verified for IPv4.
+    pub fn parse(source: V, buf: &[u8]) -> Result<Self, ParseError> {
+        let [icmp_type, code, _, _, a, b, c, d] = *buf.get(..8).ok_or(ParseError::Truncated)?
+        else {
+            unreachable!()
+        };
+        if V::IS_V4 && ones_complement_sum(buf) != 0xffff {
+            return Err(ParseError::BadChecksum);
+        }
+        let rest = [a, b, c, d];
+        let mut original = &buf[8..];
+
+        let kind = if V::IS_V4 {
+            match icmp_type {
+                3 if code == 4 => IcmpErrorKind::PacketTooBig {
+                    mtu: u16::from_be_bytes([c, d]).into(),
+                },
+                3 => IcmpErrorKind::DestinationUnreachable { code },
+                5 => IcmpErrorKind::Redirect {
+                    code,
+                    gateway: V::from_ip_addr(Ipv4Addr::from(rest).into()).unwrap(),
+                },
+                11 => IcmpErrorKind::TimeExceeded { code },
+                12 => IcmpErrorKind::ParameterProblem {
+                    code,
+                    pointer: a.into(),
+                },
+                _ => return Err(ParseError::BadType { icmp_type, code }),
+            }
+        } else {
+            match icmp_type {
+                1 => IcmpErrorKind::DestinationUnreachable { code },
+                2 => IcmpErrorKind::PacketTooBig {
+                    mtu: u32::from_be_bytes(rest),
+                },
+                3 => IcmpErrorKind::TimeExceeded { code },
+                4 => IcmpErrorKind::ParameterProblem {
+                    code,
+                    pointer: u32::from_be_bytes(rest),
+                },
+                137 => {
+                    // RFC 4861: the target and destination addresses are followed
+                    // by options, one of which carries the original packet
+                    let target = original.get(..16).ok_or(ParseError::Truncated)?;
+                    let target = <[u8; 16]>::try_from(target).unwrap();
+                    original = original
+                        .get(32..)
+                        .and_then(redirected_header)
+                        .ok_or(ParseError::Truncated)?;
+
+                    IcmpErrorKind::Redirect {
+                        code,
+                        gateway: V::from_ip_addr(Ipv6Addr::from(target).into()).unwrap(),
+                    }
+                }
+                _ => return Err(ParseError::BadType { icmp_type, code }),
+            }
+        };
+
+        let (destination, echo_request) = if V::IS_V4 {
+            let ip_packet = Ipv4Packet::new(original).ok_or(ParseError::Truncated)?;
+            if ip_packet.get_next_level_protocol() != IpNextHeaderProtocols::Icmp {
+                return Err(ParseError::BadOriginal);
+            }
+
+            let header_len = usize::from(ip_packet.get_header_length()) * 4;
+            let echo_request = original
+                .get(header_len..header_len + 8)
+                .ok_or(ParseError::Truncated)?;
+            if echo_request[0] != IcmpTypes::EchoRequest.0 {
+                return Err(ParseError::BadOriginal);
+            }
+
+            (IpAddr::V4(ip_packet.get_destination()), echo_request)
+        } else {
+            let ip_packet = Ipv6Packet::new(original).ok_or(ParseError::Truncated)?;
+            if ip_packet.get_next_header() != IpNextHeaderProtocols::Icmpv6 {
+                return Err(ParseError::BadOriginal);
+            }
+
+            let echo_request = original.get(40..48).ok_or(ParseError::Truncated)?;
+            if echo_request[0] != Icmpv6Types::EchoRequest.0 {
+                return Err(ParseError::BadOriginal);
+            }
+
+            (IpAddr::V6(ip_packet.get_destination()), echo_request)
+        };
+
+        Ok(Self {
+            source,
+            kind,
+            destination: V::from_ip_addr(destination).unwrap(),
+            identifier: u16::from_be_bytes([echo_request[4], echo_request[5]]),
+            sequence_number: u16::from_be_bytes([echo_request[6], echo_request[7]]),
+        })
+    }
+
+    /// Get the IP address of the host which sent the error
+    pub fn source(&self) -> V {
+        self.source
+    }
+
+    /// Get the type of the error
+    pub fn kind(&self) -> IcmpErrorKind<V> {
+        self.kind
+    }
+
+    /// Get the destination of the echo request which caused the error
+    pub fn destination(&self) -> V {
+        self.destination
+    }
+
+    /// Get the identifier of the echo request which caused the error
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Get the sequence number of the echo request which caused the error
+    pub fn sequence_number(&self) -> u16 {
+        self.sequence_number
+    }
+}
+
+/// Find the original packet in the options of an `ICMPv6` Redirect message
+fn redirected_header(mut options: &[u8]) -> Option<&[u8]> {
+    loop {
+        let [option_type, len] = *options.get(..2)? else {
+            unreachable!()
+        };
+        let len = usize::from(len) * 8;
+        if len == 0 {
+            return None;
+        }
+
+        let option = options.get(..len)?;
+        if option_type == 4 {
+            // Type, length and 6 reserved bytes
+            return option.get(8..);
+        }
+        options = &options[len..];
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("truncated ICMP packet"),
+            Self::BadType { icmp_type, code } => {
+                write!(f, "unexpected ICMP type {icmp_type} with code {code}")
+            }
+            Self::BadChecksum => f.write_str("bad ICMP checksum"),
+            Self::BadOriginal => f.write_str("ICMP error not caused by an echo request"),
+            Self::BadExtension => f.write_str("bad ICMP extension structure"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for BufferTooShort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "buffer too short for a {} bytes ICMP packet",
+            self.required
+        )
+    }
+}
+
+impl std::error::Error for BufferTooShort {}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MalformedQuery => f.write_str("malformed query"),
+            Self::NoSuchInterface => f.write_str("no such interface"),
+            Self::NoSuchTableEntry => f.write_str("no such table entry"),
+            Self::MultipleInterfacesSatisfyQuery => {
+                f.write_str("multiple interfaces satisfy query")
+            }
+            Self::Other(code) => write!(f, "unknown extended echo reply code {code}"),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt,
+        net::{Ipv4Addr, Ipv6Addr},
+    };
+
+    use super::{
+        BufferTooShort, EchoReplyPacket, EchoRequestPacket, EchoRequestTemplate,
+        ExtendedEchoReplyPacket, ExtendedEchoRequestPacket, IcmpErrorKind, IcmpErrorPacket,
+        InterfaceQuery, ParseError, ProbeError, icmpv6_checksum, ones_complement_sum, util,
+    };
+    use crate::IpVersion;
+
+    const PAYLOAD: [u8; 13] = *b"massping test";
+    const SOURCE_V4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SOURCE_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    const DESTINATION_V4: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+    const DESTINATION_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x100);
+    const ROUTER_V4: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+    const ROUTER_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x200);
+
+    /// Build an `ICMPv4` error message embedding the IPv4 header and the first
+    /// 8 bytes of an echo request with the given `protocol` and ICMP type
+    fn error_v4(icmp_type: u8, code: u8, rest: [u8; 4], protocol: u8, request_type: u8) -> Vec<u8> {
+        let mut buf = vec![icmp_type, code, 0, 0];
+        buf.extend_from_slice(&rest);
+        buf.extend_from_slice(&[0x45, 0, 0, 28 + 13, 0, 0, 0, 0, 64, protocol, 0, 0]);
+        buf.extend_from_slice(&SOURCE_V4.octets());
+        buf.extend_from_slice(&DESTINATION_V4.octets());
+        buf.extend_from_slice(&EchoRequestPacket::<Ipv4Addr>::new(7, 3, &PAYLOAD).as_bytes()[..8]);
+        buf[8 + 20] = request_type;
+
+        let checksum = util::checksum(&buf, 1);
+        buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+        buf
+    }
+
+    /// The IPv6 header and the first 8 bytes of an echo request with the given next header
+    fn original_v6(next_header: u8) -> Vec<u8> {
+        let mut buf = vec![0x60, 0, 0, 0, 0, 8 + 13, next_header, 64];
+        buf.extend_from_slice(&SOURCE_V6.octets());
+        buf.extend_from_slice(&DESTINATION_V6.octets());
+        buf.extend_from_slice(&EchoRequestPacket::<Ipv6Addr>::new(7, 3, &PAYLOAD).as_bytes()[..8]);
+        buf
+    }
+
+    /// Build an `ICMPv6` error message, whose checksum isn't verified
+    fn error_v6(icmp_type: u8, code: u8, rest: [u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![icmp_type, code, 0, 0];
+        buf.extend_from_slice(&rest);
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    fn assert_error<V: IpVersion + fmt::Debug>(
+        source: V,
+        buf: &[u8],
+        kind: IcmpErrorKind<V>,
+        destination: V,
+    ) {
+        let error = IcmpErrorPacket::parse(source, buf).unwrap();
+        assert_eq!(error.source(), source);
+        assert_eq!(error.kind(), kind);
+        assert_eq!(error.destination(), destination);
+        assert_eq!(error.identifier(), 7);
+        assert_eq!(error.sequence_number(), 3);
+    }
+
+    /// Patch the sequence number and an odd slice of the payload of
+    /// `template`, checking it against the packet built from scratch by `build`
+    fn assert_patches<V: IpVersion>(
+        mut template: EchoRequestTemplate<V>,
+        build: impl Fn(u16, &[u8]) -> EchoRequestPacket<V>,
+    ) {
+        let mut payload = PAYLOAD;
+        for (sequence_number, patch) in [(1, [0xff; 3]), (0xfffe, [0x00; 3]), (0x1234, [0x5a; 3])] {
+            template.set_sequence_number(sequence_number);
+            template.patch_payload(5, &patch);
+            payload[5..8].copy_from_slice(&patch);
+
+            assert_eq!(
+                template.as_bytes(),
+                build(sequence_number, &payload).as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn template_patch_v4() {
+        assert_patches(EchoRequestTemplate::new(7, 0, &PAYLOAD), |seq, payload| {
+            EchoRequestPacket::<Ipv4Addr>::new(7, seq, payload)
+        });
+
+        let source = Ipv4Addr::new(192, 0, 2, 1);
+        let destination = Ipv4Addr::new(198, 51, 100, 1);
+        assert_patches(
+            EchoRequestTemplate::with_addresses(source, destination, 7, 0, &PAYLOAD),
+            |seq, payload| EchoRequestPacket::with_addresses(source, destination, 7, seq, payload),
+        );
+    }
+
+    #[test]
+    fn template_patch_v6() {
+        // The checksum is left for the kernel to fill in
+        assert_patches(EchoRequestTemplate::new(7, 0, &PAYLOAD), |seq, payload| {
+            EchoRequestPacket::<Ipv6Addr>::new(7, seq, payload)
+        });
+
+        let source = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let destination = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        assert_patches(
+            EchoRequestTemplate::with_addresses(source, destination, 7, 0, &PAYLOAD),
+            |seq, payload| EchoRequestPacket::with_addresses(source, destination, 7, seq, payload),
+        );
+    }
+
+    #[test]
+    fn parse_echo_v4() {
+        let request = EchoRequestPacket::<Ipv4Addr>::new(7, 3, &PAYLOAD);
+        let parsed = EchoRequestPacket::<Ipv4Addr>::parse(request.as_bytes()).unwrap();
+        assert_eq!(parsed.identifier(), 7);
+        assert_eq!(parsed.sequence_number(), 3);
+        assert_eq!(parsed.payload(), PAYLOAD);
+
+        let reply = EchoReplyPacket::new(SOURCE_V4, 7, 3, &PAYLOAD);
+        let parsed = EchoReplyPacket::parse(SOURCE_V4, reply.as_bytes()).unwrap();
+        assert_eq!(parsed.source(), SOURCE_V4);
+        assert_eq!(parsed.identifier(), 7);
+        assert_eq!(parsed.sequence_number(), 3);
+        assert_eq!(parsed.payload(), PAYLOAD);
+    }
+
+    #[test]
+    fn parse_echo_v6() {
+        let request = EchoRequestPacket::<Ipv6Addr>::new(7, 3, &PAYLOAD);
+        let parsed = EchoRequestPacket::<Ipv6Addr>::parse(request.as_bytes()).unwrap();
+        assert_eq!(parsed.identifier(), 7);
+        assert_eq!(parsed.sequence_number(), 3);
+
+        let reply = EchoReplyPacket::new(SOURCE_V6, 7, 3, &PAYLOAD);
+        let parsed = EchoReplyPacket::parse(SOURCE_V6, reply.as_bytes()).unwrap();
+        assert_eq!(parsed.identifier(), 7);
+        assert_eq!(parsed.sequence_number(), 3);
+        assert_eq!(parsed.payload(), PAYLOAD);
+    }
+
+    #[test]
+    fn parse_echo_errors() {
+        let reply = EchoReplyPacket::new(SOURCE_V4, 7, 3, &PAYLOAD);
+        let bytes = reply.as_bytes();
+
+        assert_eq!(
+            EchoReplyPacket::parse(SOURCE_V4, &bytes[..7]).err(),
+            Some(ParseError::Truncated)
+        );
+        assert_eq!(
+            EchoRequestPacket::<Ipv4Addr>::parse(bytes).err(),
+            Some(ParseError::BadType {
+                icmp_type: 0,
+                code: 0
+            })
+        );
+
+        let mut bad_code = bytes.to_vec();
+        bad_code[1] = 1;
+        assert_eq!(
+            EchoReplyPacket::parse(SOURCE_V4, &bad_code).err(),
+            Some(ParseError::BadType {
+                icmp_type: 0,
+                code: 1
+            })
+        );
+
+        let mut bad_checksum = bytes.to_vec();
+        bad_checksum[10] ^= 1;
+        assert_eq!(
+            EchoReplyPacket::parse(SOURCE_V4, &bad_checksum).err(),
+            Some(ParseError::BadChecksum)
+        );
+
+        // For IPv6 the checksum also covers the addresses, so it isn't verified
+        let reply = EchoReplyPacket::with_addresses(SOURCE_V6, SOURCE_V6, 7, 3, &PAYLOAD);
+        let mut bad_checksum = reply.as_bytes().to_vec();
+        bad_checksum[10] ^= 1;
+        assert!(EchoReplyPacket::parse(SOURCE_V6, &bad_checksum).is_ok());
+    }
+
+    #[test]
+    fn write_to() {
+        let request = EchoRequestPacket::<Ipv4Addr>::new(7, 3, &PAYLOAD);
+
+        let mut buf = [0; 32];
+        assert_eq!(request.write_to(&mut buf), Ok(request.len()));
+        assert_eq!(&buf[..request.len()], request.as_bytes());
+
+        let mut buf = [0; 8];
+        assert_eq!(
+            request.write_to(&mut buf),
+            Err(BufferTooShort {
+                required: request.len()
+            })
+        );
+        assert_eq!(buf, [0; 8]);
+    }
+
+    #[test]
+    fn icmpv6_pseudo_header() {
+        let destination = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        let request = EchoRequestPacket::<Ipv6Addr>::new(7, 3, &PAYLOAD);
+
+        // RFC 8200, section 8.1: source, destination, upper-layer
+        // packet length and next header, followed by the packet
+        let mut pseudo = Vec::new();
+        pseudo.extend_from_slice(&SOURCE_V6.octets());
+        pseudo.extend_from_slice(&destination.octets());
+        pseudo.extend_from_slice(&(request.len() as u32).to_be_bytes());
+        pseudo.extend_from_slice(&[0, 0, 0, 58]);
+        pseudo.extend_from_slice(request.as_bytes());
+
+        assert_eq!(
+            icmpv6_checksum(request.as_bytes(), SOURCE_V6, destination),
+            !ones_complement_sum(&pseudo)
+        );
+    }
+
+    #[test]
+    fn verify_checksum_v4() {
+        // The IPv4 checksum doesn't cover the addresses
+        let request = EchoRequestPacket::<Ipv4Addr>::new(7, 3, &PAYLOAD);
+        assert!(request.verify_checksum(SOURCE_V4, Ipv4Addr::LOCALHOST));
+
+        let reply = EchoReplyPacket::new(SOURCE_V4, 7, 3, &PAYLOAD);
+        assert!(reply.verify_checksum(Ipv4Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn verify_checksum_v6() {
+        let destination = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        let other = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 3);
+
+        // Left as zero for the kernel to fill in
+        let request = EchoRequestPacket::<Ipv6Addr>::new(7, 3, &PAYLOAD);
+        assert!(!request.verify_checksum(SOURCE_V6, destination));
+
+        let request = EchoRequestPacket::with_addresses(SOURCE_V6, destination, 7, 3, &PAYLOAD);
+        assert!(request.verify_checksum(SOURCE_V6, destination));
+        assert!(!request.verify_checksum(SOURCE_V6, other));
+
+        let reply = EchoReplyPacket::with_addresses(SOURCE_V6, destination, 7, 3, &PAYLOAD);
+        let parsed = EchoReplyPacket::parse(SOURCE_V6, reply.as_bytes()).unwrap();
+        assert!(parsed.verify_checksum(destination));
+        assert!(!parsed.verify_checksum(other));
+
+        let mut bytes = reply.as_bytes().to_vec();
+        bytes[8] ^= 1;
+        let tampered = EchoReplyPacket::parse(SOURCE_V6, &bytes).unwrap();
+        assert!(!tampered.verify_checksum(destination));
+    }
+
+    #[test]
+    fn parse_icmp_error_v4() {
+        let cases = [
+            (
+                3,
+                1,
+                [0; 4],
+                IcmpErrorKind::DestinationUnreachable { code: 1 },
+            ),
+            (
+                3,
+                4,
+                [0, 0, 0x05, 0xdc],
+                IcmpErrorKind::PacketTooBig { mtu: 1500 },
+            ),
+            (
+                5,
+                1,
+                [192, 0, 2, 254],
+                IcmpErrorKind::Redirect {
+                    code: 1,
+                    gateway: Ipv4Addr::new(192, 0, 2, 254),
+                },
+            ),
+            (11, 0, [0; 4], IcmpErrorKind::TimeExceeded { code: 0 }),
+            (
+                12,
+                0,
+                [9, 0, 0, 0],
+                IcmpErrorKind::ParameterProblem {
+                    code: 0,
+                    pointer: 9,
+                },
+            ),
+        ];
+        for (icmp_type, code, rest, kind) in cases {
+            let buf = error_v4(icmp_type, code, rest, 1, 8);
+            assert_error(ROUTER_V4, &buf, kind, DESTINATION_V4);
+        }
+    }
+
+    #[test]
+    fn parse_icmp_error_v4_errors() {
+        let parse = |buf: &[u8]| IcmpErrorPacket::parse(ROUTER_V4, buf).err();
+
+        let buf = error_v4(3, 1, [0; 4], 1, 8);
+        assert_eq!(parse(&buf[..7]), Some(ParseError::Truncated));
+
+        let mut bad_checksum = buf.clone();
+        bad_checksum[4] ^= 1;
+        assert_eq!(parse(&bad_checksum), Some(ParseError::BadChecksum));
+
+        let echo_reply = EchoReplyPacket::new(ROUTER_V4, 7, 3, &PAYLOAD);
+        assert_eq!(
+            parse(echo_reply.as_bytes()),
+            Some(ParseError::BadType {
+                icmp_type: 0,
+                code: 0
+            })
+        );
+
+        // Not an ICMP packet, and not an echo request
+        assert_eq!(
+            parse(&error_v4(3, 3, [0; 4], 17, 8)),
+            Some(ParseError::BadOriginal)
+        );
+        assert_eq!(
+            parse(&error_v4(3, 3, [0; 4], 1, 13)),
+            Some(ParseError::BadOriginal)
+        );
+
+        // The echo request is cut short
+        let mut truncated = buf[..8 + 20 + 4].to_vec();
+        truncated[2..4].fill(0);
+        let checksum = util::checksum(&truncated, 1);
+        truncated[2..4].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(parse(&truncated), Some(ParseError::Truncated));
+    }
+
+    #[test]
+    fn parse_icmp_error_v6() {
+        let original = original_v6(58);
+        let cases = [
+            (
+                1,
+                3,
+                [0; 4],
+                IcmpErrorKind::DestinationUnreachable { code: 3 },
+            ),
+            (
+                2,
+                0,
+                1280_u32.to_be_bytes(),
+                IcmpErrorKind::PacketTooBig { mtu: 1280 },
+            ),
+            (3, 0, [0; 4], IcmpErrorKind::TimeExceeded { code: 0 }),
+            (
+                4,
+                1,
+                40_u32.to_be_bytes(),
+                IcmpErrorKind::ParameterProblem {
+                    code: 1,
+                    pointer: 40,
+                },
+            ),
+        ];
+        for (icmp_type, code, rest, kind) in cases {
+            let buf = error_v6(icmp_type, code, rest, &original);
+            assert_error(ROUTER_V6, &buf, kind, DESTINATION_V6);
+        }
+
+        // The target and destination addresses, a Target Link-Layer Address
+        // option and the Redirected Header option carrying the original packet
+        let gateway = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let mut body = Vec::new();
+        body.extend_from_slice(&gateway.octets());
+        body.extend_from_slice(&DESTINATION_V6.octets());
+        body.extend_from_slice(&[2, 1, 0x02, 0, 0, 0, 0, 1]);
+        body.extend_from_slice(&[4, 7, 0, 0, 0, 0, 0, 0]);
+        body.extend_from_slice(&original);
+        assert_error(
+            ROUTER_V6,
+            &error_v6(137, 0, [0; 4], &body),
+            IcmpErrorKind::Redirect { code: 0, gateway },
+            DESTINATION_V6,
+        );
+
+        // Without the Redirected Header option
+        body.truncate(32 + 8);
+        assert_eq!(
+            IcmpErrorPacket::parse(ROUTER_V6, &error_v6(137, 0, [0; 4], &body)).err(),
+            Some(ParseError::Truncated)
+        );
+    }
+
+    #[test]
+    fn parse_icmp_error_v6_errors() {
+        let parse = |buf: &[u8]| IcmpErrorPacket::parse(ROUTER_V6, buf).err();
+
+        let buf = error_v6(1, 3, [0; 4], &original_v6(58));
+        assert_eq!(parse(&buf[..7]), Some(ParseError::Truncated));
+        assert_eq!(parse(&buf[..8 + 40 + 4]), Some(ParseError::Truncated));
+        assert_eq!(
+            parse(&error_v6(128, 0, [0; 4], &original_v6(58))),
+            Some(ParseError::BadType {
+                icmp_type: 128,
+                code: 0
+            })
+        );
+        assert_eq!(
+            parse(&error_v6(1, 3, [0; 4], &original_v6(17))),
+            Some(ParseError::BadOriginal)
+        );
+    }
+
+    #[test]
+    fn parse_extended_echo_request() {
+        let queries = [
+            InterfaceQuery::Name("eth0".to_owned()),
+            InterfaceQuery::Name("wlan0".to_owned()),
+            InterfaceQuery::Index(2),
+            InterfaceQuery::Address(SOURCE_V4.into()),
+            InterfaceQuery::Address(SOURCE_V6.into()),
+            InterfaceQuery::Neighbor(DESTINATION_V4.into()),
+            InterfaceQuery::Neighbor(DESTINATION_V6.into()),
+        ];
+        for query in queries {
+            let request = ExtendedEchoRequestPacket::<Ipv4Addr>::new(7, 3, query.clone());
+            let parsed = ExtendedEchoRequestPacket::<Ipv4Addr>::parse(request.as_bytes()).unwrap();
+            assert_eq!(parsed.identifier(), 7);
+            assert_eq!(parsed.sequence_number(), 3);
+            assert_eq!(parsed.query(), &query);
+
+            let request = ExtendedEchoRequestPacket::with_addresses(
+                SOURCE_V6,
+                DESTINATION_V6,
+                7,
+                3,
+                query.clone(),
+            );
+            let parsed = ExtendedEchoRequestPacket::<Ipv6Addr>::parse(request.as_bytes()).unwrap();
+            assert_eq!(parsed.query(), &query);
+        }
+    }
+
+    #[test]
+    fn parse_extended_echo_request_errors() {
+        let parse_v4 = |buf: &[u8]| ExtendedEchoRequestPacket::<Ipv4Addr>::parse(buf).err();
+        // The ICMPv6 checksum isn't verified, so the extension can be tampered with
+        let parse_v6 = |buf: &[u8]| ExtendedEchoRequestPacket::<Ipv6Addr>::parse(buf).err();
+
+        let request = ExtendedEchoRequestPacket::<Ipv4Addr>::new(7, 3, InterfaceQuery::Index(2));
+        let bytes = request.as_bytes();
+        assert_eq!(parse_v4(&bytes[..7]), Some(ParseError::Truncated));
+        assert_eq!(
+            parse_v6(bytes),
+            Some(ParseError::BadType {
+                icmp_type: 42,
+                code: 0
+            })
+        );
+
+        let mut bad_checksum = bytes.to_vec();
+        bad_checksum[19] ^= 1;
+        assert_eq!(parse_v4(&bad_checksum), Some(ParseError::BadChecksum));
+
+        let request = ExtendedEchoRequestPacket::<Ipv6Addr>::new(7, 3, InterfaceQuery::Index(2));
+        let bytes = request.as_bytes();
+        assert!(parse_v6(bytes).is_none());
+
+        // Without extension structure, or with the object cut short
+        assert_eq!(parse_v6(&bytes[..8]), Some(ParseError::Truncated));
+        assert_eq!(parse_v6(&bytes[..18]), Some(ParseError::Truncated));
+
+        let tampered = |offset: usize, value: u8| {
+            let mut buf = bytes.to_vec();
+            buf[offset] = value;
+            parse_v6(&buf)
+        };
+        // Extension version, object class and C-Type
+        assert_eq!(tampered(8, 1 << 4), Some(ParseError::BadExtension));
+        assert_eq!(tampered(14, 1), Some(ParseError::BadExtension));
+        assert_eq!(tampered(15, 4), Some(ParseError::BadExtension));
+        // An object longer than the packet
+        assert_eq!(tampered(13, 12), Some(ParseError::Truncated));
+        // Only addresses can identify the interface of a neighbor
+        assert_eq!(tampered(7, 0), Some(ParseError::BadExtension));
+    }
+
+    /// Build an extended echo reply, with the `ICMPv4` checksum
+    fn extended_echo_reply(icmp_type: u8, code: u8, flags: u8) -> Vec<u8> {
+        let mut buf = vec![icmp_type, code, 0, 0, 0, 7, 3, flags];
+        let checksum = util::checksum(&buf, 1);
+        buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+        buf
+    }
+
+    #[test]
+    fn parse_extended_echo_reply() {
+        // Reachable neighbor, active interface running IPv4
+        let buf = extended_echo_reply(43, 0, 2 << 5 | 0b110);
+        let reply = ExtendedEchoReplyPacket::parse(ROUTER_V4, &buf).unwrap();
+        assert_eq!(reply.source(), ROUTER_V4);
+        assert_eq!(reply.identifier(), 7);
+        assert_eq!(reply.sequence_number(), 3);
+
+        let status = reply.status().unwrap();
+        assert_eq!(status.state(), 2);
+        assert!(status.is_active());
+        assert!(status.is_ipv4_running());
+        assert!(!status.is_ipv6_running());
+
+        let errors = [
+            (1, ProbeError::MalformedQuery),
+            (2, ProbeError::NoSuchInterface),
+            (3, ProbeError::NoSuchTableEntry),
+            (4, ProbeError::MultipleInterfacesSatisfyQuery),
+            (5, ProbeError::Other(5)),
+        ];
+        for (code, error) in errors {
+            let buf = extended_echo_reply(161, code, 0);
+            let reply = ExtendedEchoReplyPacket::parse(ROUTER_V6, &buf).unwrap();
+            assert_eq!(reply.status(), Err(error));
+        }
+    }
+
+    #[test]
+    fn parse_extended_echo_reply_errors() {
+        let parse = |buf: &[u8]| ExtendedEchoReplyPacket::parse(ROUTER_V4, buf).err();
+
+        let buf = extended_echo_reply(43, 0, 0);
+        assert_eq!(parse(&buf[..7]), Some(ParseError::Truncated));
+        assert_eq!(
+            parse(&extended_echo_reply(0, 0, 0)),
+            Some(ParseError::BadType {
+                icmp_type: 0,
+                code: 0
+            })
+        );
+
+        let mut bad_checksum = buf;
+        bad_checksum[7] ^= 1;
+        assert_eq!(parse(&bad_checksum), Some(ParseError::BadChecksum));
     }
 }
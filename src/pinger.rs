@@ -1,26 +1,33 @@
 use std::{
-    collections::HashMap,
-    io,
-    iter::Peekable,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io, mem,
     net::{Ipv4Addr, Ipv6Addr},
+    num::NonZeroUsize,
+    pin::Pin,
     sync::{
-        Arc,
+        Arc, Mutex,
         atomic::{AtomicU16, Ordering},
     },
-    task::{Context, Poll},
+    task::{Context, Poll, Waker, ready},
     time::Duration,
 };
-#[cfg(feature = "stream")]
-use std::{pin::Pin, task::ready};
 
 #[cfg(feature = "stream")]
 use futures_core::Stream;
-use tokio::{
-    sync::mpsc::{self, error::TryRecvError},
-    time::Instant,
-};
 
-use crate::{IpVersion, packet::EchoRequestPacket, raw_pinger::RawPinger};
+#[cfg(feature = "stream")]
+use crate::RetryMeasureStream;
+
+use crate::{
+    IpVersion,
+    packet::{EchoReplyPacket, EchoRequestTemplate},
+    raw_pinger::RawPinger,
+    reply_queue::{OverflowPolicy, ReplyQueue},
+    retry::{RetryMeasureManyStream, RetryOptions},
+    rt::{Instant, Sleep},
+    transport::Transport,
+};
 
 /// A pinger for IPv4 addresses
 pub type V4Pinger = Pinger<Ipv4Addr>;
@@ -34,19 +41,43 @@ pub struct Pinger<V: IpVersion> {
 
 struct InnerPinger<V: IpVersion> {
     raw: RawPinger<V>,
-    round_sender: mpsc::UnboundedSender<RoundMessage<V>>,
+    rounds: Mutex<Rounds<V>>,
+    driver: Mutex<DriverState>,
+    options: PingerOptions,
     identifier: u16,
     sequence_number: AtomicU16,
 }
 
-enum RoundMessage<V: IpVersion> {
-    Subscribe {
-        sequence_number: u16,
-        sender: mpsc::UnboundedSender<(V, Instant)>,
-    },
-    Unsubscribe {
-        sequence_number: u16,
-    },
+/// Source and receive time of the replies belonging to a round
+type RoundReplies<V> = ReplyQueue<(V, Instant)>;
+
+struct Rounds<V: IpVersion> {
+    replies: HashMap<u16, Arc<RoundReplies<V>>>,
+    /// Set once the receive task has stopped
+    closed: Option<Arc<io::Error>>,
+}
+
+#[derive(Default)]
+struct DriverState {
+    shutdown: bool,
+    finished: bool,
+    waker: Option<Waker>,
+    shutdown_wakers: Vec<Waker>,
+}
+
+/// Maximum number of packets the receive task processes before yielding
+const RECV_BUDGET: usize = 128;
+/// Number of packets queued by a transport which queues sends, after
+/// which they are flushed without waiting for the send queue to be drained
+pub(crate) const FLUSH_BATCH: usize = 32;
+
+/// Options for constructing a [`Pinger`].
+#[derive(Debug, Clone)]
+pub struct PingerOptions {
+    reply_queue_capacity: NonZeroUsize,
+    overflow_policy: OverflowPolicy,
+    max_in_flight: Option<NonZeroUsize>,
+    in_flight_timeout: Duration,
 }
 
 impl<V: IpVersion> Pinger<V> {
@@ -56,63 +87,88 @@ impl<V: IpVersion> Pinger<V> {
     /// be used for as long as possible, altough it might also
     /// be beneficial to `Drop` the `Pinger` and recreate it if
     /// you are not going to be sending pings for a long period of time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime, as the receive task is
+    /// spawned with [`tokio::spawn`]. See [`Pinger::with_driver`] for
+    /// running it on a different executor.
+    #[cfg(feature = "runtime-tokio")]
     pub fn new() -> io::Result<Self> {
+        Self::with_options(PingerOptions::default())
+    }
+
+    /// Construct a new `Pinger` with the given `options`.
+    ///
+    /// The same considerations of [`Pinger::new`] apply.
+    #[cfg(feature = "runtime-tokio")]
+    pub fn with_options(options: PingerOptions) -> io::Result<Self> {
+        let (pinger, driver) = Self::with_driver(options)?;
+
+        // Spawn async receive task using the same socket
+        tokio::spawn(driver);
+
+        Ok(pinger)
+    }
+
+    /// Construct a new `Pinger` with the given `options`, leaving it to
+    /// the caller to run the receive task.
+    ///
+    /// The returned [`PingerDriver`] must be polled for the `Pinger` to
+    /// receive any reply. It can be spawned on any executor, or awaited
+    /// next to the code using the `Pinger`.
+    ///
+    /// If the driver is dropped before completing, for example because the
+    /// executor it was spawned on shut down, the `Pinger` stops: active
+    /// [`MeasureManyStream`]s yield an error after the replies received so
+    /// far, and so do the ones created afterwards. [`Pinger::shutdown`]
+    /// completes immediately.
+    pub fn with_driver(options: PingerOptions) -> io::Result<(Self, PingerDriver<V>)> {
         let raw = RawPinger::new()?;
 
-        let identifier = rand::random::<u16>();
+        Ok(Self::with_raw(options, raw))
+    }
 
-        let (sender, mut receiver) = mpsc::unbounded_channel();
+    /// Construct a new `Pinger` with the given `options`, sending and
+    /// receiving ICMP packets through `transport`
+    ///
+    /// The receive task is left to the caller, the same way
+    /// as [`Pinger::with_driver`].
+    pub fn with_transport(
+        options: PingerOptions,
+        transport: impl Transport,
+    ) -> (Self, PingerDriver<V>) {
+        Self::with_raw(options, RawPinger::with_transport(transport))
+    }
+
+    fn with_raw(options: PingerOptions, raw: RawPinger<V>) -> (Self, PingerDriver<V>) {
+        let identifier = rand::random::<u16>();
 
         let inner = Arc::new(InnerPinger {
             raw,
-            round_sender: sender,
+            rounds: Mutex::new(Rounds {
+                replies: HashMap::new(),
+                closed: None,
+            }),
+            driver: Mutex::new(DriverState::default()),
+            options,
             identifier,
             sequence_number: AtomicU16::new(0),
         });
 
-        // Spawn async receive task using the same socket
-        let inner_recv = Arc::clone(&inner);
-        tokio::spawn(async move {
-            let mut subscribers: HashMap<u16, mpsc::UnboundedSender<(V, Instant)>> = HashMap::new();
-
-            loop {
-                // Process any pending subscription changes
-                loop {
-                    match receiver.try_recv() {
-                        Ok(RoundMessage::Subscribe {
-                            sequence_number,
-                            sender,
-                        }) => {
-                            subscribers.insert(sequence_number, sender);
-                        }
-                        Ok(RoundMessage::Unsubscribe { sequence_number }) => {
-                            drop(subscribers.remove(&sequence_number));
-                        }
-                        Err(TryRecvError::Empty) => break,
-                        Err(TryRecvError::Disconnected) => return,
-                    }
-                }
-
-                // Receive next packet (with DGRAM sockets, kernel handles routing)
-                let packet = match inner_recv.raw.recv().await {
-                    Ok(packet) => packet,
-                    Err(_) => continue,
-                };
-
-                let recv_instant = Instant::now();
-
-                let packet_source = packet.source();
-                let packet_sequence_number = packet.sequence_number();
-
-                if let Some(subscriber) = subscribers.get(&packet_sequence_number) {
-                    if subscriber.send((packet_source, recv_instant)).is_err() {
-                        subscribers.remove(&packet_sequence_number);
-                    }
-                }
-            }
-        });
+        let driver = PingerDriver {
+            inner: Arc::clone(&inner),
+        };
+        (Self { inner }, driver)
+    }
 
-        Ok(Self { inner })
+    /// Stop the receive task, waiting for it to exit
+    ///
+    /// Active [`MeasureManyStream`]s yield an error after the replies
+    /// received so far, and so do the ones created afterwards.
+    pub fn shutdown(&self) -> Shutdown<'_, V> {
+        self.inner.request_shutdown();
+        Shutdown { pinger: self }
     }
 
     /// Ping `addresses`
@@ -126,28 +182,290 @@ impl<V: IpVersion> Pinger<V> {
         I: Iterator<Item = V>,
     {
         let (size_hint, _) = addresses.size_hint();
-        let send_queue = addresses.into_iter().peekable();
-        let (sender, receiver) = mpsc::unbounded_channel();
+        let send_queue = addresses.into_iter();
+        let replies = Arc::new(ReplyQueue::new(
+            self.inner.options.reply_queue_capacity.get(),
+            self.inner.options.overflow_policy,
+        ));
 
         let sequence_number = self.inner.sequence_number.fetch_add(1, Ordering::AcqRel);
-        if self
-            .inner
-            .round_sender
-            .send(RoundMessage::Subscribe {
-                sequence_number,
-                sender,
-            })
-            .is_err()
         {
-            panic!("Receiver closed");
+            let mut rounds = self.inner.rounds.lock().unwrap();
+            match &rounds.closed {
+                Some(err) => replies.close(shared_error(err)),
+                None => {
+                    rounds.replies.insert(sequence_number, Arc::clone(&replies));
+                }
+            }
         }
 
+        let template = EchoRequestTemplate::new(
+            self.inner.identifier,
+            sequence_number,
+            &rand::random::<[u8; 64]>(),
+        );
+
         MeasureManyStream {
             pinger: self,
+            template,
             send_queue,
+            blocked_addr: None,
+            unflushed: Vec::new(),
             in_flight: HashMap::with_capacity(size_hint),
-            receiver,
+            sent: VecDeque::new(),
+            expiry: None,
+            expired: 0,
+            replies,
             sequence_number,
+            finished: false,
+        }
+    }
+
+    /// Ping `addresses`, retrying the ones which don't reply in time
+    ///
+    /// Creates [`RetryMeasureManyStream`] which **lazily** sends ping
+    /// requests and [`Stream`]s a single final outcome per target, recording
+    /// which attempt got a reply. The timeout is multiplied by the backoff
+    /// factor of `options` at every retry.
+    ///
+    /// [`Stream`]: futures_core::Stream
+    pub fn measure_many_with_retries<I>(
+        &self,
+        addresses: I,
+        options: RetryOptions,
+    ) -> RetryMeasureManyStream<'_, V, I>
+    where
+        I: Iterator<Item = V>,
+    {
+        RetryMeasureManyStream::new(self, addresses, options)
+    }
+
+    /// Ping the addresses coming from `addresses` as they arrive, retrying
+    /// the ones which don't reply in time
+    ///
+    /// Creates [`RetryMeasureStream`] which [`Stream`]s a single final outcome
+    /// per target, the same way as [`Pinger::measure_many_with_retries`].
+    /// Use [`RetryOptions::retries`] to ping every target only once.
+    ///
+    /// Streams which aren't [`Unpin`] can be pinned with [`Box::pin`].
+    #[cfg(feature = "stream")]
+    pub fn measure_stream_with_retries<S>(
+        &self,
+        addresses: S,
+        options: RetryOptions,
+    ) -> RetryMeasureStream<'_, V, S>
+    where
+        S: Stream<Item = V> + Unpin,
+    {
+        RetryMeasureStream::new(self, addresses, options)
+    }
+}
+
+impl<V: IpVersion> Drop for Pinger<V> {
+    fn drop(&mut self) {
+        self.inner.request_shutdown();
+    }
+}
+
+impl<V: IpVersion> InnerPinger<V> {
+    fn dispatch(&self, packet: EchoReplyPacket<V>, recv_instant: Instant) {
+        let round = self
+            .rounds
+            .lock()
+            .unwrap()
+            .replies
+            .get(&packet.sequence_number())
+            .cloned();
+        if let Some(round) = round {
+            round.push((packet.source(), recv_instant));
+        }
+    }
+
+    fn request_shutdown(&self) {
+        let mut driver = self.driver.lock().unwrap();
+        driver.shutdown = true;
+
+        if let Some(waker) = driver.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Propagate `err` to every round, present and future, and
+    /// mark the receive task as finished
+    fn close(&self, err: io::Error) {
+        {
+            let mut rounds = self.rounds.lock().unwrap();
+            let err = Arc::new(err);
+            for (_, round) in rounds.replies.drain() {
+                round.close(shared_error(&err));
+            }
+            rounds.closed = Some(err);
+        }
+
+        let mut driver = self.driver.lock().unwrap();
+        driver.finished = true;
+        for waker in driver.shutdown_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// The receive task of a [`Pinger`], routing replies to the
+/// [`MeasureManyStream`] they belong to.
+///
+/// Obtained from [`Pinger::with_driver`]. It completes after
+/// [`Pinger::shutdown`], once the `Pinger` is dropped or
+/// after a socket error.
+///
+/// Dropping it stops the `Pinger` the same way as a socket error.
+#[must_use = "the Pinger doesn't receive any reply unless the driver is polled"]
+pub struct PingerDriver<V: IpVersion> {
+    inner: Arc<InnerPinger<V>>,
+}
+
+impl<V: IpVersion> Future for PingerDriver<V> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &self.inner;
+
+        {
+            let mut driver = inner.driver.lock().unwrap();
+            if driver.finished {
+                return Poll::Ready(());
+            }
+            if driver.shutdown {
+                drop(driver);
+                inner.close(io::Error::other("pinger has been shut down"));
+                return Poll::Ready(());
+            }
+
+            match &mut driver.waker {
+                Some(waker) => waker.clone_from(cx.waker()),
+                None => driver.waker = Some(cx.waker().clone()),
+            }
+        }
+
+        for _ in 0..RECV_BUDGET {
+            // Receive next packet (with DGRAM sockets, kernel handles routing)
+            match inner.raw.poll_recv(cx) {
+                Poll::Ready(Ok(packet)) => inner.dispatch(packet, Instant::now()),
+                Poll::Ready(Err(err)) if is_transient(&err) => {}
+                Poll::Ready(Err(err)) => {
+                    inner.close(err);
+                    return Poll::Ready(());
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        // Let other tasks run before processing more packets
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl<V: IpVersion> Drop for PingerDriver<V> {
+    fn drop(&mut self) {
+        let finished = self.inner.driver.lock().unwrap().finished;
+        if !finished {
+            self.inner.close(io::Error::other("pinger driver dropped"));
+        }
+    }
+}
+
+/// [`Future`] obtained from [`Pinger::shutdown`].
+pub struct Shutdown<'a, V: IpVersion> {
+    pinger: &'a Pinger<V>,
+}
+
+impl<V: IpVersion> Future for Shutdown<'_, V> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut driver = self.pinger.inner.driver.lock().unwrap();
+        if driver.finished {
+            return Poll::Ready(());
+        }
+
+        if !driver
+            .shutdown_wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            driver.shutdown_wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Whether the receive task should keep going after `err`
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Make a new [`io::Error`] out of an error shared between many rounds
+fn shared_error(err: &Arc<io::Error>) -> io::Error {
+    io::Error::new(err.kind(), Arc::clone(err))
+}
+
+impl PingerOptions {
+    /// Construct the default `PingerOptions`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many replies each [`MeasureManyStream`] can queue up
+    /// before its [`OverflowPolicy`] kicks in.
+    ///
+    /// Defaults to 1024.
+    pub fn reply_queue_capacity(mut self, capacity: NonZeroUsize) -> Self {
+        self.reply_queue_capacity = capacity;
+        self
+    }
+
+    /// Set what to do with replies arriving when the queue is full.
+    ///
+    /// Defaults to [`OverflowPolicy::DropNewest`].
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Limit the number of probes each [`MeasureManyStream`] can
+    /// have waiting for a reply.
+    ///
+    /// Once the limit is reached no more ICMP echo requests are sent until
+    /// a reply arrives or an unanswered probe is older than
+    /// [`in_flight_timeout`](Self::in_flight_timeout). Defaults to no limit.
+    pub fn max_in_flight(mut self, max: NonZeroUsize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Set after how long an unanswered probe stops counting towards
+    /// [`max_in_flight`](Self::max_in_flight).
+    ///
+    /// Defaults to 5 seconds.
+    pub fn in_flight_timeout(mut self, timeout: Duration) -> Self {
+        self.in_flight_timeout = timeout;
+        self
+    }
+}
+
+impl Default for PingerOptions {
+    fn default() -> Self {
+        Self {
+            reply_queue_capacity: NonZeroUsize::new(1024).unwrap(),
+            overflow_policy: OverflowPolicy::default(),
+            max_in_flight: None,
+            in_flight_timeout: Duration::from_secs(5),
         }
     }
 }
@@ -158,23 +476,52 @@ impl<V: IpVersion> Pinger<V> {
 /// like [`tokio::time::timeout`] should be used to prevent the program
 /// from hanging indefinitely.
 ///
-/// Leaking this method might crate a slowly forever growing memory leak.
+/// Replies are queued up until the stream is polled. The queue is bounded
+/// by [`PingerOptions::reply_queue_capacity`], after which replies are
+/// dropped according to [`PingerOptions::overflow_policy`].
 ///
 /// [`Stream`]: futures_core::Stream
-/// [`tokio::time::timeout`]: tokio::time::timeout
+/// [`tokio::time::timeout`]: https://docs.rs/tokio/latest/tokio/time/fn.timeout.html
 pub struct MeasureManyStream<'a, V: IpVersion, I: Iterator<Item = V>> {
     pinger: &'a Pinger<V>,
-    send_queue: Peekable<I>,
+    template: EchoRequestTemplate<V>,
+    send_queue: I,
+    blocked_addr: Option<V>,
+    /// Probes queued by the transport, which aren't sent out until flushed
+    unflushed: Vec<V>,
     in_flight: HashMap<V, Instant>,
-    receiver: mpsc::UnboundedReceiver<(V, Instant)>,
+    /// Probes in the order they were sent, only tracked
+    /// when [`PingerOptions::max_in_flight`] is set
+    sent: VecDeque<(V, Instant)>,
+    expiry: Option<Sleep>,
+    expired: u64,
+    replies: Arc<RoundReplies<V>>,
     sequence_number: u16,
+    finished: bool,
 }
 
 impl<V: IpVersion, I: Iterator<Item = V>> MeasureManyStream<'_, V, I> {
-    pub fn poll_next_unpin(&mut self, cx: &mut Context<'_>) -> Poll<(V, Duration)> {
+    /// Poll for the next ping response
+    ///
+    /// An error is returned if the receive task stopped, either because
+    /// of [`Pinger::shutdown`] or of a socket error. The stream
+    /// then ends by returning `None`.
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<(V, Duration)>>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+
         // Try to see if another `MeasureManyStream` got it
-        if let Poll::Ready(Some((addr, rtt))) = self.poll_next_from_different_round(cx) {
-            return Poll::Ready((addr, rtt));
+        match self.poll_next_from_different_round(cx) {
+            Poll::Ready(Ok((addr, rtt))) => return Poll::Ready(Some(Ok((addr, rtt)))),
+            Poll::Ready(Err(err)) => {
+                self.finished = true;
+                return Poll::Ready(Some(Err(err)));
+            }
+            Poll::Pending => {}
         }
 
         // Try to send ICMP echo requests
@@ -183,43 +530,146 @@ impl<V: IpVersion, I: Iterator<Item = V>> MeasureManyStream<'_, V, I> {
         Poll::Pending
     }
 
-    fn poll_next_icmp_replies(&mut self, cx: &mut Context<'_>) {
-        while let Some(&addr) = self.send_queue.peek() {
-            let payload = rand::random::<[u8; 64]>();
+    pub(crate) fn send_queue_mut(&mut self) -> &mut I {
+        &mut self.send_queue
+    }
 
-            let packet = EchoRequestPacket::new(
-                self.pinger.inner.identifier,
-                self.sequence_number,
-                &payload,
-            );
-            match self.pinger.inner.raw.poll_send_to(cx, addr, &packet) {
+    /// Get when the probe to `addr` was sent, if it's still waiting for a reply
+    pub(crate) fn sent_at(&self, addr: &V) -> Option<Instant> {
+        self.in_flight.get(addr).copied()
+    }
+
+    /// Stop waiting for the reply from `addr`, which is then ignored
+    pub(crate) fn forget(&mut self, addr: &V) {
+        self.in_flight.remove(addr);
+    }
+
+    /// Get the number of replies dropped because the stream
+    /// wasn't being polled fast enough
+    pub fn dropped_replies(&self) -> u64 {
+        self.replies.dropped()
+    }
+
+    /// Get the number of probes that stopped counting towards
+    /// [`PingerOptions::max_in_flight`] without being answered
+    pub fn expired_probes(&self) -> u64 {
+        self.expired
+    }
+
+    /// Whether the stream stopped sending ICMP echo requests
+    /// before the send queue could be drained
+    pub(crate) fn is_send_blocked(&self) -> bool {
+        self.blocked_addr.is_some()
+    }
+
+    pub(crate) fn poll_next_icmp_replies(&mut self, cx: &mut Context<'_>) {
+        let queues_sends = self.pinger.inner.raw.queues_sends();
+        let mut sent = false;
+        while let Some(addr) = self.blocked_addr.take().or_else(|| self.send_queue.next()) {
+            if self.poll_in_flight_full(cx) {
+                self.blocked_addr = Some(addr);
+                break;
+            }
+
+            self.template.patch_payload(0, &rand::random::<[u8; 8]>());
+
+            // Taken before sending, as the receive task may be running on another
+            // thread and get the reply before `poll_send_template_to` returns
+            let sent_at = Instant::now();
+            match self
+                .pinger
+                .inner
+                .raw
+                .poll_send_template_to(cx, addr, &self.template)
+            {
+                Poll::Ready(_) if queues_sends => {
+                    sent = true;
+                    self.unflushed.push(addr);
+                    if self.unflushed.len() >= FLUSH_BATCH {
+                        self.flush(cx);
+                    }
+                }
                 Poll::Ready(_) => {
-                    let sent_at = Instant::now();
+                    sent = true;
+                    self.track_sent(addr, sent_at);
+                }
+                Poll::Pending => {
+                    self.blocked_addr = Some(addr);
+                    break;
+                }
+            }
+        }
+
+        if sent {
+            self.flush(cx);
+        }
+    }
+
+    /// Flush the probes sent, taking the time the ones
+    /// queued by the transport are actually sent out
+    fn flush(&mut self, cx: &mut Context<'_>) {
+        let flushed_at = Instant::now();
+        // Like failed sends, targets whose send failed while
+        // being flushed aren't reported and simply time out
+        let _ = self.pinger.inner.raw.poll_flush(cx);
+
+        let mut unflushed = mem::take(&mut self.unflushed);
+        for addr in unflushed.drain(..) {
+            self.track_sent(addr, flushed_at);
+        }
+        self.unflushed = unflushed;
+    }
 
-                    let taken_addr = self.send_queue.next();
-                    debug_assert!(taken_addr.is_some());
+    fn track_sent(&mut self, addr: V, sent_at: Instant) {
+        self.in_flight.insert(addr, sent_at);
+        if self.pinger.inner.options.max_in_flight.is_some() {
+            self.sent.push_back((addr, sent_at));
+        }
+    }
+
+    /// Whether [`PingerOptions::max_in_flight`] has been reached.
+    ///
+    /// If it has, `cx` is woken up once the oldest probe expires.
+    fn poll_in_flight_full(&mut self, cx: &mut Context<'_>) -> bool {
+        let Some(max_in_flight) = self.pinger.inner.options.max_in_flight else {
+            return false;
+        };
+        if self.in_flight.len() + self.unflushed.len() < max_in_flight.get() {
+            return false;
+        }
 
-                    self.in_flight.insert(addr, sent_at);
+        let timeout = self.pinger.inner.options.in_flight_timeout;
+        let now = Instant::now();
+        while let Some(&(addr, sent_at)) = self.sent.front() {
+            if self.in_flight.get(&addr) == Some(&sent_at) {
+                let deadline = sent_at + timeout;
+                if deadline > now {
+                    let expiry = self.expiry.get_or_insert_with(|| Sleep::new(deadline));
+                    expiry.reset(deadline);
+                    if expiry.poll(cx).is_pending() {
+                        break;
+                    }
                 }
-                Poll::Pending => break,
+
+                self.in_flight.remove(&addr);
+                self.expired += 1;
             }
+
+            self.sent.pop_front();
         }
+
+        self.in_flight.len() + self.unflushed.len() >= max_in_flight.get()
     }
 
     fn poll_next_from_different_round(
         &mut self,
         cx: &mut Context<'_>,
-    ) -> Poll<Option<(V, Duration)>> {
+    ) -> Poll<io::Result<(V, Duration)>> {
         loop {
-            match self.receiver.poll_recv(cx) {
-                Poll::Pending => return Poll::Pending,
-                Poll::Ready(Some((addr, recv_instant))) => {
-                    if let Some(send_instant) = self.in_flight.remove(&addr) {
-                        let rtt = recv_instant - send_instant;
-                        return Poll::Ready(Some((addr, rtt)));
-                    }
-                }
-                Poll::Ready(None) => return Poll::Ready(None),
+            let (addr, recv_instant) = ready!(self.replies.poll_pop(cx))?;
+            if let Some(send_instant) = self.in_flight.remove(&addr) {
+                let rtt = recv_instant - send_instant;
+                return Poll::Ready(Ok((addr, rtt)));
             }
         }
     }
@@ -227,22 +677,181 @@ impl<V: IpVersion, I: Iterator<Item = V>> MeasureManyStream<'_, V, I> {
 
 #[cfg(feature = "stream")]
 impl<V: IpVersion, I: Iterator<Item = V> + Unpin> Stream for MeasureManyStream<'_, V, I> {
-    type Item = (V, Duration);
+    type Item = io::Result<(V, Duration)>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let result = ready!(self.as_mut().poll_next_unpin(cx));
-        Poll::Ready(Some(result))
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+/// Addresses handed out to a [`MeasureManyStream`] one at a time
+pub(crate) struct SendQueue<V>(pub(crate) VecDeque<V>);
+
+impl<V> Iterator for SendQueue<V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
     }
 }
 
 impl<V: IpVersion, I: Iterator<Item = V>> Drop for MeasureManyStream<'_, V, I> {
     fn drop(&mut self) {
-        let _ = self
-            .pinger
-            .inner
-            .round_sender
-            .send(RoundMessage::Unsubscribe {
-                sequence_number: self.sequence_number,
-            });
+        let mut rounds = self.pinger.inner.rounds.lock().unwrap();
+        // The sequence number might have wrapped around and now belong to another round
+        if rounds
+            .replies
+            .get(&self.sequence_number)
+            .is_some_and(|round| Arc::ptr_eq(round, &self.replies))
+        {
+            rounds.replies.remove(&self.sequence_number);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::poll_fn,
+        io, iter,
+        mem::MaybeUninit,
+        net::{Ipv4Addr, SocketAddr},
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use tokio::time;
+
+    use super::{PingerOptions, V4Pinger};
+    use crate::transport::Transport;
+
+    /// Transport which sends everything into the void
+    struct SilentTransport;
+
+    impl Transport for SilentTransport {
+        fn poll_recv_from(
+            &self,
+            _cx: &mut Context<'_>,
+            _buf: &mut [MaybeUninit<u8>],
+        ) -> Poll<io::Result<(usize, SocketAddr)>> {
+            Poll::Pending
+        }
+
+        fn poll_send_to(
+            &self,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+            _addr: SocketAddr,
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_driver_stops_pinger() {
+        let (pinger, driver) = V4Pinger::with_transport(PingerOptions::new(), SilentTransport);
+        let mut before = pinger.measure_many(iter::once(Ipv4Addr::LOCALHOST));
+        assert!(poll_fn(|cx| Poll::Ready(before.poll_next_unpin(cx).is_pending())).await);
+
+        drop(driver);
+
+        time::timeout(Duration::from_secs(1), async {
+            pinger.shutdown().await;
+
+            let result = poll_fn(|cx| before.poll_next_unpin(cx)).await;
+            assert!(matches!(result, Some(Err(_))));
+
+            let mut after = pinger.measure_many(iter::once(Ipv4Addr::LOCALHOST));
+            let result = poll_fn(|cx| after.poll_next_unpin(cx)).await;
+            assert!(matches!(result, Some(Err(_))));
+        })
+        .await
+        .expect("pinger stopped");
+    }
+
+    #[cfg(all(feature = "sim", feature = "runtime-tokio"))]
+    mod overflow {
+        use std::{
+            future::poll_fn,
+            net::{IpAddr, Ipv4Addr},
+            num::NonZeroUsize,
+            task::Poll,
+            time::Duration,
+        };
+
+        use tokio::time;
+
+        use crate::{
+            OverflowPolicy, PingerOptions, V4Pinger,
+            sim::{SimHost, SimNetwork},
+        };
+
+        /// Ping 5 hosts replying one after the other into a queue of 2 replies,
+        /// returning the replies received and the number of dropped ones
+        async fn overflow(policy: OverflowPolicy) -> (Vec<(Ipv4Addr, Duration)>, u64) {
+            let network = SimNetwork::new(1);
+            let targets = (1..=5)
+                .map(|i| Ipv4Addr::new(192, 0, 2, i))
+                .collect::<Vec<_>>();
+            for (i, &target) in (1..).zip(&targets) {
+                let host = SimHost::new().latency(Duration::from_millis(10 * i));
+                network.add_host(IpAddr::V4(target), host);
+            }
+
+            let options = PingerOptions::new()
+                .reply_queue_capacity(NonZeroUsize::new(2).unwrap())
+                .overflow_policy(policy);
+            let (pinger, driver) = V4Pinger::with_transport(options, network.socket());
+            tokio::spawn(driver);
+
+            let mut stream = pinger.measure_many(targets.into_iter());
+            assert!(poll_fn(|cx| Poll::Ready(stream.poll_next_unpin(cx).is_pending())).await);
+            // Every reply arrives without the stream being polled
+            time::sleep(Duration::from_millis(100)).await;
+
+            let mut replies = Vec::new();
+            while let Ok(reply) = time::timeout(
+                Duration::from_secs(1),
+                poll_fn(|cx| stream.poll_next_unpin(cx)),
+            )
+            .await
+            {
+                replies.push(reply.unwrap().unwrap());
+            }
+            (replies, stream.dropped_replies())
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn drop_newest() {
+            // The default policy, where the probes whose reply
+            // didn't fit are lost and never yielded
+            assert_eq!(
+                PingerOptions::new().overflow_policy,
+                OverflowPolicy::DropNewest
+            );
+
+            let (replies, dropped) = overflow(OverflowPolicy::DropNewest).await;
+            assert_eq!(
+                replies,
+                [
+                    (Ipv4Addr::new(192, 0, 2, 1), Duration::from_millis(10)),
+                    (Ipv4Addr::new(192, 0, 2, 2), Duration::from_millis(20)),
+                ]
+            );
+            assert_eq!(dropped, 3);
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn drop_oldest() {
+            let (replies, dropped) = overflow(OverflowPolicy::DropOldest).await;
+            assert_eq!(
+                replies,
+                [
+                    (Ipv4Addr::new(192, 0, 2, 4), Duration::from_millis(40)),
+                    (Ipv4Addr::new(192, 0, 2, 5), Duration::from_millis(50)),
+                ]
+            );
+            assert_eq!(dropped, 3);
+        }
     }
 }
@@ -0,0 +1,193 @@
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, Ipv6Addr},
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+
+use crate::{
+    IpVersion,
+    packet::{ExtendedEchoRequestPacket, InterfaceQuery, InterfaceStatus, ProbeError},
+    raw_pinger::RawPinger,
+};
+
+/// A PROBE pinger for IPv4 proxy nodes
+pub type V4ProbePinger = ProbePinger<Ipv4Addr>;
+/// A PROBE pinger for IPv6 proxy nodes
+pub type V6ProbePinger = ProbePinger<Ipv6Addr>;
+
+/// A pinger querying the status of interfaces through ICMP extended
+/// echo requests, as described by RFC 8335.
+///
+/// Each request is sent to a proxy node, which reports the status of one
+/// of its own interfaces, or of one of the nodes directly connected to it.
+/// Proxy nodes usually don't answer unless explicitly configured to, which
+/// on Linux is done through the `net.ipv4.icmp_echo_enable_probe` sysctl.
+///
+/// Sending requests through ICMP sockets requires Linux 5.13 or later.
+pub struct ProbePinger<V: IpVersion> {
+    raw: RawPinger<V>,
+    sequence_number: u8,
+}
+
+/// The reply of a proxy node to an ICMP extended echo request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeResult<V: IpVersion> {
+    proxy: V,
+    query: InterfaceQuery,
+    status: Result<InterfaceStatus, ProbeError>,
+}
+
+impl<V: IpVersion> ProbePinger<V> {
+    /// Construct a new `ProbePinger`.
+    pub fn new() -> io::Result<Self> {
+        let raw = RawPinger::new()?;
+
+        Ok(Self {
+            raw,
+            sequence_number: 0,
+        })
+    }
+
+    /// Send an ICMP extended echo request for each `(proxy, query)` pair
+    ///
+    /// Creates [`ProbeManyStream`] which **lazily** sends the requests and
+    /// [`Stream`]s the replies as they arrive. As there is no receive task,
+    /// replies are only received while the stream is being polled.
+    ///
+    /// [`Stream`]: futures_core::Stream
+    pub fn probe_many<I>(&mut self, queries: I) -> ProbeManyStream<'_, V, I>
+    where
+        I: Iterator<Item = (V, InterfaceQuery)>,
+    {
+        let (size_hint, _) = queries.size_hint();
+        ProbeManyStream {
+            pinger: self,
+            send_queue: queries,
+            blocked_query: None,
+            in_flight: HashMap::with_capacity(size_hint.min(256)),
+        }
+    }
+}
+
+/// A [`Stream`] of ICMP extended echo replies.
+///
+/// Each request gets its own 8 bit sequence number, so at most 256 of them
+/// can be in flight at the same time: if more are sent before the replies
+/// arrive, the older request sharing the same sequence number is forgotten.
+///
+/// No kind of timeout is implemented, so an external mechanism
+/// like [`tokio::time::timeout`] should be used to prevent the program
+/// from hanging indefinitely.
+///
+/// [`Stream`]: futures_core::Stream
+/// [`tokio::time::timeout`]: https://docs.rs/tokio/latest/tokio/time/fn.timeout.html
+pub struct ProbeManyStream<'a, V: IpVersion, I: Iterator<Item = (V, InterfaceQuery)>> {
+    pinger: &'a mut ProbePinger<V>,
+    send_queue: I,
+    blocked_query: Option<(V, ExtendedEchoRequestPacket<V>)>,
+    in_flight: HashMap<u8, (V, InterfaceQuery)>,
+}
+
+impl<V: IpVersion, I: Iterator<Item = (V, InterfaceQuery)>> ProbeManyStream<'_, V, I> {
+    /// Poll for the next ICMP extended echo reply
+    ///
+    /// An error is returned if receiving from the socket fails.
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<ProbeResult<V>>>> {
+        self.poll_send(cx);
+
+        loop {
+            let packet = match self.pinger.raw.poll_recv_probe(cx) {
+                Poll::Ready(Ok(packet)) => packet,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match self.in_flight.get(&packet.sequence_number()) {
+                Some((proxy, _)) if *proxy == packet.source() => {}
+                _ => continue,
+            }
+            let (_, query) = self.in_flight.remove(&packet.sequence_number()).unwrap();
+            return Poll::Ready(Some(Ok(ProbeResult {
+                proxy: packet.source(),
+                query,
+                status: packet.status(),
+            })));
+        }
+    }
+
+    fn poll_send(&mut self, cx: &mut Context<'_>) {
+        let mut sent = false;
+        loop {
+            let (proxy, packet) = match self.blocked_query.take() {
+                Some(blocked) => blocked,
+                None => {
+                    let Some((proxy, query)) = self.send_queue.next() else {
+                        break;
+                    };
+
+                    let sequence_number = self.pinger.sequence_number;
+                    self.pinger.sequence_number = self.pinger.sequence_number.wrapping_add(1);
+                    // The identifier is rewritten by the kernel
+                    (
+                        proxy,
+                        ExtendedEchoRequestPacket::new(0, sequence_number, query),
+                    )
+                }
+            };
+
+            match self.pinger.raw.poll_send_probe_to(cx, proxy, &packet) {
+                Poll::Ready(_) => {
+                    sent = true;
+                    self.in_flight
+                        .insert(packet.sequence_number(), (proxy, packet.query().clone()));
+                }
+                Poll::Pending => {
+                    self.blocked_query = Some((proxy, packet));
+                    break;
+                }
+            }
+        }
+
+        if sent {
+            let _ = self.pinger.raw.poll_flush(cx);
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<V: IpVersion, I: Iterator<Item = (V, InterfaceQuery)> + Unpin> Stream
+    for ProbeManyStream<'_, V, I>
+{
+    type Item = io::Result<ProbeResult<V>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+impl<V: IpVersion> ProbeResult<V> {
+    /// Get the proxy node the request was sent to
+    pub fn proxy(&self) -> V {
+        self.proxy
+    }
+
+    /// Get the interface the request asked about
+    pub fn query(&self) -> &InterfaceQuery {
+        &self.query
+    }
+
+    /// Get the status of the interface, or why the proxy node couldn't report it
+    pub fn status(&self) -> Result<InterfaceStatus, ProbeError> {
+        self.status
+    }
+}
@@ -0,0 +1,398 @@
+//! Voice quality metrics computed from consecutive pings
+//!
+//! [`QualityStats`] follows the pings sent to a single target, in the order
+//! they were sent, and computes:
+//!
+//! * the interarrival jitter of [RFC 3550], applied to the round trip times;
+//! * the distribution of the IP packet delay variation (IPDV) of
+//!   [RFC 3393] between consecutive pings;
+//! * the distribution of the length of loss bursts;
+//! * an estimate of the mean opinion score (MOS) of a voice call, through
+//!   the E-model of [ITU-T G.107].
+//!
+//! [`QualityTracker`] keeps one for each target, fed with [`PingRecord`]s,
+//! for example the ones yielded by a [`Monitor`], or built from repeated
+//! [`Pinger::measure_many`] rounds. Records are put back in the order the
+//! pings were sent using their sequence number.
+//!
+//! [RFC 3550]: https://www.rfc-editor.org/rfc/rfc3550#appendix-A.8
+//! [RFC 3393]: https://www.rfc-editor.org/rfc/rfc3393
+//! [ITU-T G.107]: https://www.itu.int/rec/T-REC-G.107
+//! [`Pinger::measure_many`]: crate::Pinger::measure_many
+//! [`Monitor`]: crate::Monitor
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    time::Duration,
+};
+
+use crate::{aggregate::RttHistogram, report::PingRecord};
+
+/// Delay added by the codec and the packetization, in milliseconds
+const CODEC_DELAY_MS: f64 = 10.0;
+/// Basic signal-to-noise ratio of the E-model, with the default values
+/// of every other parameter
+const R0: f64 = 93.2;
+/// Packet loss robustness factor of G.711 with packet loss concealment
+const BPL: f64 = 25.1;
+
+/// Quality metrics of the pings sent to a single target
+///
+/// Durations are [serialized as nanoseconds](crate#serialization).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QualityStats {
+    sent: u64,
+    received: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    total_rtt: Duration,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    jitter: Duration,
+    /// Round trip time of the last reply
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    last_rtt: Option<Duration>,
+    /// Round trip time of the last ping, if it got a reply
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    previous_rtt: Option<Duration>,
+    /// Absolute IPDV between consecutive pings which both got a reply
+    ipdv: RttHistogram,
+    /// Number of loss bursts by length
+    loss_bursts: BTreeMap<u64, u64>,
+    /// Length of the loss burst still going on
+    current_burst: u64,
+}
+
+/// Keeps the [`QualityStats`] of each target
+#[derive(Debug, Clone, Default)]
+pub struct QualityTracker {
+    targets: HashMap<IpAddr, TargetQuality>,
+}
+
+#[derive(Debug, Clone)]
+struct TargetQuality {
+    stats: QualityStats,
+    /// Sequence number of the next ping to add to `stats`
+    next_sequence_number: u64,
+    /// Results of the pings observed before an earlier one, by sequence number
+    pending: BTreeMap<u64, Option<Duration>>,
+}
+
+impl QualityStats {
+    /// Construct empty `QualityStats`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the round trip time of the next ping, `None` if it was lost
+    ///
+    /// Pings must be added in the order they were sent.
+    pub fn add(&mut self, rtt: Option<Duration>) {
+        self.sent += 1;
+
+        let Some(rtt) = rtt else {
+            self.current_burst += 1;
+            self.previous_rtt = None;
+            return;
+        };
+
+        self.received += 1;
+        self.total_rtt += rtt;
+
+        if self.current_burst > 0 {
+            *self.loss_bursts.entry(self.current_burst).or_default() += 1;
+            self.current_burst = 0;
+        }
+
+        // J(i) = J(i-1) + (|D(i-1,i)| - J(i-1)) / 16
+        if let Some(last_rtt) = self.last_rtt {
+            let difference = rtt.abs_diff(last_rtt);
+            if difference >= self.jitter {
+                self.jitter += (difference - self.jitter) / 16;
+            } else {
+                self.jitter -= (self.jitter - difference) / 16;
+            }
+        }
+        if let Some(previous_rtt) = self.previous_rtt {
+            self.ipdv.record(rtt.abs_diff(previous_rtt));
+        }
+
+        self.last_rtt = Some(rtt);
+        self.previous_rtt = Some(rtt);
+    }
+
+    /// Get the number of pings added
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Get the number of pings which got a reply
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Get the ratio of pings which were lost, between `0.0` and `1.0`
+    ///
+    /// Returns `None` if no ping was added.
+    pub fn loss_ratio(&self) -> Option<f64> {
+        (self.sent > 0).then(|| (self.sent - self.received) as f64 / self.sent as f64)
+    }
+
+    /// Get the average round trip time
+    ///
+    /// Returns `None` if no ping got a reply.
+    pub fn mean_rtt(&self) -> Option<Duration> {
+        let received = u32::try_from(self.received).unwrap_or(u32::MAX);
+        self.total_rtt.checked_div(received)
+    }
+
+    /// Get the interarrival jitter as defined by RFC 3550
+    ///
+    /// It's a running average of the difference between the round trip
+    /// times of consecutive replies, smoothed over about 16 replies.
+    pub fn jitter(&self) -> Duration {
+        self.jitter
+    }
+
+    /// Get the distribution of the absolute IPDV, as defined by RFC 3393,
+    /// between consecutive pings which both got a reply
+    pub fn ipdv(&self) -> &RttHistogram {
+        &self.ipdv
+    }
+
+    /// Iterate over the lengths of the loss bursts, along with how many
+    /// bursts of each length happened, shortest bursts first
+    ///
+    /// A loss burst is a run of consecutive pings which didn't get a reply.
+    /// The burst going on, if any, is included.
+    pub fn loss_bursts(&self) -> impl Iterator<Item = (u64, u64)> {
+        let mut loss_bursts = self.loss_bursts.clone();
+        if self.current_burst > 0 {
+            *loss_bursts.entry(self.current_burst).or_default() += 1;
+        }
+        loss_bursts.into_iter()
+    }
+
+    /// Estimate the transmission rating factor R of a voice call
+    ///
+    /// This is a simplified version of the E-model of ITU-T G.107, assuming
+    /// a G.711 call with packet loss concealment and default values for
+    /// every other parameter. The one way delay is taken to be half the
+    /// average round trip time, plus a jitter buffer of twice the
+    /// [`jitter`](Self::jitter) and 10 ms of codec delay.
+    ///
+    /// Returns `None` if no ping was added.
+    pub fn r_factor(&self) -> Option<f64> {
+        let loss_percent = self.loss_ratio()? * 100.0;
+        let delay_ms = self.mean_rtt().unwrap_or_default().as_secs_f64() * 1000.0 / 2.0
+            + self.jitter.as_secs_f64() * 1000.0 * 2.0
+            + CODEC_DELAY_MS;
+
+        // Delay impairment, as approximated by Cole and Rosenbluth
+        let delay_impairment = 0.024 * delay_ms + 0.11 * (delay_ms - 177.3).max(0.0);
+        // Effective equipment impairment, with random losses
+        let loss_impairment = 95.0 * loss_percent / (loss_percent + BPL);
+
+        Some((R0 - delay_impairment - loss_impairment).clamp(0.0, 100.0))
+    }
+
+    /// Estimate the mean opinion score of a voice call, between `1.0`
+    /// (bad) and `4.5` (excellent)
+    ///
+    /// Converted from the [`r_factor`](Self::r_factor) as in ITU-T G.107.
+    ///
+    /// Returns `None` if no ping was added.
+    pub fn mos(&self) -> Option<f64> {
+        let r = self.r_factor()?;
+        // The polynomial dips slightly below 1 for the lowest ratings
+        Some((1.0 + 0.035 * r + r * (r - 60.0) * (100.0 - r) * 7e-6).clamp(1.0, 4.5))
+    }
+}
+
+impl QualityTracker {
+    /// Construct a new `QualityTracker` without any target
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the result of a ping
+    ///
+    /// The pings sent to each target must have consecutive sequence
+    /// numbers, starting with the one of the first record observed for the
+    /// target, like the records yielded by a [`Monitor`]. Records may be
+    /// observed out of order, for example a reply before the timeout of an
+    /// earlier ping: they are only added to the [`QualityStats`] once every
+    /// earlier ping was observed. Records of pings older than the ones
+    /// already added, or observed twice, are ignored.
+    ///
+    /// Pings which timed out or couldn't be sent count as lost.
+    ///
+    /// [`Monitor`]: crate::Monitor
+    pub fn observe(&mut self, record: &PingRecord) {
+        let target = self
+            .targets
+            .entry(record.target())
+            .or_insert_with(|| TargetQuality {
+                stats: QualityStats::new(),
+                next_sequence_number: record.sequence_number(),
+                pending: BTreeMap::new(),
+            });
+
+        if record.sequence_number() != target.next_sequence_number {
+            if record.sequence_number() > target.next_sequence_number {
+                target
+                    .pending
+                    .entry(record.sequence_number())
+                    .or_insert(record.rtt());
+            }
+            return;
+        }
+
+        target.stats.add(record.rtt());
+        target.next_sequence_number += 1;
+        while let Some(rtt) = target.pending.remove(&target.next_sequence_number) {
+            target.stats.add(rtt);
+            target.next_sequence_number += 1;
+        }
+    }
+
+    /// Get the quality metrics of `target`
+    pub fn stats(&self, target: IpAddr) -> Option<&QualityStats> {
+        self.targets.get(&target).map(|target| &target.stats)
+    }
+
+    /// Iterate over the targets and their quality metrics
+    pub fn iter(&self) -> impl Iterator<Item = (IpAddr, &QualityStats)> + '_ {
+        self.targets
+            .iter()
+            .map(|(&target, quality)| (target, &quality.stats))
+    }
+
+    /// Stop tracking `target`, forgetting its quality metrics
+    ///
+    /// This should be called when `target` is removed from a [`Monitor`],
+    /// since its sequence numbers start over if it's added back.
+    ///
+    /// [`Monitor`]: crate::Monitor
+    pub fn remove(&mut self, target: IpAddr) {
+        self.targets.remove(&target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use super::{QualityStats, QualityTracker};
+    use crate::report::PingRecord;
+
+    const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn added(rtts: &[Option<u64>]) -> QualityStats {
+        let mut stats = QualityStats::new();
+        for rtt in rtts {
+            stats.add(rtt.map(Duration::from_millis));
+        }
+        stats
+    }
+
+    #[test]
+    fn jitter_and_ipdv() {
+        let stats = added(&[Some(10), Some(13), None, Some(10), Some(11)]);
+        assert_eq!(stats.sent(), 5);
+        assert_eq!(stats.received(), 4);
+        assert_eq!(stats.loss_ratio(), Some(0.2));
+        assert_eq!(stats.mean_rtt(), Some(Duration::from_micros(11_000)));
+
+        // J = 3/16, then 3/16 + (3 - 3/16)/16, then J + (1 - J)/16 milliseconds,
+        // each step truncated to the nanosecond
+        assert_eq!(stats.jitter(), Duration::from_nanos(403_075));
+
+        // The lost ping breaks the IPDV between 13 and 10 ms
+        let ipdv = stats.ipdv();
+        assert_eq!(ipdv.count(), 2);
+        assert_eq!(ipdv.min(), Some(Duration::from_millis(1)));
+        assert_eq!(ipdv.max(), Some(Duration::from_millis(3)));
+    }
+
+    #[test]
+    fn loss_bursts() {
+        let stats = added(&[
+            Some(1),
+            None,
+            None,
+            Some(1),
+            None,
+            Some(1),
+            None,
+            None,
+            None,
+        ]);
+        assert_eq!(
+            stats.loss_bursts().collect::<Vec<_>>(),
+            [(1, 1), (2, 1), (3, 1)]
+        );
+
+        let stats = added(&[None, Some(1), None, Some(1), None, None]);
+        assert_eq!(stats.loss_bursts().collect::<Vec<_>>(), [(1, 2), (2, 1)]);
+        assert_eq!(QualityStats::new().loss_bursts().count(), 0);
+    }
+
+    #[test]
+    fn r_factor_and_mos() {
+        let assert_close = |actual: Option<f64>, expected: f64| {
+            let actual = actual.unwrap();
+            assert!((actual - expected).abs() < 0.001, "{actual} != {expected}");
+        };
+
+        assert_eq!(QualityStats::new().r_factor(), None);
+        assert_eq!(QualityStats::new().mos(), None);
+
+        let perfect = added(&[Some(0); 10]);
+        assert_close(perfect.r_factor(), 92.96);
+        assert_close(perfect.mos(), 4.4046);
+
+        let lost = added(&[None; 10]);
+        assert_close(lost.r_factor(), 17.0208);
+        assert_close(lost.mos(), 1.1708);
+
+        // R = 5.23 would give a MOS of 0.993
+        let bad = added(&[Some(640), None]);
+        assert_close(bad.r_factor(), 5.234);
+        assert_eq!(bad.mos(), Some(1.0));
+    }
+
+    #[test]
+    fn reorder_records() {
+        let reply = |sequence_number, rtt| {
+            PingRecord::reply(TARGET, sequence_number, Duration::from_millis(rtt))
+        };
+
+        let mut tracker = QualityTracker::new();
+        tracker.observe(&reply(3, 10));
+        tracker.observe(&reply(5, 13));
+        assert_eq!(tracker.stats(TARGET).unwrap().sent(), 1);
+        tracker.observe(&reply(4, 10));
+        assert_eq!(tracker.stats(TARGET).unwrap().sent(), 3);
+
+        // A reply observed before the timeout of an earlier ping
+        tracker.observe(&reply(7, 11));
+        tracker.observe(&PingRecord::timeout(TARGET, 6));
+        assert_eq!(
+            tracker.stats(TARGET),
+            Some(&added(&[Some(10), Some(10), Some(13), None, Some(11)]))
+        );
+
+        // Duplicates and records older than the first one are ignored
+        tracker.observe(&reply(7, 20));
+        tracker.observe(&reply(2, 20));
+        assert_eq!(tracker.stats(TARGET).unwrap().sent(), 5);
+        assert_eq!(tracker.iter().count(), 1);
+
+        tracker.remove(TARGET);
+        assert_eq!(tracker.stats(TARGET), None);
+    }
+}
@@ -3,25 +3,37 @@
 use std::{
     io,
     marker::PhantomData,
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     pin::Pin,
     task::{Context, Poll, ready},
+    time::{Duration, Instant},
 };
 
-use bytes::BytesMut;
-
 use crate::{
     IpVersion,
-    packet::{EchoReplyPacket, EchoRequestPacket},
-    socket::Socket,
+    buffer_pool::BufferPool,
+    packet::{
+        EchoReplyPacket, EchoRequestPacket, EchoRequestTemplate, ExtendedEchoReplyPacket,
+        ExtendedEchoRequestPacket, TimestampReplyPacket, TimestampRequestPacket,
+    },
+    socket::{BaseSocket, Socket},
+    transport::Transport,
 };
 
 pub type RawV4Pinger = RawPinger<Ipv4Addr>;
 pub type RawV6Pinger = RawPinger<Ipv6Addr>;
+pub type SyncRawV4Pinger = SyncRawPinger<Ipv4Addr>;
+pub type SyncRawV6Pinger = SyncRawPinger<Ipv6Addr>;
+
+/// Size of the buffers ICMP packets are received into
+const RECV_BUFFER_LEN: usize = 2048;
+/// Maximum number of receive buffers kept around for reuse
+const RECV_BUFFER_POOL_LEN: usize = 64;
 
 /// Asynchronous pinger
 pub struct RawPinger<V: IpVersion> {
-    socket: Socket,
+    socket: Box<dyn Transport>,
+    pool: BufferPool,
     _version: PhantomData<V>,
 }
 
@@ -29,10 +41,17 @@ impl<V: IpVersion> RawPinger<V> {
     pub fn new() -> io::Result<Self> {
         let socket = Socket::new_icmp::<V>()?;
 
-        Ok(Self {
-            socket,
+        Ok(Self::with_transport(socket))
+    }
+
+    /// Construct a new `RawPinger` sending and receiving
+    /// ICMP packets through `transport`
+    pub fn with_transport(transport: impl Transport) -> Self {
+        Self {
+            socket: Box::new(transport),
+            pool: BufferPool::new(RECV_BUFFER_LEN, RECV_BUFFER_POOL_LEN),
             _version: PhantomData,
-        })
+        }
     }
 
     /// Send a ICMP ECHO request packet
@@ -40,7 +59,8 @@ impl<V: IpVersion> RawPinger<V> {
         SendFuture {
             pinger: self,
             addr,
-            packet,
+            packet: packet.as_bytes(),
+            sent: false,
         }
     }
 
@@ -50,66 +70,454 @@ impl<V: IpVersion> RawPinger<V> {
         cx: &mut Context<'_>,
         addr: V,
         packet: &EchoRequestPacket<V>,
+    ) -> Poll<io::Result<()>> {
+        self.poll_send_bytes_to(cx, addr, packet.as_bytes())
+    }
+
+    /// Send the current state of an ICMP ECHO request template
+    pub fn send_template_to<'a>(
+        &'a self,
+        addr: V,
+        template: &'a EchoRequestTemplate<V>,
+    ) -> SendFuture<'a, V> {
+        SendFuture {
+            pinger: self,
+            addr,
+            packet: template.as_bytes(),
+            sent: false,
+        }
+    }
+
+    /// Send the current state of an ICMP ECHO request template
+    pub fn poll_send_template_to(
+        &self,
+        cx: &mut Context<'_>,
+        addr: V,
+        template: &EchoRequestTemplate<V>,
+    ) -> Poll<io::Result<()>> {
+        self.poll_send_bytes_to(cx, addr, template.as_bytes())
+    }
+
+    fn poll_send_bytes_to(
+        &self,
+        cx: &mut Context<'_>,
+        addr: V,
+        packet: &[u8],
     ) -> Poll<io::Result<()>> {
         let addr = SocketAddr::new(addr.into(), 0);
 
-        let result = ready!(self.socket.poll_write_to(cx, packet.as_bytes(), addr));
+        let result = ready!(self.socket.poll_send_to(cx, packet, addr));
         Poll::Ready(result.map(|_sent| ()))
     }
 
+    /// Flush the packets sent by the `poll_send_*` methods
+    ///
+    /// Depending on the [`Transport`], packets may only be queued by the
+    /// `poll_send_*` methods, until this is called. The futures returned
+    /// by the `send_*` methods flush by themselves.
+    ///
+    /// Packets which the transport failed to send after queueing them are
+    /// reported here, possibly by a later call.
+    pub fn poll_flush(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.socket.poll_flush(cx)
+    }
+
+    /// Whether packets are only sent out by [`poll_flush`](Self::poll_flush),
+    /// see [`Transport::queues_sends`]
+    pub(crate) fn queues_sends(&self) -> bool {
+        self.socket.queues_sends()
+    }
+
     /// Receive an ICMP ECHO reply packet
     pub fn recv(&self) -> RecvFuture<'_, V> {
-        RecvFuture {
+        RecvFuture { pinger: self }
+    }
+
+    /// Receive an ICMP ECHO reply packet
+    ///
+    /// Packets are received into buffers taken from a pool owned by the
+    /// `RawPinger`. The buffer goes back to the pool once the returned
+    /// [`EchoReplyPacket`] is dropped.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<EchoReplyPacket<V>>> {
+        loop {
+            let mut buf = self.pool.get();
+            let (len, source) = ready!(self.socket.poll_recv_from(cx, buf.spare_capacity_mut()))?;
+            // Skip packets from the other IP version, which a transport could hand out
+            let Some(source) = V::from_ip_addr(source.ip()) else {
+                continue;
+            };
+            if let Ok(packet) = EchoReplyPacket::from_reply(source, buf.freeze(len)) {
+                return Poll::Ready(Ok(packet));
+            }
+        }
+    }
+
+    /// Send a ICMP EXTENDED ECHO request packet
+    ///
+    /// Linux only allows sending them through ICMP sockets since version 5.13.
+    pub fn send_probe_to<'a>(
+        &'a self,
+        addr: V,
+        packet: &'a ExtendedEchoRequestPacket<V>,
+    ) -> SendFuture<'a, V> {
+        SendFuture {
             pinger: self,
-            buf: BytesMut::new(),
+            addr,
+            packet: packet.as_bytes(),
+            sent: false,
         }
     }
 
-    /// Receive an ICMP ECHO reply packet
-    pub fn poll_recv(
+    /// Send a ICMP EXTENDED ECHO request packet
+    pub fn poll_send_probe_to(
+        &self,
+        cx: &mut Context<'_>,
+        addr: V,
+        packet: &ExtendedEchoRequestPacket<V>,
+    ) -> Poll<io::Result<()>> {
+        self.poll_send_bytes_to(cx, addr, packet.as_bytes())
+    }
+
+    /// Receive an ICMP EXTENDED ECHO reply packet
+    pub fn recv_probe(&self) -> ProbeRecvFuture<'_, V> {
+        ProbeRecvFuture { pinger: self }
+    }
+
+    /// Receive an ICMP EXTENDED ECHO reply packet
+    ///
+    /// Every other ICMP message is skipped, so this shouldn't be called
+    /// concurrently with [`poll_recv`](Self::poll_recv), as the two would
+    /// discard each other's replies.
+    pub fn poll_recv_probe(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<ExtendedEchoReplyPacket<V>>> {
+        loop {
+            let mut buf = self.pool.get();
+            let (len, source) = ready!(self.socket.poll_recv_from(cx, buf.spare_capacity_mut()))?;
+            // Skip packets from the other IP version, which a transport could hand out
+            let Some(source) = V::from_ip_addr(source.ip()) else {
+                continue;
+            };
+            if let Ok(packet) = ExtendedEchoReplyPacket::parse(source, &buf.freeze(len)) {
+                return Poll::Ready(Ok(packet));
+            }
+        }
+    }
+}
+
+/// Asynchronous ICMP timestamp pinger
+///
+/// Linux only allows sending echo requests through the ICMP sockets used by
+/// [`RawPinger`], so a raw socket is used instead, which requires
+/// `CAP_NET_RAW`. Raw sockets receive every ICMP message addressed to the
+/// host, so replies have to be matched by their identifier.
+pub struct RawTimestampPinger {
+    socket: Socket,
+    pool: BufferPool,
+}
+
+impl RawTimestampPinger {
+    pub fn new() -> io::Result<Self> {
+        let socket = Socket::new_raw_icmpv4()?;
+
+        Ok(Self {
+            socket,
+            pool: BufferPool::new(RECV_BUFFER_LEN, RECV_BUFFER_POOL_LEN),
+        })
+    }
+
+    /// Send a ICMP TIMESTAMP request packet
+    pub fn send_to<'a>(
+        &'a self,
+        addr: Ipv4Addr,
+        packet: &'a TimestampRequestPacket,
+    ) -> TimestampSendFuture<'a> {
+        TimestampSendFuture {
+            pinger: self,
+            addr,
+            packet,
+            sent: false,
+        }
+    }
+
+    /// Send a ICMP TIMESTAMP request packet
+    pub fn poll_send_to(
         &self,
-        buf: &mut BytesMut,
         cx: &mut Context<'_>,
-    ) -> Poll<io::Result<EchoReplyPacket<V>>> {
-        let (buf, source) = ready!(self.socket.poll_read(buf, cx))?;
-        let source = V::from_ip_addr(source.ip()).unwrap();
-        match EchoReplyPacket::from_reply(source, buf) {
-            Some(packet) => Poll::Ready(Ok(packet)),
-            None => {
-                cx.waker().wake_by_ref();
-                Poll::Pending
+        addr: Ipv4Addr,
+        packet: &TimestampRequestPacket,
+    ) -> Poll<io::Result<()>> {
+        let addr = SocketAddr::new(addr.into(), 0);
+
+        let result = ready!(self.socket.poll_write_to(cx, packet.as_bytes(), addr));
+        Poll::Ready(result.map(|_sent| ()))
+    }
+
+    /// Flush the packets sent by [`poll_send_to`](Self::poll_send_to)
+    ///
+    /// See [`RawPinger::poll_flush`].
+    pub fn poll_flush(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.socket.poll_flush(cx)
+    }
+
+    /// Whether packets are only sent out by [`poll_flush`](Self::poll_flush)
+    pub(crate) fn queues_sends(&self) -> bool {
+        Socket::QUEUES_SENDS
+    }
+
+    /// Receive an ICMP TIMESTAMP reply packet
+    pub fn recv(&self) -> TimestampRecvFuture<'_> {
+        TimestampRecvFuture { pinger: self }
+    }
+
+    /// Receive an ICMP TIMESTAMP reply packet
+    ///
+    /// Every other ICMP message received by the raw socket is skipped.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<TimestampReplyPacket>> {
+        loop {
+            let mut buf = self.pool.get();
+            let (len, source) = ready!(self.socket.poll_read(buf.spare_capacity_mut(), cx))?;
+            let IpAddr::V4(source) = source.ip() else {
+                continue;
+            };
+
+            // Raw IPv4 sockets also receive the IP header
+            let buf = buf.freeze(len);
+            let Some(&version_ihl) = buf.first() else {
+                continue;
+            };
+            let header_len = usize::from(version_ihl & 0x0f) * 4;
+            if let Some(packet) = buf
+                .get(header_len..)
+                .and_then(|packet| TimestampReplyPacket::parse(source, packet).ok())
+            {
+                return Poll::Ready(Ok(packet));
             }
         }
     }
 }
 
-/// [`Future`] obtained from [`RawPinger::send_to`].
+/// [`Future`] obtained from [`RawPinger::send_to`], [`RawPinger::send_template_to`]
+/// and [`RawPinger::send_probe_to`].
 pub struct SendFuture<'a, V: IpVersion> {
     pinger: &'a RawPinger<V>,
     addr: V,
-    packet: &'a EchoRequestPacket<V>,
+    packet: &'a [u8],
+    sent: bool,
 }
 
 impl<V: IpVersion> Future for SendFuture<'_, V> {
     type Output = io::Result<()>;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.pinger.poll_send_to(cx, self.addr, self.packet)
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if !self.sent {
+            ready!(self.pinger.poll_send_bytes_to(cx, self.addr, self.packet))?;
+            self.sent = true;
+        }
+        self.pinger.poll_flush(cx)
     }
 }
 
 /// [`Future`] obtained from [`RawPinger::recv`].
 pub struct RecvFuture<'a, V: IpVersion> {
     pinger: &'a RawPinger<V>,
-    buf: BytesMut,
 }
 
 impl<V: IpVersion> Future for RecvFuture<'_, V> {
     type Output = io::Result<EchoReplyPacket<V>>;
 
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.pinger.poll_recv(cx)
+    }
+}
+
+/// [`Future`] obtained from [`RawPinger::recv_probe`].
+pub struct ProbeRecvFuture<'a, V: IpVersion> {
+    pinger: &'a RawPinger<V>,
+}
+
+impl<V: IpVersion> Future for ProbeRecvFuture<'_, V> {
+    type Output = io::Result<ExtendedEchoReplyPacket<V>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.pinger.poll_recv_probe(cx)
+    }
+}
+
+/// Blocking pinger
+///
+/// Doesn't need an async runtime, as every operation blocks the
+/// calling thread until it completes.
+pub struct SyncRawPinger<V: IpVersion> {
+    socket: BaseSocket,
+    pool: BufferPool,
+    _version: PhantomData<V>,
+}
+
+impl<V: IpVersion> SyncRawPinger<V> {
+    pub fn new() -> io::Result<Self> {
+        let socket = BaseSocket::new_icmp::<V>()?;
+
+        Ok(Self {
+            socket,
+            pool: BufferPool::new(RECV_BUFFER_LEN, RECV_BUFFER_POOL_LEN),
+            _version: PhantomData,
+        })
+    }
+
+    /// Send a ICMP ECHO request packet
+    pub fn send_to(&self, addr: V, packet: &EchoRequestPacket<V>) -> io::Result<()> {
+        self.send_bytes_to(addr, packet.as_bytes())
+    }
+
+    /// Send the current state of an ICMP ECHO request template
+    pub fn send_template_to(&self, addr: V, template: &EchoRequestTemplate<V>) -> io::Result<()> {
+        self.send_bytes_to(addr, template.as_bytes())
+    }
+
+    fn send_bytes_to(&self, addr: V, packet: &[u8]) -> io::Result<()> {
+        let addr = SocketAddr::new(addr.into(), 0);
+
+        self.socket.send_to(packet, addr).map(|_sent| ())
+    }
+
+    /// Receive an ICMP ECHO reply packet, waiting at most `timeout` for it
+    ///
+    /// Returns `Ok(None)` if no reply arrived in time. Packets are received
+    /// into buffers taken from a pool, the same way as [`RawPinger::poll_recv`].
+    pub fn recv_timeout(&self, timeout: Duration) -> io::Result<Option<EchoReplyPacket<V>>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            // The timeout is truncated to microseconds, and a zero one blocks forever
+            if remaining < Duration::from_micros(1) {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            let mut buf = self.pool.get();
+            let (len, source) = match self.socket.recv(buf.spare_capacity_mut()) {
+                Ok(received) => received,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            // Skip packets from the other IP version, which a transport could hand out
+            let Some(source) = V::from_ip_addr(source.ip()) else {
+                continue;
+            };
+            if let Ok(packet) = EchoReplyPacket::from_reply(source, buf.freeze(len)) {
+                return Ok(Some(packet));
+            }
+        }
+    }
+}
+
+/// [`Future`] obtained from [`RawTimestampPinger::send_to`].
+pub struct TimestampSendFuture<'a> {
+    pinger: &'a RawTimestampPinger,
+    addr: Ipv4Addr,
+    packet: &'a TimestampRequestPacket,
+    sent: bool,
+}
+
+impl Future for TimestampSendFuture<'_> {
+    type Output = io::Result<()>;
+
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let packet = ready!(self.pinger.poll_recv(&mut self.buf, cx))?;
-        // SAFETY: `RawPinger` already checked that the packet is valid
-        Poll::Ready(Ok(packet))
+        if !self.sent {
+            ready!(self.pinger.poll_send_to(cx, self.addr, self.packet))?;
+            self.sent = true;
+        }
+        self.pinger.poll_flush(cx)
+    }
+}
+
+/// [`Future`] obtained from [`RawTimestampPinger::recv`].
+pub struct TimestampRecvFuture<'a> {
+    pinger: &'a RawTimestampPinger,
+}
+
+impl Future for TimestampRecvFuture<'_> {
+    type Output = io::Result<TimestampReplyPacket>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.pinger.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::poll_fn,
+        io,
+        mem::MaybeUninit,
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+        sync::Mutex,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use tokio::time;
+
+    use super::RawV4Pinger;
+    use crate::transport::Transport;
+
+    /// Transport receiving a fixed list of packets
+    struct ScriptedTransport(Mutex<Vec<(SocketAddr, Vec<u8>)>>);
+
+    impl Transport for ScriptedTransport {
+        fn poll_recv_from(
+            &self,
+            _cx: &mut Context<'_>,
+            buf: &mut [MaybeUninit<u8>],
+        ) -> Poll<io::Result<(usize, SocketAddr)>> {
+            let mut packets = self.0.lock().unwrap();
+            if packets.is_empty() {
+                return Poll::Pending;
+            }
+
+            let (source, packet) = packets.remove(0);
+            for (dst, &src) in buf.iter_mut().zip(&packet) {
+                dst.write(src);
+            }
+            Poll::Ready(Ok((packet.len(), source)))
+        }
+
+        fn poll_send_to(
+            &self,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+            _addr: SocketAddr,
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+    }
+
+    #[tokio::test]
+    async fn other_ip_version_is_skipped() {
+        // Echo reply with identifier 1 and sequence number 2
+        let reply = vec![0, 0, 0xff, 0xfc, 0, 1, 0, 2];
+        let transport = ScriptedTransport(Mutex::new(vec![
+            ((Ipv6Addr::LOCALHOST, 0).into(), reply.clone()),
+            ((Ipv4Addr::LOCALHOST, 0).into(), reply),
+        ]));
+        let pinger = RawV4Pinger::with_transport(transport);
+
+        let packet = time::timeout(Duration::from_secs(1), poll_fn(|cx| pinger.poll_recv(cx)))
+            .await
+            .expect("reply received")
+            .unwrap();
+        assert_eq!(packet.source(), Ipv4Addr::LOCALHOST);
+        assert_eq!(packet.sequence_number(), 2);
     }
 }
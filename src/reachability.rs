@@ -0,0 +1,425 @@
+//! Tracking whether targets are up or down from the results of their pings
+//!
+//! A [`ReachabilityTracker`] is fed [`PingRecord`]s, usually coming from a
+//! [`Monitor`], and turns them into [`Transition`]s between [`State`]s.
+//! Single lost pings are smoothed over: a target only goes down once enough
+//! pings out of the last few failed, and only comes back up after enough
+//! consecutive replies.
+//!
+//! ```no_run
+//! use std::future::poll_fn;
+//!
+//! use massping::{
+//!     DualstackPinger, Monitor, MonitorOptions,
+//!     reachability::{ReachabilityOptions, ReachabilityTracker},
+//! };
+//!
+//! async fn watch(pinger: &DualstackPinger) -> std::io::Result<()> {
+//!     let targets = ["127.0.0.1".parse().unwrap()];
+//!     let (mut monitor, _handle) = Monitor::new(pinger, targets, MonitorOptions::new());
+//!     let mut tracker = ReachabilityTracker::new(ReachabilityOptions::new());
+//!
+//!     while let Some(record) = poll_fn(|cx| monitor.poll_next_unpin(cx)).await {
+//!         if let Some(transition) = tracker.observe(&record?) {
+//!             println!("{} is now {}", transition.target(), transition.to());
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`Monitor`]: crate::Monitor
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use crate::report::{Outcome, PingRecord};
+
+/// Options for [`ReachabilityTracker`]
+#[derive(Debug, Clone)]
+pub struct ReachabilityOptions {
+    down_failures: usize,
+    down_window: usize,
+    up_successes: usize,
+    flap_transitions: usize,
+    flap_window: Duration,
+}
+
+/// The reachability of a target
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum State {
+    /// Not enough pings were observed yet
+    Unknown,
+    /// The target is answering
+    Up,
+    /// The target stopped answering
+    Down,
+}
+
+/// A change of the [`State`] of a target
+///
+/// The timestamp is [serialized as nanoseconds](crate#serialization)
+/// since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transition {
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::system_time"))]
+    timestamp: SystemTime,
+    target: IpAddr,
+    from: State,
+    to: State,
+    flapping: bool,
+}
+
+/// Turns the results of pings into up and down [`Transition`]s
+///
+/// A target goes [`Down`] once at least `failures` of its last `window`
+/// pings failed, and [`Up`] once `successes` consecutive pings got a
+/// reply. Targets start out [`Unknown`].
+///
+/// A target is considered to be flapping when it transitioned between
+/// [`Up`] and [`Down`] at least `transitions` times within the flap window.
+/// Transitions out of [`Unknown`] don't count.
+///
+/// [`Down`]: State::Down
+/// [`Up`]: State::Up
+/// [`Unknown`]: State::Unknown
+#[derive(Debug)]
+pub struct ReachabilityTracker {
+    options: ReachabilityOptions,
+    targets: HashMap<IpAddr, TargetState>,
+}
+
+#[derive(Debug)]
+struct TargetState {
+    state: State,
+    /// Whether each of the last pings failed, oldest first
+    window: VecDeque<bool>,
+    consecutive_successes: usize,
+    /// Timestamps of the recent transitions between up and down, oldest first
+    transitions: VecDeque<SystemTime>,
+}
+
+impl ReachabilityOptions {
+    /// Construct the default `ReachabilityOptions`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consider a target down once `failures` of its last `window`
+    /// pings failed.
+    ///
+    /// Defaults to 3 out of 5.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `failures` is 0 or greater than `window`.
+    pub fn down_after(mut self, failures: usize, window: usize) -> Self {
+        assert!(
+            failures > 0 && failures <= window,
+            "failures must be between 1 and window"
+        );
+        self.down_failures = failures;
+        self.down_window = window;
+        self
+    }
+
+    /// Consider a target up once `successes` consecutive pings got a reply.
+    ///
+    /// Defaults to 3.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `successes` is 0.
+    pub fn up_after(mut self, successes: usize) -> Self {
+        assert!(successes > 0, "successes must be at least 1");
+        self.up_successes = successes;
+        self
+    }
+
+    /// Consider a target flapping once it transitioned between up and down
+    /// `transitions` times within `window`.
+    ///
+    /// Defaults to 4 transitions within 5 minutes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `transitions` is 0.
+    pub fn flapping(mut self, transitions: usize, window: Duration) -> Self {
+        assert!(transitions > 0, "transitions must be at least 1");
+        self.flap_transitions = transitions;
+        self.flap_window = window;
+        self
+    }
+}
+
+impl Default for ReachabilityOptions {
+    fn default() -> Self {
+        Self {
+            down_failures: 3,
+            down_window: 5,
+            up_successes: 3,
+            flap_transitions: 4,
+            flap_window: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl State {
+    /// Get the name of the state
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Up => "up",
+            Self::Down => "down",
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Transition {
+    /// Get the time of the ping which caused the transition
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Get the address of the target
+    pub fn target(&self) -> IpAddr {
+        self.target
+    }
+
+    /// Get the state the target was in
+    pub fn from(&self) -> State {
+        self.from
+    }
+
+    /// Get the state the target is now in
+    pub fn to(&self) -> State {
+        self.to
+    }
+
+    /// Whether the target is flapping, this transition included
+    pub fn is_flapping(&self) -> bool {
+        self.flapping
+    }
+}
+
+impl ReachabilityTracker {
+    /// Construct a new `ReachabilityTracker` without any target
+    pub fn new(options: ReachabilityOptions) -> Self {
+        Self {
+            options,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Feed the result of a ping
+    ///
+    /// Pings which timed out or couldn't be sent count as failures.
+    /// Returns the transition caused by the ping, if any.
+    pub fn observe(&mut self, record: &PingRecord) -> Option<Transition> {
+        let success = record.outcome() == Outcome::Reply;
+        self.observe_outcome(record.target(), success, record.timestamp())
+    }
+
+    /// Feed whether a ping to `target` at `timestamp` got a reply
+    ///
+    /// Returns the transition caused by the ping, if any.
+    pub fn observe_outcome(
+        &mut self,
+        target: IpAddr,
+        success: bool,
+        timestamp: SystemTime,
+    ) -> Option<Transition> {
+        let options = &self.options;
+        let target_state = self.targets.entry(target).or_insert_with(|| TargetState {
+            state: State::Unknown,
+            window: VecDeque::with_capacity(options.down_window),
+            consecutive_successes: 0,
+            transitions: VecDeque::new(),
+        });
+
+        if target_state.window.len() == options.down_window {
+            target_state.window.pop_front();
+        }
+        target_state.window.push_back(!success);
+        if success {
+            target_state.consecutive_successes += 1;
+        } else {
+            target_state.consecutive_successes = 0;
+        }
+
+        let failures = target_state.window.iter().filter(|&&failed| failed).count();
+        let to = if target_state.state != State::Up
+            && target_state.consecutive_successes >= options.up_successes
+        {
+            State::Up
+        } else if target_state.state != State::Down && failures >= options.down_failures {
+            State::Down
+        } else {
+            return None;
+        };
+
+        // Start over, so that pings from before the transition can't cause the next one
+        target_state.window.clear();
+        target_state.consecutive_successes = 0;
+
+        let from = target_state.state;
+        target_state.state = to;
+        target_state.prune_transitions(options, timestamp);
+        if from != State::Unknown {
+            target_state.transitions.push_back(timestamp);
+        }
+        let flapping = target_state.flapping(options, timestamp);
+
+        Some(Transition {
+            timestamp,
+            target,
+            from,
+            to,
+            flapping,
+        })
+    }
+
+    /// Get the state of `target`
+    ///
+    /// Targets which were never observed are [`Unknown`](State::Unknown).
+    pub fn state(&self, target: IpAddr) -> State {
+        self.targets
+            .get(&target)
+            .map_or(State::Unknown, |target_state| target_state.state)
+    }
+
+    /// Whether `target` transitioned often enough to be considered
+    /// flapping within the flap window ending at `now`
+    pub fn is_flapping(&self, target: IpAddr, now: SystemTime) -> bool {
+        self.targets
+            .get(&target)
+            .is_some_and(|target_state| target_state.flapping(&self.options, now))
+    }
+
+    /// Stop tracking `target`, forgetting its state
+    pub fn remove(&mut self, target: IpAddr) {
+        self.targets.remove(&target);
+    }
+}
+
+impl TargetState {
+    /// Forget the transitions which happened before the flap window ending at `now`
+    fn prune_transitions(&mut self, options: &ReachabilityOptions, now: SystemTime) {
+        while self
+            .transitions
+            .front()
+            .is_some_and(|&transition| !in_flap_window(options, transition, now))
+        {
+            self.transitions.pop_front();
+        }
+    }
+
+    fn flapping(&self, options: &ReachabilityOptions, now: SystemTime) -> bool {
+        let recent = self
+            .transitions
+            .iter()
+            .filter(|&&transition| in_flap_window(options, transition, now))
+            .count();
+        recent >= options.flap_transitions
+    }
+}
+
+/// Whether `transition` happened within the flap window ending at `now`
+fn in_flap_window(options: &ReachabilityOptions, transition: SystemTime, now: SystemTime) -> bool {
+    now.duration_since(transition)
+        .map_or(true, |elapsed| elapsed <= options.flap_window)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use super::{ReachabilityOptions, ReachabilityTracker, State};
+
+    const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    /// Feed `outcomes` one second apart starting at `start`, returning
+    /// the state the target is in after each of them
+    fn feed(tracker: &mut ReachabilityTracker, start: u64, outcomes: &str) -> Vec<State> {
+        outcomes
+            .chars()
+            .enumerate()
+            .map(|(i, outcome)| {
+                let timestamp = UNIX_EPOCH + Duration::from_secs(start + i as u64);
+                tracker.observe_outcome(TARGET, outcome == '+', timestamp);
+                tracker.state(TARGET)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn down_after_failures_in_window() {
+        use State::{Down, Unknown, Up};
+
+        let mut tracker = ReachabilityTracker::new(ReachabilityOptions::new());
+        assert_eq!(feed(&mut tracker, 0, "+++"), [Unknown, Unknown, Up]);
+
+        // 2 failures out of the last 5 pings aren't enough, the 3rd one is
+        assert_eq!(feed(&mut tracker, 3, "-+-++-"), [Up, Up, Up, Up, Up, Up]);
+        assert_eq!(feed(&mut tracker, 9, "+-+-"), [Up, Up, Up, Down]);
+    }
+
+    #[test]
+    fn up_after_consecutive_successes() {
+        use State::{Down, Unknown, Up};
+
+        let mut tracker = ReachabilityTracker::new(ReachabilityOptions::new().up_after(2));
+        assert_eq!(feed(&mut tracker, 0, "---"), [Unknown, Unknown, Down]);
+        assert_eq!(
+            feed(&mut tracker, 3, "+-+-++"),
+            [Down, Down, Down, Down, Down, Up]
+        );
+    }
+
+    #[test]
+    fn flapping() {
+        let options = ReachabilityOptions::new()
+            .down_after(1, 1)
+            .up_after(1)
+            .flapping(3, Duration::from_secs(10));
+        let mut tracker = ReachabilityTracker::new(options);
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+        // Unknown -> Up doesn't count
+        let transition = tracker.observe_outcome(TARGET, true, at(0)).unwrap();
+        assert!(!transition.is_flapping());
+
+        tracker.observe_outcome(TARGET, false, at(1));
+        tracker.observe_outcome(TARGET, true, at(2));
+        assert!(!tracker.is_flapping(TARGET, at(2)));
+        let transition = tracker.observe_outcome(TARGET, false, at(3)).unwrap();
+        assert!(transition.is_flapping());
+        assert!(tracker.is_flapping(TARGET, at(11)));
+
+        // Querying doesn't forget anything
+        assert!(!tracker.is_flapping(TARGET, at(12)));
+        assert!(tracker.is_flapping(TARGET, at(11)));
+
+        // The transition at 1 left the window
+        let transition = tracker.observe_outcome(TARGET, true, at(12)).unwrap();
+        assert!(transition.is_flapping());
+        assert!(!tracker.is_flapping(TARGET, at(20)));
+        assert!(!tracker.is_flapping(IpAddr::V4(Ipv4Addr::LOCALHOST), SystemTime::now()));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+/// What to do when a reply arrives and the [`MeasureManyStream`] queue is full.
+///
+/// [`MeasureManyStream`]: crate::MeasureManyStream
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued reply to make room for the new one
+    DropOldest,
+    /// Drop the new reply, so that the probe is treated as lost
+    #[default]
+    DropNewest,
+}
+
+/// A bounded single consumer queue of replies belonging to the same round.
+pub(crate) struct ReplyQueue<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: u64,
+    error: Option<io::Error>,
+    waker: Option<Waker>,
+}
+
+impl<T> ReplyQueue<T> {
+    pub(crate) fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                capacity,
+                policy,
+                dropped: 0,
+                error: None,
+                waker: None,
+            }),
+        }
+    }
+
+    /// Push `item` into the queue, applying the [`OverflowPolicy`] if it's full
+    pub(crate) fn push(&self, item: T) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.queue.len() >= state.capacity {
+            state.dropped += 1;
+            match state.policy {
+                OverflowPolicy::DropOldest => {
+                    state.queue.pop_front();
+                }
+                OverflowPolicy::DropNewest => return,
+            }
+        }
+        state.queue.push_back(item);
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Make the consumer receive `error` once it has popped all queued items
+    pub(crate) fn close(&self, error: io::Error) {
+        let mut state = self.state.lock().unwrap();
+        state.error = Some(error);
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<io::Result<T>> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(item) = state.queue.pop_front() {
+            return Poll::Ready(Ok(item));
+        }
+
+        match state.error.take() {
+            Some(error) => Poll::Ready(Err(error)),
+            None => {
+                match &mut state.waker {
+                    Some(waker) => waker.clone_from(cx.waker()),
+                    None => state.waker = Some(cx.waker().clone()),
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Number of items dropped because the queue was full
+    pub(crate) fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+}
@@ -0,0 +1,591 @@
+//! Machine-readable reports of ping results
+//!
+//! [`PingRecord`]s and [`SummaryRecord`]s can be written as JSON Lines
+//! through [`JsonLinesWriter`], or as CSV through [`CsvWriter`].
+//!
+//! The schemas are stable: fields are only ever added at the end.
+//! Timestamps are written in RFC 3339 format in UTC, with nanosecond
+//! precision, while round trip times are written in milliseconds, with
+//! nanosecond precision. Loss percentages are written with three decimals.
+//! Missing values are written as `null` in JSON and as empty fields in CSV.
+//!
+//! ## Ping records
+//!
+//! | Field       | Description                                   |
+//! |-------------|-----------------------------------------------|
+//! | `timestamp` | when the outcome of the ping was known         |
+//! | `target`    | the pinged IP address                         |
+//! | `seq`       | the sequence number given to the ping         |
+//! | `outcome`   | `reply`, `timeout` or `error`                 |
+//! | `rtt_ms`    | the round trip time, only set for replies     |
+//!
+//! ## Summary records
+//!
+//! | Field       | Description                                   |
+//! |-------------|-----------------------------------------------|
+//! | `timestamp` | when the last ping was added to the summary   |
+//! | `target`    | the pinged IP address                         |
+//! | `sent`      | number of pings sent                          |
+//! | `received`  | number of replies received                    |
+//! | `loss_pct`  | percentage of pings without a reply           |
+//! | `min_ms`    | the minimum round trip time                   |
+//! | `avg_ms`    | the average round trip time                   |
+//! | `max_ms`    | the maximum round trip time                   |
+
+use std::{
+    fmt,
+    io::{self, Write},
+    marker::PhantomData,
+    net::IpAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The outcome of a single ping
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum Outcome {
+    /// A reply was received
+    Reply,
+    /// No reply was received in time
+    Timeout,
+    /// The request couldn't be sent
+    Error,
+}
+
+/// The result of a single ping
+///
+/// With the `serde` feature, records are serialized as a struct with the
+/// fields `timestamp`, `target`, `sequence_number`, `outcome` and `rtt`.
+/// This representation is meant for storing and exchanging records, and
+/// doesn't follow the [schema](self#ping-records) of [`JsonLinesWriter`]:
+/// the timestamp is in nanoseconds since the Unix epoch rather than in
+/// RFC 3339 format, and the round trip time is in nanoseconds rather than
+/// in milliseconds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingRecord {
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::system_time"))]
+    timestamp: SystemTime,
+    target: IpAddr,
+    sequence_number: u64,
+    outcome: Outcome,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    rtt: Option<Duration>,
+}
+
+/// The results of every ping sent to a target
+///
+/// With the `serde` feature, summaries are serialized as a struct with the
+/// fields `timestamp`, `target`, `sent`, `received`, `min`, `max` and
+/// `total`, the sum of the round trip times of every reply. Like for
+/// [`PingRecord`], this doesn't follow the [schema](self#summary-records)
+/// of [`JsonLinesWriter`]: times are in nanoseconds, and the loss and
+/// average round trip time aren't included, as they are derived from the
+/// other fields.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SummaryRecord {
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::system_time"))]
+    timestamp: SystemTime,
+    target: IpAddr,
+    sent: u64,
+    received: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    min: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    max: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    total: Duration,
+}
+
+/// A record that can be written by [`JsonLinesWriter`] and [`CsvWriter`].
+///
+/// Implemented by [`PingRecord`] and [`SummaryRecord`].
+pub trait Record: private::Sealed {}
+
+impl Record for PingRecord {}
+impl Record for SummaryRecord {}
+
+/// Writes records as JSON Lines, one JSON object per line.
+#[derive(Debug)]
+pub struct JsonLinesWriter<W: Write> {
+    inner: W,
+}
+
+/// Writes records as CSV, preceded by a header line.
+///
+/// All records written by the same writer must be of the same type,
+/// as they share the same header.
+#[derive(Debug)]
+pub struct CsvWriter<W: Write, R: Record> {
+    inner: W,
+    header_written: bool,
+    _record: PhantomData<fn(&R)>,
+}
+
+impl Outcome {
+    /// Get the name of the outcome, as written in records
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reply => "reply",
+            Self::Timeout => "timeout",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PingRecord {
+    /// Construct a record for a reply received now from `target`
+    pub fn reply(target: IpAddr, sequence_number: u64, rtt: Duration) -> Self {
+        Self::new(target, sequence_number, Outcome::Reply, Some(rtt))
+    }
+
+    /// Construct a record for a ping to `target` which just timed out
+    pub fn timeout(target: IpAddr, sequence_number: u64) -> Self {
+        Self::new(target, sequence_number, Outcome::Timeout, None)
+    }
+
+    /// Construct a record for a ping to `target` which couldn't be sent
+    pub fn error(target: IpAddr, sequence_number: u64) -> Self {
+        Self::new(target, sequence_number, Outcome::Error, None)
+    }
+
+    fn new(target: IpAddr, sequence_number: u64, outcome: Outcome, rtt: Option<Duration>) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            target,
+            sequence_number,
+            outcome,
+            rtt,
+        }
+    }
+
+    /// Replace the timestamp, which defaults to the time the record was constructed at
+    pub fn with_timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Get the time the outcome of the ping was known at
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Get the pinged address
+    pub fn target(&self) -> IpAddr {
+        self.target
+    }
+
+    /// Get the sequence number given to the ping
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
+    /// Get the outcome of the ping
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// Get the round trip time, if a reply was received
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+}
+
+impl SummaryRecord {
+    /// Construct an empty summary for `target`
+    pub fn new(target: IpAddr) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            target,
+            sent: 0,
+            received: 0,
+            min: None,
+            max: None,
+            total: Duration::ZERO,
+        }
+    }
+
+    /// Add the result of a ping to the summary
+    ///
+    /// Pings which couldn't be sent aren't counted. The timestamp of the
+    /// summary becomes the one of `record`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `record` is about a different target.
+    pub fn add(&mut self, record: &PingRecord) {
+        assert_eq!(record.target, self.target, "record of a different target");
+
+        self.timestamp = record.timestamp;
+        if record.outcome == Outcome::Error {
+            return;
+        }
+
+        self.sent += 1;
+        if let Some(rtt) = record.rtt {
+            self.received += 1;
+            self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+            self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+            self.total += rtt;
+        }
+    }
+
+    /// Get the time the last ping was added at
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Get the pinged address
+    pub fn target(&self) -> IpAddr {
+        self.target
+    }
+
+    /// Get the number of pings sent
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Get the number of replies received
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Get the percentage of pings without a reply
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            (self.sent - self.received) as f64 * 100.0 / self.sent as f64
+        }
+    }
+
+    /// Get the minimum round trip time
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    /// Get the average round trip time
+    pub fn avg(&self) -> Option<Duration> {
+        let received = u32::try_from(self.received).unwrap_or(u32::MAX);
+        self.total.checked_div(received)
+    }
+
+    /// Get the maximum round trip time
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    /// Construct a new `JsonLinesWriter` writing into `inner`
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Write `record` as a single line
+    pub fn write<R: Record>(&mut self, record: &R) -> io::Result<()> {
+        record.write_json(&mut self.inner)?;
+        self.inner.write_all(b"\n")
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Get the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write, R: Record> CsvWriter<W, R> {
+    /// Construct a new `CsvWriter` writing into `inner`
+    ///
+    /// The header is written together with the first record.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            header_written: false,
+            _record: PhantomData,
+        }
+    }
+
+    /// Write `record` as a single line
+    pub fn write(&mut self, record: &R) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+
+        record.write_csv(&mut self.inner)?;
+        self.inner.write_all(b"\n")
+    }
+
+    /// Write the header line, unless it was already written
+    ///
+    /// Useful for producing a valid CSV file even if there are no records.
+    pub fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+
+        self.header_written = true;
+        writeln!(self.inner, "{}", R::CSV_HEADER)
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Get the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+mod private {
+    use std::io::{self, Write};
+
+    use super::{Millis, Percent, PingRecord, SummaryRecord, Timestamp};
+
+    pub trait Sealed {
+        const CSV_HEADER: &'static str;
+
+        fn write_json(&self, w: &mut dyn Write) -> io::Result<()>;
+
+        fn write_csv(&self, w: &mut dyn Write) -> io::Result<()>;
+    }
+
+    impl Sealed for PingRecord {
+        const CSV_HEADER: &'static str = "timestamp,target,seq,outcome,rtt_ms";
+
+        fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+            write!(
+                w,
+                r#"{{"timestamp":"{}","target":"{}","seq":{},"outcome":"{}","rtt_ms":{}}}"#,
+                Timestamp(self.timestamp),
+                self.target,
+                self.sequence_number,
+                self.outcome,
+                Millis::json(self.rtt),
+            )
+        }
+
+        fn write_csv(&self, w: &mut dyn Write) -> io::Result<()> {
+            write!(
+                w,
+                "{},{},{},{},{}",
+                Timestamp(self.timestamp),
+                self.target,
+                self.sequence_number,
+                self.outcome,
+                Millis::csv(self.rtt),
+            )
+        }
+    }
+
+    impl Sealed for SummaryRecord {
+        const CSV_HEADER: &'static str =
+            "timestamp,target,sent,received,loss_pct,min_ms,avg_ms,max_ms";
+
+        fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+            write!(
+                w,
+                r#"{{"timestamp":"{}","target":"{}","sent":{},"received":{},"loss_pct":{},"min_ms":{},"avg_ms":{},"max_ms":{}}}"#,
+                Timestamp(self.timestamp),
+                self.target,
+                self.sent,
+                self.received,
+                Percent(self.loss()),
+                Millis::json(self.min),
+                Millis::json(self.avg()),
+                Millis::json(self.max),
+            )
+        }
+
+        fn write_csv(&self, w: &mut dyn Write) -> io::Result<()> {
+            write!(
+                w,
+                "{},{},{},{},{},{},{},{}",
+                Timestamp(self.timestamp),
+                self.target,
+                self.sent,
+                self.received,
+                Percent(self.loss()),
+                Millis::csv(self.min),
+                Millis::csv(self.avg()),
+                Millis::csv(self.max),
+            )
+        }
+    }
+}
+
+/// Formats a [`Duration`] in milliseconds with nanosecond precision,
+/// or the missing value if there is none
+struct Millis {
+    duration: Option<Duration>,
+    missing: &'static str,
+}
+
+impl Millis {
+    fn json(duration: Option<Duration>) -> Self {
+        Self {
+            duration,
+            missing: "null",
+        }
+    }
+
+    fn csv(duration: Option<Duration>) -> Self {
+        Self {
+            duration,
+            missing: "",
+        }
+    }
+}
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.duration {
+            Some(duration) => {
+                let nanos = duration.as_nanos();
+                write!(f, "{}.{:06}", nanos / 1_000_000, nanos % 1_000_000)
+            }
+            None => f.write_str(self.missing),
+        }
+    }
+}
+
+/// Formats a percentage with three decimals
+struct Percent(f64);
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3}", self.0)
+    }
+}
+
+/// Formats a [`SystemTime`] in RFC 3339 format, in UTC
+struct Timestamp(SystemTime);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Times before the epoch aren't expected, so they're clamped to it
+        let since_epoch = self.0.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:09}Z",
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+            since_epoch.subsec_nanos(),
+        )
+    }
+}
+
+/// Convert days since the Unix epoch into a `(year, month, day)` date
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{JsonLinesWriter, PingRecord, SummaryRecord, Timestamp, civil_from_days};
+
+    const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    #[test]
+    fn civil_dates() {
+        for (days, date) in [
+            (0, (1970, 1, 1)),
+            (364, (1970, 12, 31)),
+            (365, (1971, 1, 1)),
+            (10_956, (1999, 12, 31)),
+            (10_957, (2000, 1, 1)),
+            (11_015, (2000, 2, 28)),
+            (11_016, (2000, 2, 29)),
+            (11_017, (2000, 3, 1)),
+            (20_088, (2024, 12, 31)),
+            (20_089, (2025, 1, 1)),
+            // 2100 isn't a leap year
+            (47_540, (2100, 2, 28)),
+            (47_541, (2100, 3, 1)),
+        ] {
+            assert_eq!(civil_from_days(days), date, "{days} days");
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            Timestamp(UNIX_EPOCH).to_string(),
+            "1970-01-01T00:00:00.000000000Z"
+        );
+        assert_eq!(
+            Timestamp(UNIX_EPOCH + Duration::new(951_868_799, 123_456_789)).to_string(),
+            "2000-02-29T23:59:59.123456789Z"
+        );
+        assert_eq!(
+            Timestamp(UNIX_EPOCH + Duration::from_secs(951_868_800)).to_string(),
+            "2000-03-01T00:00:00.000000000Z"
+        );
+        // Clamped to the epoch
+        assert_eq!(
+            Timestamp(UNIX_EPOCH - Duration::from_secs(1)).to_string(),
+            "1970-01-01T00:00:00.000000000Z"
+        );
+    }
+
+    #[test]
+    fn summary_json() {
+        let mut summary = SummaryRecord::new(TARGET);
+        for rtt in [Some(Duration::from_micros(1500)), None, None] {
+            let record = match rtt {
+                Some(rtt) => PingRecord::reply(TARGET, 0, rtt),
+                None => PingRecord::timeout(TARGET, 0),
+            };
+            summary.add(&record.with_timestamp(UNIX_EPOCH));
+        }
+
+        let mut writer = JsonLinesWriter::new(Vec::new());
+        writer.write(&summary).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            concat!(
+                r#"{"timestamp":"1970-01-01T00:00:00.000000000Z","target":"192.0.2.1","#,
+                r#""sent":3,"received":1,"loss_pct":66.667,"min_ms":1.500000,"#,
+                r#""avg_ms":1.500000,"max_ms":1.500000}"#,
+                "\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,551 @@
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+
+use crate::{
+    IpVersion, MeasureManyStream, Pinger,
+    pinger::SendQueue,
+    rt::{Instant, Sleep},
+};
+
+/// Options for [`Pinger::measure_many_with_retries`]
+#[derive(Debug, Clone)]
+pub struct RetryOptions {
+    retries: u32,
+    timeout: Duration,
+    backoff: f64,
+}
+
+/// The final outcome of pinging a target, possibly more than once.
+///
+/// The round trip time is [serialized as nanoseconds](crate#serialization).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryResult<A> {
+    addr: A,
+    attempt: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos::option"))]
+    rtt: Option<Duration>,
+}
+
+/// A [`Stream`] of the final outcome of every target.
+///
+/// Unlike [`MeasureManyStream`], targets which don't reply in time are
+/// reported, and the stream ends once every target got a reply or ran out
+/// of retries.
+///
+/// Each attempt is sent as a separate round, so a late reply to an
+/// earlier attempt is still matched to the right ICMP echo request.
+/// A target coming up again while it's still waiting for a reply is skipped.
+///
+/// [`Stream`]: futures_core::Stream
+pub struct RetryMeasureManyStream<'a, V: IpVersion, I: Iterator<Item = V>> {
+    inner: Retrier<'a, V, IterSource<I>>,
+}
+
+/// Pings the targets coming from an [`AddressSource`], retrying the
+/// ones which don't reply in time
+pub(crate) struct Retrier<'a, V: IpVersion, S: AddressSource<V>> {
+    pinger: &'a Pinger<V>,
+    options: RetryOptions,
+    addresses: S,
+    exhausted: bool,
+    attempts: Vec<Attempt<'a, V>>,
+    /// The attempt each target still waiting for a reply is at
+    unresolved: HashMap<V, usize>,
+    lost: VecDeque<RetryResult<V>>,
+    sleep: Option<Sleep>,
+    finished: bool,
+}
+
+/// Where a [`Retrier`] takes the targets of the first attempt from
+pub(crate) trait AddressSource<V> {
+    /// Poll for the next target, `None` once there are no more
+    fn poll_next_address(&mut self, cx: &mut Context<'_>) -> Poll<Option<V>>;
+}
+
+pub(crate) struct IterSource<I>(I);
+
+/// The targets pinged for the same time
+///
+/// Targets are indexed by `pending`, which gives each of them an id. Entries
+/// of `waiting` and `sent` whose id doesn't match the one in `pending` are
+/// stale: they belong to targets which were forgotten, and are skipped
+/// once they reach the front. The front of both queues is never stale.
+struct Attempt<'a, V: IpVersion> {
+    stream: MeasureManyStream<'a, V, SendQueue<V>>,
+    timeout: Duration,
+    /// Id of each target still waiting for a reply
+    pending: HashMap<V, u64>,
+    next_id: u64,
+    /// Targets yet to be handed to `stream`
+    waiting: VecDeque<(V, u64)>,
+    /// Targets handed to `stream`, in the order they were sent, along
+    /// with when. The last one may not have been sent yet if `stream`
+    /// is blocked.
+    sent: VecDeque<(V, u64, Option<Instant>)>,
+}
+
+impl RetryOptions {
+    /// Construct the default `RetryOptions`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many more times a target which didn't reply is pinged.
+    ///
+    /// Defaults to 3.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set how long to wait for a reply to the first attempt.
+    ///
+    /// Defaults to 500 milliseconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the factor the timeout is multiplied by at every retry.
+    ///
+    /// Defaults to 1.5.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `backoff` is lower than `1.0` or isn't finite.
+    pub fn backoff(mut self, backoff: f64) -> Self {
+        assert!(
+            backoff.is_finite() && backoff >= 1.0,
+            "backoff must be at least 1.0"
+        );
+        self.backoff = backoff;
+        self
+    }
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            timeout: Duration::from_millis(500),
+            backoff: 1.5,
+        }
+    }
+}
+
+impl<A: Copy> RetryResult<A> {
+    /// Get the pinged address
+    pub fn addr(&self) -> A {
+        self.addr
+    }
+
+    /// Get the attempt which got a reply, starting from 1
+    ///
+    /// For targets which never replied, this is the number of attempts made.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Get the round trip time of the reply, if one was received
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    pub(crate) fn map_addr<B>(self, f: impl FnOnce(A) -> B) -> RetryResult<B> {
+        RetryResult {
+            addr: f(self.addr),
+            attempt: self.attempt,
+            rtt: self.rtt,
+        }
+    }
+}
+
+impl<'a, V: IpVersion, I: Iterator<Item = V>> RetryMeasureManyStream<'a, V, I> {
+    pub(crate) fn new(pinger: &'a Pinger<V>, addresses: I, options: RetryOptions) -> Self {
+        Self {
+            inner: Retrier::new(pinger, IterSource(addresses), options),
+        }
+    }
+
+    /// Poll for the final outcome of the next target
+    ///
+    /// An error is returned if the receive task stopped, see
+    /// [`MeasureManyStream::poll_next_unpin`]. The stream then ends
+    /// by returning `None`.
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<RetryResult<V>>>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<V: IpVersion, I: Iterator<Item = V> + Unpin> Stream for RetryMeasureManyStream<'_, V, I> {
+    type Item = io::Result<RetryResult<V>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+impl<'a, V: IpVersion, S: AddressSource<V>> Retrier<'a, V, S> {
+    pub(crate) fn new(pinger: &'a Pinger<V>, addresses: S, options: RetryOptions) -> Self {
+        let first = Attempt::new(pinger, options.timeout);
+
+        Self {
+            pinger,
+            options,
+            addresses,
+            exhausted: false,
+            attempts: vec![first],
+            unresolved: HashMap::new(),
+            lost: VecDeque::new(),
+            sleep: None,
+            finished: false,
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    pub(crate) fn source_mut(&mut self) -> &mut S {
+        &mut self.addresses
+    }
+
+    pub(crate) fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<RetryResult<V>>>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            self.expire(Instant::now());
+            self.poll_dispatch(cx);
+
+            match self.poll_replies(cx) {
+                Poll::Ready(Ok(result)) => return Poll::Ready(Some(Ok(result))),
+                Poll::Ready(Err(err)) => {
+                    self.finished = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Pending => {}
+            }
+
+            if let Some(result) = self.lost.pop_front() {
+                return Poll::Ready(Some(Ok(result)));
+            }
+            if self.exhausted && self.unresolved.is_empty() {
+                self.finished = true;
+                return Poll::Ready(None);
+            }
+
+            let Some(deadline) = self.attempts.iter().filter_map(Attempt::deadline).min() else {
+                return Poll::Pending;
+            };
+            let sleep = self.sleep.get_or_insert_with(|| Sleep::new(deadline));
+            sleep.reset(deadline);
+            if sleep.poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Hand out targets to the attempt they're at, until it stops
+    /// accepting ICMP echo requests
+    fn poll_dispatch(&mut self, cx: &mut Context<'_>) {
+        for (index, attempt) in self.attempts.iter_mut().enumerate() {
+            loop {
+                attempt.mark_sent();
+                if attempt.stream.is_send_blocked() {
+                    break;
+                }
+
+                let (addr, id) = match attempt.pop_waiting() {
+                    Some(waiting) => waiting,
+                    None if index == 0 && !self.exhausted => {
+                        match self.addresses.poll_next_address(cx) {
+                            // Duplicates are only pinged once at a time
+                            Poll::Ready(Some(addr)) if self.unresolved.contains_key(&addr) => {
+                                continue;
+                            }
+                            Poll::Ready(Some(addr)) => {
+                                self.unresolved.insert(addr, 0);
+                                (addr, attempt.track(addr))
+                            }
+                            Poll::Ready(None) => {
+                                self.exhausted = true;
+                                break;
+                            }
+                            Poll::Pending => break,
+                        }
+                    }
+                    None => break,
+                };
+
+                attempt.stream.send_queue_mut().0.push_back(addr);
+                attempt.sent.push_back((addr, id, None));
+                attempt.stream.poll_next_icmp_replies(cx);
+            }
+        }
+    }
+
+    /// Poll every attempt for a reply from a target which is still unresolved
+    fn poll_replies(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<RetryResult<V>>> {
+        for index in 0..self.attempts.len() {
+            loop {
+                let (addr, rtt) = match self.attempts[index].stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(reply))) => reply,
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+                    Poll::Ready(None) | Poll::Pending => break,
+                };
+
+                // A late reply to an earlier attempt resolves the target too
+                if let Some(current) = self.unresolved.remove(&addr) {
+                    self.forget(addr, current);
+                    return Poll::Ready(Ok(RetryResult {
+                        addr,
+                        attempt: attempt_number(index),
+                        rtt: Some(rtt),
+                    }));
+                }
+            }
+        }
+
+        Poll::Pending
+    }
+
+    /// Move the targets which didn't reply in time to their next attempt,
+    /// or into `lost` if they ran out of retries
+    fn expire(&mut self, now: Instant) {
+        for index in 0..self.attempts.len() {
+            while let Some(addr) = self.attempts[index].pop_expired(now) {
+                if index < self.options.retries as usize {
+                    if self.attempts.len() == index + 1 {
+                        let timeout = self.attempts[index].timeout.mul_f64(self.options.backoff);
+                        self.attempts.push(Attempt::new(self.pinger, timeout));
+                    }
+                    let next = &mut self.attempts[index + 1];
+                    let id = next.track(addr);
+                    next.waiting.push_back((addr, id));
+                    self.unresolved.insert(addr, index + 1);
+                } else {
+                    self.unresolved.remove(&addr);
+                    self.forget(addr, index);
+                    self.lost.push_back(RetryResult {
+                        addr,
+                        attempt: attempt_number(index),
+                        rtt: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Stop waiting for replies from `addr`, which was pinged by every
+    /// attempt up to `current`
+    fn forget(&mut self, addr: V, current: usize) {
+        self.attempts[current].forget(addr);
+        for attempt in &mut self.attempts[..=current] {
+            attempt.stream.forget(&addr);
+        }
+    }
+
+    /// Whether any attempt is still waiting for a reply from `addr`
+    #[cfg(all(test, feature = "sim", feature = "runtime-tokio"))]
+    pub(crate) fn is_waiting_for(&self, addr: &V) -> bool {
+        self.attempts.iter().any(|attempt| {
+            attempt.pending.contains_key(addr) || attempt.stream.sent_at(addr).is_some()
+        })
+    }
+}
+
+impl<V, I: Iterator<Item = V>> AddressSource<V> for IterSource<I> {
+    fn poll_next_address(&mut self, _cx: &mut Context<'_>) -> Poll<Option<V>> {
+        Poll::Ready(self.0.next())
+    }
+}
+
+impl<'a, V: IpVersion> Attempt<'a, V> {
+    fn new(pinger: &'a Pinger<V>, timeout: Duration) -> Self {
+        Self {
+            stream: pinger.measure_many(SendQueue(VecDeque::new())),
+            timeout,
+            pending: HashMap::new(),
+            next_id: 0,
+            waiting: VecDeque::new(),
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Start waiting for a reply from `addr`, returning its id
+    fn track(&mut self, addr: V) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(addr, id);
+        id
+    }
+
+    /// Take the next target to hand to `stream`
+    fn pop_waiting(&mut self) -> Option<(V, u64)> {
+        let waiting = self.waiting.pop_front();
+        self.discard_stale();
+        waiting
+    }
+
+    /// Record when the last target was sent, once it has been
+    fn mark_sent(&mut self) {
+        if self.stream.is_send_blocked() {
+            return;
+        }
+
+        if let Some((addr, _, sent_at @ None)) = self.sent.back_mut() {
+            *sent_at = Some(self.stream.sent_at(addr).unwrap_or_else(Instant::now));
+        }
+    }
+
+    /// When the oldest target waiting for a reply times out
+    fn deadline(&self) -> Option<Instant> {
+        let (_, _, sent_at) = self.sent.front()?;
+        sent_at.map(|sent_at| sent_at + self.timeout)
+    }
+
+    fn pop_expired(&mut self, now: Instant) -> Option<V> {
+        if self.deadline()? > now {
+            return None;
+        }
+
+        let (addr, _, _) = self.sent.pop_front()?;
+        self.pending.remove(&addr);
+        self.discard_stale();
+        Some(addr)
+    }
+
+    /// Stop waiting for a reply from `addr`
+    fn forget(&mut self, addr: V) {
+        if self.pending.remove(&addr).is_some() {
+            self.discard_stale();
+        }
+    }
+
+    /// Drop the stale entries from the front of `waiting` and `sent`
+    fn discard_stale(&mut self) {
+        let pending = &self.pending;
+        let is_stale = |addr: &V, id: &u64| pending.get(addr) != Some(id);
+        while self
+            .waiting
+            .front()
+            .is_some_and(|(addr, id)| is_stale(addr, id))
+        {
+            self.waiting.pop_front();
+        }
+        while self
+            .sent
+            .front()
+            .is_some_and(|(addr, id, _)| is_stale(addr, id))
+        {
+            self.sent.pop_front();
+        }
+    }
+}
+
+fn attempt_number(index: usize) -> u32 {
+    u32::try_from(index + 1).unwrap_or(u32::MAX)
+}
+
+#[cfg(all(test, feature = "sim", feature = "runtime-tokio"))]
+mod tests {
+    use std::{
+        future::poll_fn,
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use tokio::time::{self, Instant};
+
+    use super::{RetryOptions, RetryResult};
+    use crate::{
+        PingerOptions, V4Pinger,
+        sim::{SimHost, SimNetwork},
+    };
+
+    const FAST: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SLOW: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 2);
+    const LATE: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 3);
+    const LOST: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 99);
+
+    #[tokio::test(start_paused = true)]
+    async fn one_result_per_target() {
+        let network = SimNetwork::new(1);
+        network.add_host(
+            FAST.into(),
+            SimHost::new().latency(Duration::from_millis(10)),
+        );
+        network.add_host(
+            SLOW.into(),
+            SimHost::new().latency(Duration::from_millis(700)),
+        );
+        let (pinger, driver) = V4Pinger::with_transport(PingerOptions::new(), network.socket());
+        tokio::spawn(driver);
+
+        // LATE only comes up after its second attempt was lost
+        tokio::spawn({
+            let network = network.clone();
+            async move {
+                time::sleep(Duration::from_millis(600)).await;
+                let host = SimHost::new().latency(Duration::from_millis(10));
+                network.add_host(IpAddr::from(LATE), host);
+            }
+        });
+
+        let start = Instant::now();
+        let mut stream = pinger.measure_many_with_retries(
+            [FAST, SLOW, LATE, LOST, FAST].into_iter(),
+            RetryOptions::new()
+                .retries(2)
+                .timeout(Duration::from_millis(500))
+                .backoff(2.0),
+        );
+        let mut results = Vec::new();
+        while let Some(result) = poll_fn(|cx| stream.poll_next_unpin(cx)).await {
+            results.push((result.unwrap(), start.elapsed().as_millis()));
+        }
+
+        let result = |addr, attempt, rtt: Option<u64>| RetryResult {
+            addr,
+            attempt,
+            rtt: rtt.map(Duration::from_millis),
+        };
+        assert_eq!(
+            results,
+            [
+                // The duplicate is skipped, as FAST is still waiting for its reply
+                (result(FAST, 1, Some(10)), 10),
+                // The late reply to the first attempt wins over the second attempt
+                (result(SLOW, 1, Some(700)), 700),
+                (result(LATE, 3, Some(10)), 1510),
+                (result(LOST, 3, None), 3500),
+            ]
+        );
+
+        // Nothing is left behind
+        for attempt in &stream.inner.attempts {
+            assert!(attempt.pending.is_empty());
+            assert!(attempt.waiting.is_empty());
+            assert!(attempt.sent.is_empty());
+        }
+        for addr in [FAST, SLOW, LATE, LOST] {
+            assert!(!stream.inner.is_waiting_for(&addr));
+        }
+    }
+}
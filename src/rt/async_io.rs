@@ -0,0 +1,78 @@
+use std::{
+    io,
+    os::fd::{AsFd, AsRawFd},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+pub(crate) use std::time::Instant;
+
+use ::async_io::{Async, Timer};
+
+/// A file descriptor registered with the async-io reactor
+pub(crate) struct AsyncFd<T: AsFd + AsRawFd> {
+    fd: Async<T>,
+}
+
+impl<T: AsFd + AsRawFd> AsyncFd<T> {
+    pub(crate) fn new(inner: T) -> io::Result<Self> {
+        let fd = Async::new(inner)?;
+        Ok(Self { fd })
+    }
+
+    /// Run `op` once the file descriptor is readable, until
+    /// it stops failing with [`io::ErrorKind::WouldBlock`]
+    pub(crate) fn poll_read_with<R>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            match op(self.fd.get_ref()) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.fd.poll_readable(cx))?;
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+
+    /// Run `op` once the file descriptor is writable, until
+    /// it stops failing with [`io::ErrorKind::WouldBlock`]
+    #[cfg_attr(feature = "io-uring", allow(dead_code))]
+    pub(crate) fn poll_write_with<R>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            match op(self.fd.get_ref()) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.fd.poll_writable(cx))?;
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+}
+
+/// A resettable timer
+pub(crate) struct Sleep {
+    timer: Timer,
+}
+
+impl Sleep {
+    pub(crate) fn new(deadline: Instant) -> Self {
+        Self {
+            timer: Timer::at(deadline),
+        }
+    }
+
+    pub(crate) fn reset(&mut self, deadline: Instant) {
+        self.timer.set_at(deadline);
+    }
+
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.timer).poll(cx).map(|_fired_at| ())
+    }
+}
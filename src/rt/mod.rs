@@ -0,0 +1,11 @@
+//! Reactor and timer of the async runtime selected through cargo features
+
+#[cfg(all(feature = "runtime-async-io", not(feature = "runtime-tokio")))]
+pub(crate) use self::async_io::{AsyncFd, Instant, Sleep};
+#[cfg(feature = "runtime-tokio")]
+pub(crate) use self::tokio::{AsyncFd, Instant, Sleep};
+
+#[cfg(all(feature = "runtime-async-io", not(feature = "runtime-tokio")))]
+mod async_io;
+#[cfg(feature = "runtime-tokio")]
+mod tokio;
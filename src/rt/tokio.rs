@@ -0,0 +1,76 @@
+use std::{
+    io,
+    os::fd::{AsFd, AsRawFd},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+pub(crate) use ::tokio::time::Instant;
+
+/// A file descriptor registered with the tokio reactor
+pub(crate) struct AsyncFd<T: AsFd + AsRawFd> {
+    fd: ::tokio::io::unix::AsyncFd<T>,
+}
+
+impl<T: AsFd + AsRawFd> AsyncFd<T> {
+    pub(crate) fn new(inner: T) -> io::Result<Self> {
+        let fd = ::tokio::io::unix::AsyncFd::new(inner)?;
+        Ok(Self { fd })
+    }
+
+    /// Run `op` once the file descriptor is readable, until
+    /// it stops failing with [`io::ErrorKind::WouldBlock`]
+    pub(crate) fn poll_read_with<R>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+
+            match guard.try_io(|inner| op(inner.get_ref())) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Run `op` once the file descriptor is writable, until
+    /// it stops failing with [`io::ErrorKind::WouldBlock`]
+    #[cfg_attr(feature = "io-uring", allow(dead_code))]
+    pub(crate) fn poll_write_with<R>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+
+            match guard.try_io(|inner| op(inner.get_ref())) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// A resettable timer
+pub(crate) struct Sleep {
+    sleep: Pin<Box<::tokio::time::Sleep>>,
+}
+
+impl Sleep {
+    pub(crate) fn new(deadline: Instant) -> Self {
+        Self {
+            sleep: Box::pin(::tokio::time::sleep_until(deadline)),
+        }
+    }
+
+    pub(crate) fn reset(&mut self, deadline: Instant) {
+        self.sleep.as_mut().reset(deadline);
+    }
+
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.sleep.as_mut().poll(cx)
+    }
+}
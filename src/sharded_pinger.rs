@@ -0,0 +1,202 @@
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+#[cfg(feature = "runtime-tokio")]
+use std::thread;
+use std::{
+    collections::VecDeque,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    num::NonZeroUsize,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+
+use crate::{IpVersion, MeasureManyStream, Pinger, PingerDriver, PingerOptions, pinger::SendQueue};
+
+/// A sharded pinger for IPv4 addresses
+pub type V4ShardedPinger = ShardedPinger<Ipv4Addr>;
+/// A sharded pinger for IPv6 addresses
+pub type V6ShardedPinger = ShardedPinger<Ipv6Addr>;
+
+/// A pinger spreading its targets over multiple [`Pinger`]s.
+///
+/// Each shard has its own ICMP socket, identifier and receive task, so that
+/// replies can be processed by multiple cores at the same time. Targets
+/// are always assigned to the same shard.
+pub struct ShardedPinger<V: IpVersion> {
+    shards: Box<[Pinger<V>]>,
+}
+
+impl<V: IpVersion> ShardedPinger<V> {
+    /// Construct a new `ShardedPinger` with one shard per available core.
+    ///
+    /// The same considerations of [`Pinger::new`] apply.
+    #[cfg(feature = "runtime-tokio")]
+    pub fn new() -> io::Result<Self> {
+        let shards = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+        Self::with_shards(shards)
+    }
+
+    /// Construct a new `ShardedPinger` with `shards` shards.
+    ///
+    /// The same considerations of [`Pinger::new`] apply.
+    #[cfg(feature = "runtime-tokio")]
+    pub fn with_shards(shards: NonZeroUsize) -> io::Result<Self> {
+        let shards = (0..shards.get())
+            .map(|_| Pinger::new())
+            .collect::<io::Result<_>>()?;
+        Ok(Self { shards })
+    }
+
+    /// Construct a new `ShardedPinger` with `shards` shards and the given
+    /// `options`, leaving it to the caller to run the receive task of
+    /// every shard.
+    ///
+    /// See [`Pinger::with_driver`].
+    pub fn with_drivers(
+        shards: NonZeroUsize,
+        options: PingerOptions,
+    ) -> io::Result<(Self, Vec<PingerDriver<V>>)> {
+        let (shards, drivers) = (0..shards.get())
+            .map(|_| Pinger::with_driver(options.clone()))
+            .collect::<io::Result<(Vec<_>, Vec<_>)>>()?;
+        Ok((
+            Self {
+                shards: shards.into_boxed_slice(),
+            },
+            drivers,
+        ))
+    }
+
+    /// Get the number of shards
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Stop the receive task of every shard, waiting for them to exit
+    ///
+    /// See [`Pinger::shutdown`].
+    pub async fn shutdown(&self) {
+        for shard in &self.shards {
+            shard.shutdown().await;
+        }
+    }
+
+    /// Ping `addresses`
+    ///
+    /// Creates [`ShardedMeasureManyStream`] which **lazily** sends ping
+    /// requests and [`Stream`]s the responses as they arrive.
+    ///
+    /// [`Stream`]: futures_core::Stream
+    pub fn measure_many<I>(&self, addresses: I) -> ShardedMeasureManyStream<'_, V, I>
+    where
+        I: Iterator<Item = V>,
+    {
+        let shards = self
+            .shards
+            .iter()
+            .map(|shard| shard.measure_many(SendQueue(VecDeque::new())))
+            .collect();
+
+        ShardedMeasureManyStream {
+            addresses,
+            blocked_addr: None,
+            shards,
+            next_shard: 0,
+        }
+    }
+}
+
+/// A [`Stream`] of ping responses.
+///
+/// No kind of `rtt` timeout is implemented, so an external mechanism
+/// like [`tokio::time::timeout`] should be used to prevent the program
+/// from hanging indefinitely.
+///
+/// Replies are queued up the same way as for [`MeasureManyStream`].
+///
+/// [`Stream`]: futures_core::Stream
+/// [`tokio::time::timeout`]: https://docs.rs/tokio/latest/tokio/time/fn.timeout.html
+pub struct ShardedMeasureManyStream<'a, V: IpVersion, I: Iterator<Item = V>> {
+    addresses: I,
+    blocked_addr: Option<V>,
+    shards: Box<[MeasureManyStream<'a, V, SendQueue<V>>]>,
+    next_shard: usize,
+}
+
+impl<V: IpVersion, I: Iterator<Item = V>> ShardedMeasureManyStream<'_, V, I> {
+    /// Poll for the next ping response
+    ///
+    /// An error is returned for each shard whose receive task stopped.
+    /// See [`MeasureManyStream::poll_next_unpin`].
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<(V, Duration)>>> {
+        self.poll_dispatch(cx);
+
+        // Start from a different shard every time so that
+        // a busy shard can't starve the others
+        let len = self.shards.len();
+        let mut finished = 0;
+        for i in 0..len {
+            let shard = (self.next_shard + i) % len;
+            match self.shards[shard].poll_next_unpin(cx) {
+                Poll::Ready(Some(result)) => {
+                    self.next_shard = (shard + 1) % len;
+                    return Poll::Ready(Some(result));
+                }
+                Poll::Ready(None) => finished += 1,
+                Poll::Pending => {}
+            }
+        }
+
+        if finished == len {
+            return Poll::Ready(None);
+        }
+
+        Poll::Pending
+    }
+
+    /// Hand out addresses to their shard until one of them stops accepting
+    /// ICMP echo requests
+    fn poll_dispatch(&mut self, cx: &mut Context<'_>) {
+        while let Some(addr) = self.blocked_addr.take().or_else(|| self.addresses.next()) {
+            let shard = &mut self.shards[shard_of(addr, self.shards.len())];
+            if shard.is_send_blocked() {
+                self.blocked_addr = Some(addr);
+                break;
+            }
+
+            shard.send_queue_mut().0.push_back(addr);
+            shard.poll_next_icmp_replies(cx);
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<V: IpVersion, I: Iterator<Item = V> + Unpin> Stream for ShardedMeasureManyStream<'_, V, I> {
+    type Item = io::Result<(V, Duration)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+/// Map `addr` to one of `shards` shards, always giving the same result
+fn shard_of<V: IpVersion>(addr: V, shards: usize) -> usize {
+    let hash = match addr.into() {
+        IpAddr::V4(v4) => u64::from(v4.to_bits()),
+        IpAddr::V6(v6) => {
+            let bits = v6.to_bits();
+            (bits >> 64) as u64 ^ bits as u64
+        }
+    };
+
+    // Fibonacci hashing, followed by a multiply-shift range reduction
+    let hash = hash.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    ((u128::from(hash) * shards as u128) >> 64) as usize
+}
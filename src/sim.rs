@@ -0,0 +1,718 @@
+//! Simulated in-memory network for deterministic tests
+//!
+//! [`SimNetwork`] answers the ICMP echo requests sent through its
+//! [`SimSocket`]s according to the [`SimHost`] configured for each
+//! destination, without touching the real network. Time is taken from
+//! the selected runtime, so with `runtime-tokio` the simulation runs
+//! under [paused time] and tests don't have to wait for replies in real time.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use std::{net::Ipv4Addr, time::Duration};
+//!
+//! use massping::{
+//!     PingerOptions, V4Pinger,
+//!     sim::{SimHost, SimNetwork},
+//! };
+//!
+//! let network = SimNetwork::new(42);
+//! network.add_host(
+//!     Ipv4Addr::new(192, 0, 2, 1).into(),
+//!     SimHost::new()
+//!         .latency(Duration::from_millis(20))
+//!         .loss(0.1),
+//! );
+//!
+//! let (pinger, driver) = V4Pinger::with_transport(PingerOptions::default(), network.socket());
+//! tokio::spawn(driver);
+//! # }
+//! ```
+//!
+//! [paused time]: https://docs.rs/tokio/latest/tokio/time/fn.pause.html
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    io,
+    mem::MaybeUninit,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use pnet_packet::{icmpv6::Icmpv6Packet, util};
+use rand::{Rng as _, SeedableRng as _, rngs::SmallRng};
+
+use crate::{
+    rt::{Instant, Sleep},
+    transport::Transport,
+};
+
+/// Local address of the simulated sockets
+const LOCAL_V4: Ipv4Addr = Ipv4Addr::LOCALHOST;
+/// Local address of the simulated sockets
+const LOCAL_V6: Ipv6Addr = Ipv6Addr::LOCALHOST;
+/// Hop limit of the IP headers embedded into ICMP error messages
+const HOP_LIMIT: u8 = 64;
+/// Maximum length of an `ICMPv6` error message, as described by RFC 4443
+const ICMPV6_ERROR_MAX_LEN: usize = 1280 - 40;
+
+/// A simulated network of hosts answering ICMP echo requests.
+///
+/// Cloning a `SimNetwork` gives a handle to the same network, so hosts
+/// can be added, changed or removed while pingers are using it.
+/// Echo requests sent to addresses without a [`SimHost`] are lost.
+#[derive(Clone)]
+pub struct SimNetwork {
+    inner: Arc<Mutex<InnerNetwork>>,
+}
+
+struct InnerNetwork {
+    hosts: HashMap<IpAddr, SimHost>,
+    rng: SmallRng,
+    next_identifier: u16,
+}
+
+/// The behaviour of a simulated host.
+#[derive(Debug, Clone)]
+pub struct SimHost {
+    latency: Duration,
+    jitter: Duration,
+    loss: f64,
+    duplication: f64,
+    reordering: f64,
+    reordering_delay: Duration,
+    icmp_error: Option<(IpAddr, SimIcmpError)>,
+}
+
+/// An ICMP error message sent back instead of an echo reply.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SimIcmpError {
+    /// Destination Unreachable with the given code
+    DestinationUnreachable { code: u8 },
+    /// Time Exceeded with the given code
+    TimeExceeded { code: u8 },
+    /// Parameter Problem pointing to the given offset of the original packet
+    ParameterProblem { pointer: u32 },
+    /// Packet Too Big for IPv6, or Destination Unreachable with the
+    /// "fragmentation needed" code for IPv4
+    PacketTooBig { mtu: u32 },
+    /// Redirect to the given gateway
+    Redirect { gateway: IpAddr },
+}
+
+/// A [`Transport`] connected to a [`SimNetwork`].
+pub struct SimSocket {
+    network: SimNetwork,
+    identifier: u16,
+    state: Mutex<SocketState>,
+}
+
+struct SocketState {
+    queue: BinaryHeap<Reverse<Delivery>>,
+    next_seq: u64,
+    timer: Option<Sleep>,
+    waker: Option<Waker>,
+}
+
+/// A packet waiting to be received by a [`SimSocket`]
+struct Delivery {
+    at: Instant,
+    /// Breaks ties between packets delivered at the same time, in send order
+    seq: u64,
+    source: IpAddr,
+    packet: Box<[u8]>,
+}
+
+impl SimNetwork {
+    /// Construct an empty network
+    ///
+    /// All random decisions are taken from a generator initialized
+    /// with `seed`, so the same sequence of operations always gives
+    /// the same result.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(InnerNetwork {
+                hosts: HashMap::new(),
+                rng: SmallRng::seed_from_u64(seed),
+                next_identifier: 1,
+            })),
+        }
+    }
+
+    /// Add `addr` to the network, replacing its previous behaviour if it
+    /// already was part of it
+    pub fn add_host(&self, addr: IpAddr, host: SimHost) {
+        self.inner.lock().unwrap().hosts.insert(addr, host);
+    }
+
+    /// Remove `addr` from the network, so that every
+    /// echo request sent to it from now on is lost
+    pub fn remove_host(&self, addr: IpAddr) -> Option<SimHost> {
+        self.inner.lock().unwrap().hosts.remove(&addr)
+    }
+
+    /// Create a new socket connected to the network
+    ///
+    /// Each socket has its own identifier, which replaces the one of the
+    /// echo requests sent through it, like Linux does for ICMP sockets.
+    pub fn socket(&self) -> SimSocket {
+        let identifier = {
+            let mut inner = self.inner.lock().unwrap();
+            let identifier = inner.next_identifier;
+            inner.next_identifier = inner.next_identifier.wrapping_add(1);
+            identifier
+        };
+
+        SimSocket {
+            network: self.clone(),
+            identifier,
+            state: Mutex::new(SocketState {
+                queue: BinaryHeap::new(),
+                next_seq: 0,
+                timer: None,
+                waker: None,
+            }),
+        }
+    }
+}
+
+impl SimHost {
+    /// Construct a host replying to every echo request immediately
+    pub fn new() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplication: 0.0,
+            reordering: 0.0,
+            reordering_delay: Duration::ZERO,
+            icmp_error: None,
+        }
+    }
+
+    /// Set the round trip time of the replies
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Add up to `jitter` to the round trip time of each reply
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the probability of an echo request or its reply being lost
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probability` isn't between `0.0` and `1.0`.
+    pub fn loss(mut self, probability: f64) -> Self {
+        assert_probability(probability);
+        self.loss = probability;
+        self
+    }
+
+    /// Set the probability of a reply being received twice
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probability` isn't between `0.0` and `1.0`.
+    pub fn duplication(mut self, probability: f64) -> Self {
+        assert_probability(probability);
+        self.duplication = probability;
+        self
+    }
+
+    /// Set the probability of a reply being held back for `delay`,
+    /// making it arrive after the replies to later echo requests
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probability` isn't between `0.0` and `1.0`.
+    pub fn reordering(mut self, probability: f64, delay: Duration) -> Self {
+        assert_probability(probability);
+        self.reordering = probability;
+        self.reordering_delay = delay;
+        self
+    }
+
+    /// Answer every echo request with an ICMP error sent by `source`
+    ///
+    /// The error is delayed the same way as echo replies would be.
+    /// `source` must have the same IP version as the host, otherwise
+    /// the error is lost.
+    pub fn icmp_error(mut self, source: IpAddr, error: SimIcmpError) -> Self {
+        self.icmp_error = Some((source, error));
+        self
+    }
+
+    fn delay(&self, rng: &mut SmallRng) -> Duration {
+        let mut delay = self.latency;
+        if !self.jitter.is_zero() {
+            delay += self.jitter.mul_f64(rng.random::<f64>());
+        }
+        if rng.random_bool(self.reordering) {
+            delay += self.reordering_delay;
+        }
+        delay
+    }
+}
+
+impl Default for SimHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimSocket {
+    fn schedule(&self, deliveries: impl IntoIterator<Item = (Duration, IpAddr, Box<[u8]>)>) {
+        let now = Instant::now();
+
+        let mut state = self.state.lock().unwrap();
+        for (delay, source, packet) in deliveries {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.queue.push(Reverse(Delivery {
+                at: now + delay,
+                seq,
+                source,
+                packet,
+            }));
+        }
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Transport for SimSocket {
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [MaybeUninit<u8>],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            let Some(Reverse(next)) = state.queue.peek() else {
+                state.timer = None;
+                break;
+            };
+
+            let at = next.at;
+            if at <= Instant::now() {
+                let Reverse(delivery) = state.queue.pop().unwrap();
+
+                let len = delivery.packet.len().min(buf.len());
+                for (dst, &src) in buf.iter_mut().zip(&delivery.packet[..len]) {
+                    dst.write(src);
+                }
+                return Poll::Ready(Ok((len, SocketAddr::new(delivery.source, 0))));
+            }
+
+            let timer = state.timer.get_or_insert_with(|| Sleep::new(at));
+            timer.reset(at);
+            if timer.poll(cx).is_pending() {
+                break;
+            }
+        }
+
+        match &mut state.waker {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => state.waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+
+    fn poll_send_to(
+        &self,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        let target = addr.ip();
+        let echo_request_type = if target.is_ipv4() { 8 } else { 128 };
+        if buf.len() < 8 || buf[0] != echo_request_type || buf[1] != 0 {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not an ICMP echo request",
+            )));
+        }
+
+        let mut request = Box::<[u8]>::from(buf);
+        request[4..6].copy_from_slice(&self.identifier.to_be_bytes());
+        set_checksum(&mut request, target, local_addr(target));
+
+        let deliveries = {
+            let mut inner = self.network.inner.lock().unwrap();
+            let InnerNetwork { hosts, rng, .. } = &mut *inner;
+
+            let mut deliveries = Vec::new();
+            if let Some(host) = hosts.get(&target) {
+                if !rng.random_bool(host.loss) {
+                    let copies = if rng.random_bool(host.duplication) {
+                        2
+                    } else {
+                        1
+                    };
+
+                    for _ in 0..copies {
+                        let reply = match host.icmp_error {
+                            Some((source, error)) => {
+                                icmp_error(source, target, error, &request).map(|e| (source, e))
+                            }
+                            None => Some((target, echo_reply(target, &request))),
+                        };
+                        if let Some((source, packet)) = reply {
+                            deliveries.push((host.delay(rng), source, packet));
+                        }
+                    }
+                }
+            }
+            deliveries
+        };
+        self.schedule(deliveries);
+
+        Poll::Ready(Ok(buf.len()))
+    }
+}
+
+impl PartialEq for Delivery {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delivery {}
+
+impl PartialOrd for Delivery {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delivery {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+/// Build the echo reply `target` sends back for `request`
+fn echo_reply(target: IpAddr, request: &[u8]) -> Box<[u8]> {
+    let mut reply = Box::<[u8]>::from(request);
+    reply[0] = if target.is_ipv4() { 0 } else { 129 };
+    set_checksum(&mut reply, local_addr(target), target);
+    reply
+}
+
+/// Build the ICMP error `source` sends back for `request`, which was sent to `target`
+fn icmp_error(
+    source: IpAddr,
+    target: IpAddr,
+    error: SimIcmpError,
+    request: &[u8],
+) -> Option<Box<[u8]>> {
+    if source.is_ipv4() != target.is_ipv4() {
+        return None;
+    }
+
+    let (icmp_type, code, rest) = match (target, error) {
+        (IpAddr::V4(_), SimIcmpError::DestinationUnreachable { code }) => (3, code, [0; 4]),
+        (IpAddr::V4(_), SimIcmpError::TimeExceeded { code }) => (11, code, [0; 4]),
+        (IpAddr::V4(_), SimIcmpError::ParameterProblem { pointer }) => {
+            (12, 0, [pointer as u8, 0, 0, 0])
+        }
+        (IpAddr::V4(_), SimIcmpError::PacketTooBig { mtu }) => {
+            let [_, _, hi, lo] = mtu.to_be_bytes();
+            (3, 4, [0, 0, hi, lo])
+        }
+        (IpAddr::V4(_), SimIcmpError::Redirect { gateway }) => match gateway {
+            IpAddr::V4(gateway) => (5, 1, gateway.octets()),
+            IpAddr::V6(_) => return None,
+        },
+        (IpAddr::V6(_), SimIcmpError::DestinationUnreachable { code }) => (1, code, [0; 4]),
+        (IpAddr::V6(_), SimIcmpError::PacketTooBig { mtu }) => (2, 0, mtu.to_be_bytes()),
+        (IpAddr::V6(_), SimIcmpError::TimeExceeded { code }) => (3, code, [0; 4]),
+        (IpAddr::V6(_), SimIcmpError::ParameterProblem { pointer }) => {
+            (4, 0, pointer.to_be_bytes())
+        }
+        (IpAddr::V6(target), SimIcmpError::Redirect { gateway }) => {
+            let IpAddr::V6(gateway) = gateway else {
+                return None;
+            };
+            return Some(redirect_v6(source, target, gateway, request));
+        }
+    };
+
+    let local = local_addr(target);
+    let mut packet = vec![icmp_type, code, 0, 0];
+    packet.extend_from_slice(&rest);
+    match (local, target) {
+        (IpAddr::V4(local), IpAddr::V4(target)) => {
+            // RFC 792 only requires the first 8 bytes of the original datagram
+            let request = &request[..8];
+            packet.extend_from_slice(&ipv4_header(local, target, request.len()));
+            packet.extend_from_slice(request);
+        }
+        (IpAddr::V6(local), IpAddr::V6(target)) => {
+            let header = ipv6_header(local, target, request.len());
+            let len = request
+                .len()
+                .min(ICMPV6_ERROR_MAX_LEN - packet.len() - header.len());
+            packet.extend_from_slice(&header);
+            packet.extend_from_slice(&request[..len]);
+        }
+        _ => unreachable!(),
+    }
+
+    let mut packet = packet.into_boxed_slice();
+    set_checksum(&mut packet, source, local);
+    Some(packet)
+}
+
+/// Build an `ICMPv6` Redirect message with a Redirected Header option,
+/// as described by RFC 4861
+fn redirect_v6(source: IpAddr, target: Ipv6Addr, gateway: Ipv6Addr, request: &[u8]) -> Box<[u8]> {
+    let mut packet = vec![137, 0, 0, 0, 0, 0, 0, 0];
+    packet.extend_from_slice(&gateway.octets());
+    packet.extend_from_slice(&target.octets());
+
+    let header = ipv6_header(LOCAL_V6, target, request.len());
+    let max_len = ICMPV6_ERROR_MAX_LEN - packet.len() - 8 - header.len();
+    let request = &request[..request.len().min(max_len) / 8 * 8];
+    let option_len = 8 + header.len() + request.len();
+    packet.extend_from_slice(&[4, (option_len / 8) as u8, 0, 0, 0, 0, 0, 0]);
+    packet.extend_from_slice(&header);
+    packet.extend_from_slice(request);
+
+    let mut packet = packet.into_boxed_slice();
+    set_checksum(&mut packet, source, IpAddr::V6(LOCAL_V6));
+    packet
+}
+
+fn ipv4_header(source: Ipv4Addr, destination: Ipv4Addr, payload_len: usize) -> [u8; 20] {
+    let mut header = [0; 20];
+    header[0] = 0x45;
+    header[2..4].copy_from_slice(&((20 + payload_len) as u16).to_be_bytes());
+    header[8] = HOP_LIMIT;
+    header[9] = 1;
+    header[12..16].copy_from_slice(&source.octets());
+    header[16..20].copy_from_slice(&destination.octets());
+    let checksum = util::checksum(&header, 5);
+    header[10..12].copy_from_slice(&checksum.to_be_bytes());
+    header
+}
+
+fn ipv6_header(source: Ipv6Addr, destination: Ipv6Addr, payload_len: usize) -> [u8; 40] {
+    let mut header = [0; 40];
+    header[0] = 0x60;
+    header[4..6].copy_from_slice(&(payload_len as u16).to_be_bytes());
+    header[6] = 58;
+    header[7] = HOP_LIMIT;
+    header[8..24].copy_from_slice(&source.octets());
+    header[24..40].copy_from_slice(&destination.octets());
+    header
+}
+
+/// Compute the checksum of the ICMP message `packet`, sent from `source` to `destination`
+fn set_checksum(packet: &mut [u8], source: IpAddr, destination: IpAddr) {
+    packet[2..4].fill(0);
+    let checksum = match (source, destination) {
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            let icmp_packet = Icmpv6Packet::new(packet).unwrap();
+            pnet_packet::icmpv6::checksum(&icmp_packet, &source, &destination)
+        }
+        _ => util::checksum(packet, 1),
+    };
+    packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+}
+
+fn local_addr(target: IpAddr) -> IpAddr {
+    match target {
+        IpAddr::V4(_) => IpAddr::V4(LOCAL_V4),
+        IpAddr::V6(_) => IpAddr::V6(LOCAL_V6),
+    }
+}
+
+fn assert_probability(probability: f64) {
+    assert!(
+        (0.0..=1.0).contains(&probability),
+        "probability must be between 0.0 and 1.0"
+    );
+}
+
+#[cfg(all(test, feature = "runtime-tokio"))]
+mod tests {
+    use std::{
+        future::poll_fn,
+        net::{Ipv4Addr, Ipv6Addr},
+        time::Duration,
+    };
+
+    use tokio::time;
+
+    use super::{SimHost, SimIcmpError, SimNetwork};
+    use crate::{IpVersion, Pinger, PingerOptions};
+
+    /// Ping `targets` once, collecting the replies received within a second
+    async fn measure<V: IpVersion>(network: &SimNetwork, targets: &[V]) -> Vec<(V, Duration)> {
+        let (pinger, driver) = Pinger::<V>::with_transport(PingerOptions::new(), network.socket());
+        tokio::spawn(driver);
+
+        let mut stream = pinger.measure_many(targets.iter().copied());
+        let mut replies = Vec::new();
+        let _ = time::timeout(Duration::from_secs(1), async {
+            while let Some(reply) = poll_fn(|cx| stream.poll_next_unpin(cx)).await {
+                replies.push(reply.unwrap());
+            }
+        })
+        .await;
+        replies
+    }
+
+    /// Add a host at `10.0.0.0 + index` for each index, returning their addresses
+    fn add_hosts(network: &SimNetwork, count: u32, host: &SimHost) -> Vec<Ipv4Addr> {
+        (0..count)
+            .map(|index| {
+                let addr = Ipv4Addr::from(u32::from(Ipv4Addr::new(10, 0, 0, 0)) + index);
+                network.add_host(addr.into(), host.clone());
+                addr
+            })
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latency() {
+        let network = SimNetwork::new(1);
+        let v4 = Ipv4Addr::new(192, 0, 2, 1);
+        let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let latency = Duration::from_millis(20);
+        network.add_host(v4.into(), SimHost::new().latency(latency));
+        network.add_host(v6.into(), SimHost::new().latency(latency));
+
+        assert_eq!(measure(&network, &[v4]).await, [(v4, latency)]);
+        assert_eq!(measure(&network, &[v6]).await, [(v6, latency)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn jitter() {
+        let network = SimNetwork::new(1);
+        let host = SimHost::new()
+            .latency(Duration::from_millis(20))
+            .jitter(Duration::from_millis(10));
+        let targets = add_hosts(&network, 100, &host);
+
+        let replies = measure(&network, &targets).await;
+        assert_eq!(replies.len(), targets.len());
+        assert!(replies.iter().all(|&(_, rtt)| {
+            (Duration::from_millis(20)..=Duration::from_millis(30)).contains(&rtt)
+        }));
+        assert!(replies.iter().any(|&(_, rtt)| rtt != replies[0].1));
+        assert!(replies.is_sorted_by_key(|&(_, rtt)| rtt));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn loss() {
+        let network = SimNetwork::new(1);
+        let lossy = add_hosts(&network, 1000, &SimHost::new().loss(0.3));
+
+        let replies = measure(&network, &lossy).await;
+        assert!((600..800).contains(&replies.len()), "{}", replies.len());
+
+        network.add_host(lossy[0].into(), SimHost::new().loss(1.0));
+        assert!(measure(&network, &lossy[..1]).await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn duplication() {
+        let network = SimNetwork::new(1);
+        let targets = add_hosts(&network, 10, &SimHost::new().duplication(1.0));
+
+        // Every target is reported once, the duplicates being ignored
+        let mut replies = measure(&network, &targets).await;
+        replies.sort();
+        assert_eq!(
+            replies,
+            targets
+                .iter()
+                .map(|&target| (target, Duration::ZERO))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reordering() {
+        let network = SimNetwork::new(1);
+        let late = Ipv4Addr::new(192, 0, 2, 1);
+        let early = Ipv4Addr::new(192, 0, 2, 2);
+        network.add_host(
+            late.into(),
+            SimHost::new()
+                .latency(Duration::from_millis(10))
+                .reordering(1.0, Duration::from_millis(50)),
+        );
+        network.add_host(
+            early.into(),
+            SimHost::new().latency(Duration::from_millis(10)),
+        );
+
+        assert_eq!(
+            measure(&network, &[late, early]).await,
+            [
+                (early, Duration::from_millis(10)),
+                (late, Duration::from_millis(60))
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn icmp_errors() {
+        let network = SimNetwork::new(1);
+        let router_v4 = Ipv4Addr::new(198, 51, 100, 1);
+        let unreachable_v4 = Ipv4Addr::new(192, 0, 2, 1);
+        let reachable_v4 = Ipv4Addr::new(192, 0, 2, 2);
+        let router_v6 = Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 1);
+        let unreachable_v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        network.add_host(
+            unreachable_v4.into(),
+            SimHost::new().icmp_error(
+                router_v4.into(),
+                SimIcmpError::DestinationUnreachable { code: 1 },
+            ),
+        );
+        network.add_host(reachable_v4.into(), SimHost::new());
+        network.add_host(
+            unreachable_v6.into(),
+            SimHost::new().icmp_error(router_v6.into(), SimIcmpError::TimeExceeded { code: 0 }),
+        );
+
+        // Errors aren't mistaken for replies
+        assert_eq!(
+            measure(&network, &[unreachable_v4, reachable_v4]).await,
+            [(reachable_v4, Duration::ZERO)]
+        );
+        assert!(measure(&network, &[unreachable_v6]).await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn same_seed() {
+        async fn run(seed: u64) -> Vec<(Ipv4Addr, Duration)> {
+            let network = SimNetwork::new(seed);
+            let host = SimHost::new()
+                .latency(Duration::from_millis(20))
+                .jitter(Duration::from_millis(10))
+                .loss(0.2)
+                .reordering(0.1, Duration::from_millis(30));
+            let targets = add_hosts(&network, 100, &host);
+            measure(&network, &targets).await
+        }
+
+        let first = run(42).await;
+        assert_eq!(run(42).await, first);
+        assert_ne!(run(43).await, first);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    io,
+    mem::MaybeUninit,
+    net::SocketAddr,
+    task::{Context, Poll},
+};
+
+use super::BaseSocket;
+use crate::{IpVersion, rt::AsyncFd};
+
+pub(crate) struct Socket {
+    fd: AsyncFd<BaseSocket>,
+}
+
+impl Socket {
+    /// Sends go out immediately
+    pub(crate) const QUEUES_SENDS: bool = false;
+
+    pub(crate) fn new_icmp<V: IpVersion>() -> io::Result<Self> {
+        Self::from_base(BaseSocket::new_icmp::<V>()?)
+    }
+
+    pub(crate) fn new_raw_icmpv4() -> io::Result<Self> {
+        Self::from_base(BaseSocket::new_raw_icmpv4()?)
+    }
+
+    fn from_base(base: BaseSocket) -> io::Result<Self> {
+        // The reactor may report stale readiness, which must not block it
+        base.set_nonblocking(true)?;
+
+        let fd = AsyncFd::new(base)?;
+        Ok(Self { fd })
+    }
+
+    pub(crate) fn poll_read(
+        &self,
+        buf: &mut [MaybeUninit<u8>],
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        self.fd.poll_read_with(cx, |inner| inner.recv(buf))
+    }
+
+    pub(crate) fn poll_write_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        self.fd
+            .poll_write_with(cx, |inner| inner.send_to(buf, addr))
+    }
+
+    /// Sends go out immediately, there's nothing to flush
+    pub(crate) fn poll_flush(&self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -2,7 +2,11 @@ use std::{
     io,
     mem::MaybeUninit,
     net::SocketAddr,
-    os::unix::io::{AsRawFd, RawFd},
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::io::{AsRawFd, RawFd},
+    },
+    time::Duration,
 };
 
 use socket2::{Domain, Protocol, SockAddr, Type};
@@ -24,6 +28,16 @@ impl BaseSocket {
         Ok(Self { socket })
     }
 
+    /// Construct a raw ICMP socket for IPv4, which requires `CAP_NET_RAW`
+    ///
+    /// Unlike ICMP sockets, raw sockets can send any ICMP message and
+    /// receive every ICMP message addressed to the host, with its IP header.
+    pub(crate) fn new_raw_icmpv4() -> io::Result<Self> {
+        let socket = socket2::Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?;
+
+        Ok(Self { socket })
+    }
+
     fn new_icmpv4() -> io::Result<socket2::Socket> {
         socket2::Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))
     }
@@ -32,6 +46,15 @@ impl BaseSocket {
         socket2::Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::ICMPV6))
     }
 
+    #[cfg_attr(feature = "io-uring", allow(dead_code))]
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
     pub(crate) fn recv(&self, buf: &mut [MaybeUninit<u8>]) -> io::Result<(usize, SocketAddr)> {
         self.socket.recv_from(buf).map(|(filled, source)| {
             (
@@ -53,3 +76,9 @@ impl AsRawFd for BaseSocket {
         self.socket.as_raw_fd()
     }
 }
+
+impl AsFd for BaseSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
@@ -0,0 +1,458 @@
+//! `io_uring` backed [`Socket`], enabled by the `io-uring` feature.
+//!
+//! Sends and receives are submitted on two separate rings, so that the
+//! reader and the writers can each wait on their own completion eventfd,
+//! mirroring the read and write readiness of the epoll backend. Every
+//! operation works on a slot allocated once when the socket is created, so
+//! the steady state doesn't allocate.
+//!
+//! This is a batching backend: sends are only queued by
+//! [`Socket::poll_write_to`], and submitted together by [`Socket::poll_flush`],
+//! or once every send slot is taken, in a single system call. Receives are
+//! kept armed, and their completions reaped without a system call each.
+//!
+//! Buffers aren't registered with the rings. ICMP sockets fail zero copy
+//! sends with `EOPNOTSUPP`, and the fixed buffer `read` operation can't tell
+//! where datagrams came from, so every operation is a plain `sendmsg` or
+//! `recvmsg` copying from or into the buffer of its slot.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt, io,
+    mem::{self, MaybeUninit},
+    net::SocketAddr,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+    sync::Mutex,
+    task::{Context, Poll, ready},
+};
+
+use io_uring::{IoUring, opcode, squeue, types::Fd};
+use socket2::{SockAddr, SockAddrStorage};
+
+use super::BaseSocket;
+use crate::{IpVersion, rt::AsyncFd};
+
+/// Maximum size of a datagram going through a slot
+const SLOT_LEN: usize = 2048;
+/// Number of `recvmsg` operations kept armed at all times
+const RECV_SLOTS: usize = 64;
+/// Maximum number of send operations queued or in flight
+const SEND_SLOTS: usize = 256;
+
+/// `user_data` of the cancellation entries submitted on drop
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+pub(crate) struct Socket {
+    // `recv` and `send` must be dropped before `base`, as they
+    // wait for their operations on the socket to be cancelled
+    recv: Mutex<Ring<RecvState>>,
+    send: Mutex<Ring<SendState>>,
+    recv_event: AsyncFd<OwnedFd>,
+    send_event: AsyncFd<OwnedFd>,
+    base: BaseSocket,
+}
+
+struct Ring<S> {
+    ring: IoUring,
+    slots: Box<[Slot]>,
+    in_flight: usize,
+    /// Entries pushed to the submission queue but not submitted yet
+    unsubmitted: usize,
+    state: S,
+}
+
+struct RecvState {
+    completed: VecDeque<(usize, i32)>,
+}
+
+struct SendState {
+    free: Vec<usize>,
+    /// Target of every send slot
+    targets: Box<[SocketAddr]>,
+    error: Option<io::Error>,
+}
+
+/// A send which failed after [`Socket::poll_write_to`] returned
+#[derive(Debug)]
+struct SendError {
+    target: SocketAddr,
+    source: io::Error,
+}
+
+/// Memory backing a single `sendmsg`/`recvmsg` operation.
+///
+/// `msg` points to `iov` and `addr`, and `iov` points to `buf`, so a
+/// `Slot` must never move after [`Slot::init`] has been called.
+struct Slot {
+    buf: Box<[u8; SLOT_LEN]>,
+    addr: SockAddrStorage,
+    iov: libc::iovec,
+    msg: libc::msghdr,
+}
+
+// SAFETY: the raw pointers in `Slot` only point to memory owned by the same `Slot`
+unsafe impl<S: Send> Send for Ring<S> {}
+
+impl Socket {
+    /// Sends are only submitted by [`Socket::poll_flush`]
+    pub(crate) const QUEUES_SENDS: bool = true;
+
+    pub(crate) fn new_icmp<V: IpVersion>() -> io::Result<Self> {
+        Self::from_base(BaseSocket::new_icmp::<V>()?)
+    }
+
+    pub(crate) fn new_raw_icmpv4() -> io::Result<Self> {
+        Self::from_base(BaseSocket::new_raw_icmpv4()?)
+    }
+
+    fn from_base(base: BaseSocket) -> io::Result<Self> {
+        let (recv, recv_event) = Ring::new(
+            RECV_SLOTS,
+            RecvState {
+                completed: VecDeque::with_capacity(RECV_SLOTS),
+            },
+        )?;
+        let (send, send_event) = Ring::new(
+            SEND_SLOTS,
+            SendState {
+                free: (0..SEND_SLOTS).collect(),
+                targets: vec![SocketAddr::from(([0, 0, 0, 0], 0)); SEND_SLOTS].into_boxed_slice(),
+                error: None,
+            },
+        )?;
+
+        let mut recv = recv;
+        for slot in 0..RECV_SLOTS {
+            recv.push_recv(&base, slot)?;
+        }
+        recv.submit()?;
+
+        Ok(Self {
+            recv: Mutex::new(recv),
+            send: Mutex::new(send),
+            recv_event,
+            send_event,
+            base,
+        })
+    }
+
+    pub(crate) fn poll_read(
+        &self,
+        buf: &mut [MaybeUninit<u8>],
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        loop {
+            {
+                let mut recv = self.recv.lock().unwrap();
+                recv.reap_recv();
+
+                if let Some((slot, result)) = recv.state.completed.pop_front() {
+                    let received = if result < 0 {
+                        Err(io::Error::from_raw_os_error(-result))
+                    } else {
+                        let slot = &recv.slots[slot];
+                        // Like `recvfrom`, truncate datagrams that don't fit in `buf`
+                        let len = (result as usize).min(buf.len());
+
+                        // SAFETY: `len` is in bounds of both `slot.buf` and `buf`
+                        unsafe {
+                            ptr::copy_nonoverlapping(
+                                slot.buf.as_ptr(),
+                                buf.as_mut_ptr().cast::<u8>(),
+                                len,
+                            );
+                        }
+
+                        // SAFETY: the kernel wrote `msg_namelen` bytes of address into `addr`
+                        let source = unsafe { copy_addr(&slot.addr, slot.msg.msg_namelen) };
+                        Ok((len, source.as_socket().expect("SockAddr is an IP socket")))
+                    };
+
+                    recv.push_recv(&self.base, slot)?;
+                    if recv.state.completed.is_empty() {
+                        recv.submit()?;
+                    }
+                    return Poll::Ready(received);
+                }
+
+                recv.submit()?;
+            }
+
+            ready!(poll_event(&self.recv_event, cx))?;
+        }
+    }
+
+    pub(crate) fn poll_write_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        if buf.len() > SLOT_LEN {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet is larger than the io_uring send slots",
+            )));
+        }
+
+        loop {
+            {
+                let mut send = self.send.lock().unwrap();
+                send.reap_send();
+
+                if let Some(slot) = send.state.free.pop() {
+                    send.push_send(&self.base, slot, buf, addr)?;
+                    return Poll::Ready(Ok(buf.len()));
+                }
+
+                // Every slot is taken, the queued sends
+                // must go out before any of them can complete
+                send.submit()?;
+            }
+
+            ready!(poll_event(&self.send_event, cx))?;
+        }
+    }
+
+    /// Submit the queued sends.
+    ///
+    /// Sends are carried out asynchronously by the kernel, so one which
+    /// failed is reported by a later call, with the target it was meant for.
+    /// Only the first failure since the previous call is reported.
+    pub(crate) fn poll_flush(&self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut send = self.send.lock().unwrap();
+        send.submit()?;
+        send.reap_send();
+
+        Poll::Ready(send.state.error.take().map_or(Ok(()), Err))
+    }
+}
+
+impl<S> Ring<S> {
+    fn new(slots: usize, state: S) -> io::Result<(Self, AsyncFd<OwnedFd>)> {
+        let ring = IoUring::new(slots.next_power_of_two() as u32)?;
+
+        // SAFETY: `eventfd` either fails or returns a new file descriptor we own
+        let event = unsafe {
+            let fd = libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC);
+            if fd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            OwnedFd::from_raw_fd(fd)
+        };
+        ring.submitter().register_eventfd(event.as_raw_fd())?;
+
+        let mut slots = (0..slots)
+            .map(|_| Slot::new())
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        for slot in &mut slots {
+            slot.init();
+        }
+
+        let ring = Self {
+            ring,
+            slots,
+            in_flight: 0,
+            unsubmitted: 0,
+            state,
+        };
+        Ok((ring, AsyncFd::new(event)?))
+    }
+
+    fn push(&mut self, entry: squeue::Entry) -> io::Result<()> {
+        // SAFETY: every entry points to the memory of a `Slot`, which lives
+        // until the operation completes or is cancelled in `Drop`
+        unsafe { self.ring.submission().push(&entry) }
+            .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+        self.in_flight += 1;
+        self.unsubmitted += 1;
+        Ok(())
+    }
+
+    fn submit(&mut self) -> io::Result<()> {
+        if self.unsubmitted > 0 {
+            self.ring.submit()?;
+            self.unsubmitted = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Ring<RecvState> {
+    fn push_recv(&mut self, base: &BaseSocket, slot: usize) -> io::Result<()> {
+        let msg = self.slots[slot].reset_recv();
+        let entry = opcode::RecvMsg::new(Fd(base.as_raw_fd()), msg)
+            .build()
+            .user_data(slot as u64);
+        self.push(entry)
+    }
+
+    fn reap_recv(&mut self) {
+        for cqe in self.ring.completion() {
+            self.in_flight -= 1;
+            self.state
+                .completed
+                .push_back((cqe.user_data() as usize, cqe.result()));
+        }
+    }
+}
+
+impl Ring<SendState> {
+    fn push_send(
+        &mut self,
+        base: &BaseSocket,
+        slot: usize,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> io::Result<()> {
+        self.slots[slot].fill_send(buf, addr);
+        self.state.targets[slot] = addr;
+        let entry = opcode::SendMsg::new(Fd(base.as_raw_fd()), &raw const self.slots[slot].msg)
+            .build()
+            .user_data(slot as u64);
+        self.push(entry)
+    }
+
+    fn reap_send(&mut self) {
+        for cqe in self.ring.completion() {
+            self.in_flight -= 1;
+            let slot = cqe.user_data() as usize;
+            if cqe.result() < 0 && self.state.error.is_none() {
+                let source = io::Error::from_raw_os_error(-cqe.result());
+                let kind = source.kind();
+                let target = self.state.targets[slot];
+                self.state.error = Some(io::Error::new(kind, SendError { target, source }));
+            }
+            self.state.free.push(slot);
+        }
+    }
+}
+
+impl<S> Drop for Ring<S> {
+    fn drop(&mut self) {
+        if self.in_flight == 0 {
+            return;
+        }
+
+        // The kernel may still be writing into the slots,
+        // wait for every operation to complete before freeing them
+        for slot in 0..self.slots.len() {
+            let entry = opcode::AsyncCancel::new(slot as u64)
+                .build()
+                .user_data(CANCEL_USER_DATA);
+            // SAFETY: `AsyncCancel` doesn't reference any memory
+            if unsafe { self.ring.submission().push(&entry) }.is_err() {
+                let _ = self.ring.submit();
+                // SAFETY: see above
+                let _ = unsafe { self.ring.submission().push(&entry) };
+            }
+        }
+
+        while self.in_flight > 0 {
+            if self.ring.submit_and_wait(1).is_err() {
+                // Leak the slots rather than risk a use after free
+                mem::forget(mem::take(&mut self.slots));
+                return;
+            }
+
+            for cqe in self.ring.completion() {
+                if cqe.user_data() != CANCEL_USER_DATA {
+                    self.in_flight -= 1;
+                }
+            }
+        }
+    }
+}
+
+impl Slot {
+    fn new() -> Self {
+        Self {
+            buf: Box::new([0; SLOT_LEN]),
+            addr: SockAddrStorage::zeroed(),
+            iov: libc::iovec {
+                iov_base: ptr::null_mut(),
+                iov_len: 0,
+            },
+            // SAFETY: all zeros is a valid `msghdr`
+            msg: unsafe { mem::zeroed() },
+        }
+    }
+
+    fn init(&mut self) {
+        self.iov.iov_base = self.buf.as_mut_ptr().cast();
+        self.iov.iov_len = SLOT_LEN;
+        self.msg.msg_name = (&raw mut self.addr).cast();
+        self.msg.msg_namelen = self.addr.size_of();
+        self.msg.msg_iov = &raw mut self.iov;
+        self.msg.msg_iovlen = 1;
+    }
+
+    fn reset_recv(&mut self) -> *mut libc::msghdr {
+        self.iov.iov_len = SLOT_LEN;
+        self.msg.msg_namelen = self.addr.size_of();
+        self.msg.msg_flags = 0;
+        &raw mut self.msg
+    }
+
+    fn fill_send(&mut self, buf: &[u8], addr: SocketAddr) {
+        let addr = SockAddr::from(addr);
+        self.msg.msg_namelen = addr.len();
+        self.addr = addr.as_storage();
+
+        self.buf[..buf.len()].copy_from_slice(buf);
+        self.iov.iov_len = buf.len();
+    }
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to send to {}: {}", self.target.ip(), self.source)
+    }
+}
+
+impl Error for SendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Copy the first `len` bytes of `addr` into a new [`SockAddr`].
+///
+/// # Safety
+///
+/// The first `len` bytes of `addr` must be a valid socket address.
+unsafe fn copy_addr(addr: &SockAddrStorage, len: libc::socklen_t) -> SockAddr {
+    let mut storage = SockAddrStorage::zeroed();
+    // SAFETY: both pointers are valid for `size_of::<SockAddrStorage>()` bytes
+    unsafe {
+        ptr::copy_nonoverlapping(
+            ptr::from_ref(addr).cast::<u8>(),
+            (&raw mut storage).cast::<u8>(),
+            len as usize,
+        );
+        SockAddr::new(storage, len)
+    }
+}
+
+/// Wait for `event` to be signaled by the kernel and reset its counter.
+fn poll_event(event: &AsyncFd<OwnedFd>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    event.poll_read_with(cx, |inner| {
+        let mut counter = 0u64;
+        // SAFETY: `counter` is valid for 8 bytes
+        let read = unsafe {
+            libc::read(
+                inner.as_raw_fd(),
+                (&raw mut counter).cast(),
+                mem::size_of::<u64>(),
+            )
+        };
+        if read == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    })
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
+};
+
+use crate::{IpVersion, packet::EchoRequestTemplate, raw_pinger::SyncRawPinger};
+
+/// A blocking pinger for IPv4 addresses
+pub type V4SyncPinger = SyncPinger<Ipv4Addr>;
+/// A blocking pinger for IPv6 addresses
+pub type V6SyncPinger = SyncPinger<Ipv6Addr>;
+
+/// A blocking pinger for [`IpVersion`] (either [`Ipv4Addr`] or [`Ipv6Addr`]).
+///
+/// Unlike [`Pinger`] it doesn't have a receive task, so no async runtime
+/// is needed. Replies are only received while iterating over
+/// [`SyncMeasureMany`], on the calling thread.
+///
+/// [`Pinger`]: crate::Pinger
+pub struct SyncPinger<V: IpVersion> {
+    raw: SyncRawPinger<V>,
+    identifier: u16,
+    sequence_number: u16,
+}
+
+impl<V: IpVersion> SyncPinger<V> {
+    /// Construct a new `SyncPinger`.
+    pub fn new() -> io::Result<Self> {
+        let raw = SyncRawPinger::new()?;
+
+        Ok(Self {
+            raw,
+            identifier: rand::random::<u16>(),
+            sequence_number: 0,
+        })
+    }
+
+    /// Ping `addresses`, waiting for replies until `deadline`
+    ///
+    /// Creates [`SyncMeasureMany`] which **lazily** sends ping requests
+    /// the first time it's advanced, and then blocks while waiting for
+    /// each response.
+    pub fn measure_many<I>(&mut self, addresses: I, deadline: Instant) -> SyncMeasureMany<'_, V, I>
+    where
+        I: Iterator<Item = V>,
+    {
+        let sequence_number = self.sequence_number;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+
+        let template = EchoRequestTemplate::new(
+            self.identifier,
+            sequence_number,
+            &rand::random::<[u8; 64]>(),
+        );
+
+        let (size_hint, _) = addresses.size_hint();
+        SyncMeasureMany {
+            pinger: self,
+            template,
+            send_queue: addresses,
+            in_flight: HashMap::with_capacity(size_hint),
+            deadline,
+        }
+    }
+}
+
+/// An [`Iterator`] of ping responses.
+///
+/// Iteration ends once every address has replied or `deadline` has passed,
+/// whichever comes first. Addresses the ICMP echo request couldn't be sent
+/// to are treated the same as the ones that didn't reply.
+pub struct SyncMeasureMany<'a, V: IpVersion, I: Iterator<Item = V>> {
+    pinger: &'a mut SyncPinger<V>,
+    template: EchoRequestTemplate<V>,
+    send_queue: I,
+    in_flight: HashMap<V, Instant>,
+    deadline: Instant,
+}
+
+impl<V: IpVersion, I: Iterator<Item = V>> SyncMeasureMany<'_, V, I> {
+    fn send_all(&mut self) {
+        for addr in self.send_queue.by_ref() {
+            self.template.patch_payload(0, &rand::random::<[u8; 8]>());
+
+            let sent_at = Instant::now();
+            if self
+                .pinger
+                .raw
+                .send_template_to(addr, &self.template)
+                .is_ok()
+            {
+                self.in_flight.insert(addr, sent_at);
+            }
+        }
+    }
+}
+
+impl<V: IpVersion, I: Iterator<Item = V>> Iterator for SyncMeasureMany<'_, V, I> {
+    type Item = io::Result<(V, Duration)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.send_all();
+
+        while !self.in_flight.is_empty() {
+            let timeout = self.deadline.saturating_duration_since(Instant::now());
+            let packet = match self.pinger.raw.recv_timeout(timeout) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    self.in_flight.clear();
+                    break;
+                }
+                Err(err) => return Some(Err(err)),
+            };
+            let recv_instant = Instant::now();
+
+            if packet.sequence_number() != self.template.sequence_number() {
+                continue;
+            }
+            if let Some(send_instant) = self.in_flight.remove(&packet.source()) {
+                return Some(Ok((packet.source(), recv_instant - send_instant)));
+            }
+        }
+
+        None
+    }
+}
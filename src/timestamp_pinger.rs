@@ -0,0 +1,288 @@
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+use std::{
+    collections::HashMap,
+    io, mem,
+    net::Ipv4Addr,
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+
+use crate::{
+    packet::TimestampRequestPacket, pinger::FLUSH_BATCH, raw_pinger::RawTimestampPinger,
+    rt::Instant,
+};
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// A pinger sending ICMP timestamp requests to IPv4 addresses.
+///
+/// Besides the round trip time, timestamp replies carry the time the request
+/// was received and the reply was sent by the replying host, which allows
+/// estimating the one-way delays and the offset between the two clocks.
+pub struct TimestampPinger {
+    raw: RawTimestampPinger,
+    identifier: u16,
+    sequence_number: u16,
+}
+
+/// The result of an ICMP timestamp request.
+///
+/// Timestamps are expressed in milliseconds since midnight UT. See
+/// [`TimestampRequestPacket`] for the meaning of the most significant bit.
+/// The round trip time is [serialized as nanoseconds](crate#serialization).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimestampMeasurement {
+    #[cfg_attr(feature = "serde", serde(with = "crate::nanos"))]
+    rtt: Duration,
+    originate: u32,
+    receive: u32,
+    transmit: u32,
+    arrival: u32,
+}
+
+impl TimestampPinger {
+    /// Construct a new `TimestampPinger`.
+    ///
+    /// Requires `CAP_NET_RAW`, see [`RawTimestampPinger`].
+    pub fn new() -> io::Result<Self> {
+        let raw = RawTimestampPinger::new()?;
+
+        Ok(Self {
+            raw,
+            identifier: rand::random::<u16>(),
+            sequence_number: 0,
+        })
+    }
+
+    /// Send ICMP timestamp requests to `addresses`
+    ///
+    /// Creates [`TimestampMeasureManyStream`] which **lazily** sends
+    /// timestamp requests and [`Stream`]s the replies as they arrive.
+    /// As there is no receive task, replies are only received while
+    /// the stream is being polled.
+    ///
+    /// [`Stream`]: futures_core::Stream
+    pub fn measure_many<I>(&mut self, addresses: I) -> TimestampMeasureManyStream<'_, I>
+    where
+        I: Iterator<Item = Ipv4Addr>,
+    {
+        let sequence_number = self.sequence_number;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+
+        let (size_hint, _) = addresses.size_hint();
+        TimestampMeasureManyStream {
+            pinger: self,
+            send_queue: addresses,
+            blocked_addr: None,
+            unflushed: Vec::new(),
+            in_flight: HashMap::with_capacity(size_hint),
+            sequence_number,
+            finished: false,
+        }
+    }
+}
+
+/// A [`Stream`] of ICMP timestamp replies.
+///
+/// No kind of `rtt` timeout is implemented, so an external mechanism
+/// like [`tokio::time::timeout`] should be used to prevent the program
+/// from hanging indefinitely.
+///
+/// [`Stream`]: futures_core::Stream
+/// [`tokio::time::timeout`]: https://docs.rs/tokio/latest/tokio/time/fn.timeout.html
+pub struct TimestampMeasureManyStream<'a, I: Iterator<Item = Ipv4Addr>> {
+    pinger: &'a TimestampPinger,
+    send_queue: I,
+    blocked_addr: Option<Ipv4Addr>,
+    /// Requests queued by the socket, which aren't sent out until flushed
+    unflushed: Vec<Ipv4Addr>,
+    in_flight: HashMap<Ipv4Addr, Instant>,
+    sequence_number: u16,
+    finished: bool,
+}
+
+impl<I: Iterator<Item = Ipv4Addr>> TimestampMeasureManyStream<'_, I> {
+    /// Poll for the next timestamp reply
+    ///
+    /// An error is returned if receiving from the socket fails.
+    /// The stream then ends by returning `None`.
+    pub fn poll_next_unpin(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<(Ipv4Addr, TimestampMeasurement)>>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+
+        self.poll_send(cx);
+
+        loop {
+            let packet = match self.pinger.raw.poll_recv(cx) {
+                Poll::Ready(Ok(packet)) => packet,
+                Poll::Ready(Err(err)) => {
+                    self.finished = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            let recv_instant = Instant::now();
+            let arrival = milliseconds_since_midnight();
+
+            if packet.identifier() != self.pinger.identifier
+                || packet.sequence_number() != self.sequence_number
+            {
+                continue;
+            }
+
+            if let Some(send_instant) = self.in_flight.remove(&packet.source()) {
+                let measurement = TimestampMeasurement {
+                    rtt: recv_instant - send_instant,
+                    originate: packet.originate(),
+                    receive: packet.receive(),
+                    transmit: packet.transmit(),
+                    arrival,
+                };
+                return Poll::Ready(Some(Ok((packet.source(), measurement))));
+            }
+        }
+    }
+
+    fn poll_send(&mut self, cx: &mut Context<'_>) {
+        let queues_sends = self.pinger.raw.queues_sends();
+        let mut sent = false;
+        while let Some(addr) = self.blocked_addr.take().or_else(|| self.send_queue.next()) {
+            let sent_at = Instant::now();
+            let packet = TimestampRequestPacket::new(
+                self.pinger.identifier,
+                self.sequence_number,
+                milliseconds_since_midnight(),
+            );
+
+            match self.pinger.raw.poll_send_to(cx, addr, &packet) {
+                Poll::Ready(_) if queues_sends => {
+                    sent = true;
+                    self.unflushed.push(addr);
+                    if self.unflushed.len() >= FLUSH_BATCH {
+                        self.flush(cx);
+                    }
+                }
+                Poll::Ready(_) => {
+                    sent = true;
+                    self.in_flight.insert(addr, sent_at);
+                }
+                Poll::Pending => {
+                    self.blocked_addr = Some(addr);
+                    break;
+                }
+            }
+        }
+
+        if sent {
+            self.flush(cx);
+        }
+    }
+
+    /// Flush the requests sent, taking the time the ones
+    /// queued by the socket are actually sent out
+    fn flush(&mut self, cx: &mut Context<'_>) {
+        let flushed_at = Instant::now();
+        let _ = self.pinger.raw.poll_flush(cx);
+
+        let mut unflushed = mem::take(&mut self.unflushed);
+        self.in_flight
+            .extend(unflushed.drain(..).map(|addr| (addr, flushed_at)));
+        self.unflushed = unflushed;
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<I: Iterator<Item = Ipv4Addr> + Unpin> Stream for TimestampMeasureManyStream<'_, I> {
+    type Item = io::Result<(Ipv4Addr, TimestampMeasurement)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.as_mut().poll_next_unpin(cx)
+    }
+}
+
+impl TimestampMeasurement {
+    /// Get the round trip time, as measured by the local monotonic clock
+    pub fn rtt(&self) -> Duration {
+        self.rtt
+    }
+
+    /// Get the time the request was sent at
+    pub fn originate(&self) -> u32 {
+        self.originate
+    }
+
+    /// Get the time the request was received at by the replying host
+    pub fn receive(&self) -> u32 {
+        self.receive
+    }
+
+    /// Get the time the reply was sent at by the replying host
+    pub fn transmit(&self) -> u32 {
+        self.transmit
+    }
+
+    /// Get the time the reply was received at
+    pub fn arrival(&self) -> u32 {
+        self.arrival
+    }
+
+    /// Estimate the delay of the request in milliseconds
+    ///
+    /// The estimate includes the offset between the two clocks. Returns
+    /// `None` if the replying host doesn't use standard timestamps.
+    pub fn forward_delay(&self) -> Option<i64> {
+        self.is_standard()
+            .then(|| timestamp_diff(self.receive, self.originate))
+    }
+
+    /// Estimate the delay of the reply in milliseconds
+    ///
+    /// The estimate includes the offset between the two clocks. Returns
+    /// `None` if the replying host doesn't use standard timestamps.
+    pub fn reverse_delay(&self) -> Option<i64> {
+        self.is_standard()
+            .then(|| timestamp_diff(self.arrival, self.transmit))
+    }
+
+    /// Estimate how many milliseconds the clock of the replying
+    /// host is ahead of the local one
+    ///
+    /// Assumes the request and the reply took the same time to be delivered.
+    /// Returns `None` if the replying host doesn't use standard timestamps.
+    pub fn clock_offset(&self) -> Option<i64> {
+        let forward = self.forward_delay()?;
+        let reverse = self.reverse_delay()?;
+        Some((forward - reverse) / 2)
+    }
+
+    fn is_standard(&self) -> bool {
+        (self.receive | self.transmit) & (1 << 31) == 0
+    }
+}
+
+/// Get the current time in milliseconds since midnight UT
+fn milliseconds_since_midnight() -> u32 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_millis() % MILLIS_PER_DAY as u128) as u32
+}
+
+/// Compute `a - b`, taking into account that timestamps wrap around at midnight
+fn timestamp_diff(a: u32, b: u32) -> i64 {
+    let diff = (i64::from(a) - i64::from(b)).rem_euclid(MILLIS_PER_DAY);
+    if diff > MILLIS_PER_DAY / 2 {
+        diff - MILLIS_PER_DAY
+    } else {
+        diff
+    }
+}
@@ -0,0 +1,94 @@
+//! Abstraction over the ICMP socket used by the pingers
+
+use std::{
+    io,
+    mem::MaybeUninit,
+    net::SocketAddr,
+    task::{Context, Poll},
+};
+
+use crate::socket::Socket;
+
+/// A datagram transport for ICMP packets.
+///
+/// Implementations behave like a Linux `SOCK_DGRAM` socket with
+/// `IPPROTO_ICMP`/`IPPROTO_ICMPV6`: ICMP messages are sent and received
+/// without the IP header, and the identifier of outgoing echo requests
+/// may be rewritten.
+///
+/// [`RawPinger`] and [`Pinger`] use the operating system socket by default,
+/// see [`RawPinger::with_transport`] and [`Pinger::with_transport`] for
+/// using a different one.
+///
+/// [`RawPinger`]: crate::raw_pinger::RawPinger
+/// [`RawPinger::with_transport`]: crate::raw_pinger::RawPinger::with_transport
+/// [`Pinger`]: crate::Pinger
+/// [`Pinger::with_transport`]: crate::Pinger::with_transport
+pub trait Transport: Send + Sync + 'static {
+    /// Receive an ICMP message into `buf`, returning its length and source
+    ///
+    /// Messages longer than `buf` are truncated.
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [MaybeUninit<u8>],
+    ) -> Poll<io::Result<(usize, SocketAddr)>>;
+
+    /// Send the ICMP message `buf` to `addr`
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<io::Result<usize>>;
+
+    /// Flush the ICMP messages queued by [`poll_send_to`](Self::poll_send_to)
+    ///
+    /// Transports which only queue messages in `poll_send_to` send them out
+    /// here. They may also report here the messages which failed to be sent
+    /// after `poll_send_to` returned.
+    ///
+    /// The default implementation does nothing.
+    fn poll_flush(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let _ = cx;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Whether [`poll_send_to`](Self::poll_send_to) only queues ICMP
+    /// messages, which are sent out by [`poll_flush`](Self::poll_flush)
+    ///
+    /// Pingers then take the time ICMP echo requests were sent right before
+    /// flushing them, rather than before queueing them.
+    ///
+    /// The default implementation returns `false`.
+    fn queues_sends(&self) -> bool {
+        false
+    }
+}
+
+impl Transport for Socket {
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [MaybeUninit<u8>],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        self.poll_read(buf, cx)
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_to(cx, buf, addr)
+    }
+
+    fn poll_flush(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Socket::poll_flush(self, cx)
+    }
+
+    fn queues_sends(&self) -> bool {
+        Socket::QUEUES_SENDS
+    }
+}